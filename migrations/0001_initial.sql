@@ -0,0 +1,53 @@
+-- The tables which existed before migrations were added. Deployments from
+-- that time already have them, so they're only created if they're missing.
+
+CREATE TABLE IF NOT EXISTS users (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	username VARCHAR(255) UNIQUE NOT NULL,
+	password_hash TINYBLOB NOT NULL,
+	password_salt TINYBLOB NOT NULL,
+	password_version INT UNSIGNED NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS clients (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	alias VARCHAR(255) UNIQUE NOT NULL,
+	type VARCHAR(45) NOT NULL,
+	secret_hash TINYBLOB NULL,
+	secret_salt TINYBLOB NULL,
+	secret_version INT UNSIGNED NULL,
+	allowed_scopes MEDIUMTEXT NOT NULL,
+	default_scopes MEDIUMTEXT NULL,
+	trusted BOOLEAN NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS client_redirect_uris (
+	client_id BINARY(16) NOT NULL,
+	redirect_uri VARCHAR(255) NOT NULL,
+	PRIMARY KEY (client_id, redirect_uri),
+	FOREIGN KEY (client_id) REFERENCES clients(id) ON DELETE CASCADE
+);
+
+CREATE TABLE IF NOT EXISTS auth_codes (
+	jti BINARY(16) PRIMARY KEY NOT NULL,
+	exp TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS access_tokens (
+	jti BINARY(16) PRIMARY KEY NOT NULL,
+	auth_code BINARY(16) NULL,
+	exp TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS refresh_tokens (
+	jti BINARY(16) PRIMARY KEY NOT NULL,
+	auth_code BINARY(16) NULL,
+	exp TIMESTAMP NOT NULL,
+	revoked_reason ENUM('reused-authorization-code', 'new-refresh-token') NULL
+);
+
+CREATE TABLE IF NOT EXISTS login_attempts (
+	username VARCHAR(255) NOT NULL,
+	ip_address VARCHAR(45) NOT NULL,
+	time TIMESTAMP NOT NULL
+);
@@ -0,0 +1,2 @@
+ALTER TABLE clients ADD COLUMN require_pkce BOOLEAN NOT NULL DEFAULT FALSE;
+UPDATE clients SET require_pkce = TRUE WHERE type = 'public';
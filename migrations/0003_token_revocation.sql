@@ -0,0 +1,9 @@
+ALTER TABLE refresh_tokens MODIFY COLUMN revoked_reason
+	ENUM('reused-authorization-code', 'new-refresh-token', 'revoked') NULL;
+
+-- tokens were only grouped by their authorization code, so tokens from the
+-- other grants couldn't be revoked together
+ALTER TABLE access_tokens RENAME COLUMN auth_code TO grant_id;
+ALTER TABLE refresh_tokens RENAME COLUMN auth_code TO grant_id;
+CREATE INDEX access_tokens_grant_id ON access_tokens (grant_id);
+CREATE INDEX refresh_tokens_grant_id ON refresh_tokens (grant_id);
@@ -0,0 +1,6 @@
+CREATE TABLE signing_keys (
+	kid VARCHAR(255) PRIMARY KEY NOT NULL,
+	private_key BLOB NOT NULL,
+	state ENUM('next', 'active', 'retired') NOT NULL,
+	expires TIMESTAMP NULL
+);
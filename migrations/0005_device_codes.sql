@@ -0,0 +1,11 @@
+CREATE TABLE device_codes (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	user_code VARCHAR(45) UNIQUE NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	scope TEXT NOT NULL,
+	exp TIMESTAMP NOT NULL,
+	poll_interval INT UNSIGNED NOT NULL,
+	last_polled TIMESTAMP NULL,
+	status ENUM('pending', 'approved', 'denied') NOT NULL,
+	user_id BINARY(16) NULL
+);
@@ -0,0 +1,5 @@
+CREATE TABLE client_token_exchange_sources (
+	client_id BINARY(16) NOT NULL,
+	source_client_id BINARY(16) NOT NULL,
+	PRIMARY KEY (client_id, source_client_id)
+);
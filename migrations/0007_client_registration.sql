@@ -0,0 +1,16 @@
+CREATE TABLE initial_access_tokens (
+	token_hash BINARY(32) PRIMARY KEY NOT NULL,
+	exp TIMESTAMP NOT NULL
+);
+
+CREATE TABLE client_registrations (
+	client_id BINARY(16) PRIMARY KEY NOT NULL,
+	registration_token_hash BINARY(32) NOT NULL,
+	metadata TEXT NOT NULL,
+	issued_at TIMESTAMP NOT NULL
+);
+
+ALTER TABLE access_tokens ADD COLUMN client_id BINARY(16) NOT NULL;
+ALTER TABLE refresh_tokens ADD COLUMN client_id BINARY(16) NOT NULL;
+CREATE INDEX access_tokens_client_id ON access_tokens (client_id);
+CREATE INDEX refresh_tokens_client_id ON refresh_tokens (client_id);
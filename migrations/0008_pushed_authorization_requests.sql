@@ -0,0 +1,9 @@
+ALTER TABLE clients ADD COLUMN require_par BOOLEAN NOT NULL DEFAULT FALSE;
+
+CREATE TABLE pushed_authorization_requests (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	parameters TEXT NOT NULL,
+	exp TIMESTAMP NOT NULL,
+	FOREIGN KEY (client_id) REFERENCES clients(id) ON DELETE CASCADE
+);
@@ -0,0 +1 @@
+ALTER TABLE clients ADD COLUMN jwks TEXT NULL;
@@ -0,0 +1,4 @@
+CREATE TABLE dpop_proofs (
+	jti_hash BINARY(32) PRIMARY KEY NOT NULL,
+	exp TIMESTAMP NOT NULL
+);
@@ -0,0 +1,3 @@
+ALTER TABLE clients ADD COLUMN auth_method VARCHAR(45) NOT NULL DEFAULT 'client_secret_basic';
+ALTER TABLE clients ADD COLUMN tls_client_auth_san_dns VARCHAR(255) NULL;
+UPDATE clients SET auth_method = 'none' WHERE type = 'public';
@@ -0,0 +1,6 @@
+ALTER TABLE clients ADD COLUMN secret_ciphertext BLOB NULL;
+
+CREATE TABLE client_assertions (
+	jti_hash BINARY(32) PRIMARY KEY NOT NULL,
+	exp TIMESTAMP NOT NULL
+);
@@ -0,0 +1,14 @@
+ALTER TABLE clients ADD COLUMN backchannel_client_notification_endpoint VARCHAR(255) NULL;
+
+CREATE TABLE backchannel_authentications (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	user_id BINARY(16) NOT NULL,
+	scope TEXT NOT NULL,
+	binding_message VARCHAR(255) NULL,
+	client_notification_token VARCHAR(255) NULL,
+	exp TIMESTAMP NOT NULL,
+	poll_interval INT UNSIGNED NOT NULL,
+	last_polled TIMESTAMP NULL,
+	status VARCHAR(45) NOT NULL
+);
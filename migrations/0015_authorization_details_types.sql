@@ -0,0 +1,5 @@
+CREATE TABLE client_authorization_details_types (
+	client_id BINARY(16) NOT NULL,
+	authorization_details_type VARCHAR(255) NOT NULL,
+	PRIMARY KEY (client_id, authorization_details_type)
+);
@@ -0,0 +1,5 @@
+CREATE TABLE resource_servers (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	uri VARCHAR(255) UNIQUE NOT NULL,
+	scopes TEXT NOT NULL
+);
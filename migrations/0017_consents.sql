@@ -0,0 +1,25 @@
+ALTER TABLE refresh_tokens MODIFY COLUMN revoked_reason
+	ENUM('reused-authorization-code', 'new-refresh-token', 'revoked', 'consent-revoked') NULL;
+
+CREATE TABLE consents (
+	user_id BINARY(16) NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	scopes TEXT NOT NULL,
+	granted_at TIMESTAMP NOT NULL,
+	PRIMARY KEY (user_id, client_id)
+);
+
+CREATE TABLE consent_tokens (
+	user_id BINARY(16) NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	grant_id BINARY(16) NOT NULL,
+	PRIMARY KEY (user_id, client_id, grant_id)
+);
+
+CREATE TABLE consent_requests (
+	id BINARY(16) PRIMARY KEY NOT NULL,
+	client_id BINARY(16) NOT NULL,
+	user_id BINARY(16) NOT NULL,
+	parameters TEXT NOT NULL,
+	exp TIMESTAMP NOT NULL
+);
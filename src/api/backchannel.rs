@@ -0,0 +1,207 @@
+use std::str::FromStr;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use tera::Tera;
+use unic_langid::subtags::Language;
+use uuid::Uuid;
+
+use crate::resources::{languages, templates};
+use crate::services::backchannel_authentication::BackchannelAuthenticationStatus;
+use crate::services::backchannel_notifier::BackchannelNotifier;
+use crate::services::{brute_force_detection, db};
+
+#[derive(Debug, Clone, Deserialize)]
+struct BackchannelPageParameters {
+	auth_req_id: Option<Uuid>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum BackchannelDecision {
+	Approve,
+	Deny,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BackchannelForm {
+	auth_req_id: Uuid,
+	username: Box<str>,
+	password: Box<str>,
+	decision: BackchannelDecision,
+}
+
+/// Show the page for a pending request. If the request doesn't exist, then
+/// only an error message is shown.
+async fn render_backchannel_page(
+	db: &MySqlPool,
+	tera: &Tera,
+	auth_req_id: Option<Uuid>,
+	error_message: Option<&str>,
+	language: Language,
+	translations: languages::Translations,
+) -> String {
+	let request = match auth_req_id {
+		Some(auth_req_id) => db::get_pending_backchannel_authentication(db, auth_req_id)
+			.await
+			.unwrap(),
+		None => None,
+	};
+	let Some(request) = request else {
+		return templates::backchannel_page(
+			tera,
+			None,
+			None,
+			None,
+			Some("backchannelErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+	};
+
+	let client_alias = db::get_client_alias(db, request.client_id)
+		.await
+		.unwrap()
+		.unwrap();
+	templates::backchannel_page(
+		tera,
+		Some(&request.id.to_string()),
+		Some(&client_alias),
+		request.binding_message.as_deref(),
+		error_message,
+		language,
+		translations,
+	)
+	.unwrap()
+}
+
+/// The page where a user approves a login which was started by a client
+/// through the backchannel authentication endpoint. The user is sent a link to
+/// this page on their own device.
+#[get("")]
+async fn backchannel_page(
+	db: web::Data<MySqlPool>,
+	params: web::Query<BackchannelPageParameters>,
+	tera: web::Data<Tera>,
+	translations: web::Data<languages::Translations>,
+) -> HttpResponse {
+	// TODO find a better way of doing languages
+	let language = Language::from_str("en").unwrap();
+	let translations = translations.get_ref().clone();
+	let db = db.get_ref();
+
+	let page =
+		render_backchannel_page(db, &tera, params.auth_req_id, None, language, translations).await;
+	HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[post("")]
+async fn approve_backchannel(
+	db: web::Data<MySqlPool>,
+	http_req: HttpRequest,
+	form: web::Form<BackchannelForm>,
+	tera: web::Data<Tera>,
+	translations: web::Data<languages::Translations>,
+	notifier: web::Data<dyn BackchannelNotifier>,
+) -> HttpResponse {
+	// TODO find a better way of doing languages
+	let language = Language::from_str("en").unwrap();
+	let translations = translations.get_ref().clone();
+	let db = db.get_ref();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	// check for brute force attack
+	if brute_force_detection::brute_force_detected(db, &form.username, ip)
+		.await
+		.unwrap()
+	{
+		let page = templates::error_page(
+			&tera,
+			language,
+			translations,
+			templates::ErrorPage::TooManyRequests,
+		)
+		.unwrap();
+		return HttpResponse::TooManyRequests()
+			.content_type("text/html")
+			.body(page);
+	}
+
+	// authenticate user
+	let user = db::get_user_by_username(db, &form.username).await.unwrap();
+	let authenticated = match &user {
+		Some(user) => user.check_password(&form.password).unwrap(),
+		None => false,
+	};
+	let (Some(user), true) = (user, authenticated) else {
+		db::add_failed_login_attempt(db, &form.username, ip)
+			.await
+			.unwrap();
+		let page = render_backchannel_page(
+			db,
+			&tera,
+			Some(form.auth_req_id),
+			Some("loginErrorMessage"),
+			language,
+			translations,
+		)
+		.await;
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	};
+
+	// the request can only be approved by the user it was made for
+	let approved = form.decision == BackchannelDecision::Approve;
+	let status = if approved {
+		BackchannelAuthenticationStatus::Approved
+	} else {
+		BackchannelAuthenticationStatus::Denied
+	};
+	if !db::update_backchannel_authentication_status(db, form.auth_req_id, user.id, status)
+		.await
+		.unwrap()
+	{
+		db::add_failed_login_attempt(db, &form.username, ip)
+			.await
+			.unwrap();
+		let page = templates::backchannel_page(
+			&tera,
+			None,
+			None,
+			None,
+			Some("backchannelErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	}
+
+	// clients using the ping mode are told that the tokens are ready
+	let request = db::get_backchannel_authentication(db, form.auth_req_id)
+		.await
+		.unwrap();
+	if let Some(request) = request {
+		let endpoint =
+			db::get_client_backchannel_client_notification_endpoint(db, request.client_id)
+				.await
+				.unwrap()
+				.flatten();
+		if let (Some(endpoint), Some(token)) = (endpoint, request.client_notification_token) {
+			if let Err(e) = notifier.ping_client(&endpoint, &token, request.id).await {
+				log::error!("{e}");
+			}
+		}
+	}
+
+	let page =
+		templates::backchannel_complete_page(&tera, approved, language, translations).unwrap();
+	HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+pub fn service() -> Scope {
+	web::scope("/backchannel")
+		.service(backchannel_page)
+		.service(approve_backchannel)
+}
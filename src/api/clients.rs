@@ -7,9 +7,10 @@ use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 
-use crate::models::client::{Client, ClientType, CreateClientError};
-use crate::services::crypto::PasswordHash;
+use crate::models::client::{AuthMethod, Client, ClientOptions, ClientType, CreateClientError};
+use crate::services::crypto::{self, PasswordHash};
 use crate::services::db::ClientRow;
+use crate::services::keys::JwkSet;
 use crate::services::{db, id};
 
 #[derive(Debug, Clone, Serialize)]
@@ -21,6 +22,11 @@ struct ClientResponse {
 	allowed_scopes: Box<[Box<str>]>,
 	default_scopes: Option<Box<[Box<str>]>>,
 	is_trusted: bool,
+	requires_pkce: bool,
+	requires_par: bool,
+	auth_method: AuthMethod,
+	tls_client_auth_san_dns: Option<Box<str>>,
+	backchannel_client_notification_endpoint: Option<Box<str>>,
 }
 
 impl From<ClientRow> for ClientResponse {
@@ -38,6 +44,13 @@ impl From<ClientRow> for ClientResponse {
 				.default_scopes
 				.map(|s| s.split_whitespace().map(Box::from).collect()),
 			is_trusted: value.is_trusted,
+			requires_pkce: value.requires_pkce,
+			requires_par: value.requires_par,
+			auth_method: value.auth_method,
+			tls_client_auth_san_dns: value.tls_client_auth_san_dns.map(String::into_boxed_str),
+			backchannel_client_notification_endpoint: value
+				.backchannel_client_notification_endpoint
+				.map(String::into_boxed_str),
 		}
 	}
 }
@@ -127,6 +140,57 @@ async fn get_client_redirect_uris(
 	Ok(HttpResponse::Ok().json(redirect_uris))
 }
 
+#[get("/{client_id}/jwks")]
+async fn get_client_jwks(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(jwks) = db::get_client_jwks(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(jwks))
+}
+
+#[get("/{client_id}/token-exchange-sources")]
+async fn get_client_token_exchange_sources(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	let sources = db::get_client_token_exchange_sources(db, id).await.unwrap();
+
+	Ok(HttpResponse::Ok().json(sources))
+}
+
+#[get("/{client_id}/authorization-details-types")]
+async fn get_client_authorization_details_types(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	let types = db::get_client_authorization_details_types(db, id)
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::Ok().json(types))
+}
+
 #[get("/{client_id}/allowed-scopes")]
 async fn get_client_allowed_scopes(
 	client_id: web::Path<Uuid>,
@@ -181,6 +245,87 @@ async fn get_client_is_trusted(
 	Ok(HttpResponse::Ok().json(is_trusted))
 }
 
+#[get("/{client_id}/requires-pkce")]
+async fn get_client_requires_pkce(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(requires_pkce) = db::client_requires_pkce(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(requires_pkce))
+}
+
+#[get("/{client_id}/requires-par")]
+async fn get_client_requires_par(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(requires_par) = db::client_requires_par(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(requires_par))
+}
+
+#[get("/{client_id}/auth-method")]
+async fn get_client_auth_method(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(auth_method) = db::get_client_auth_method(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(auth_method))
+}
+
+#[get("/{client_id}/tls-client-auth-san-dns")]
+async fn get_client_tls_client_auth_san_dns(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(san_dns) = db::get_client_tls_client_auth_san_dns(db, id)
+		.await
+		.unwrap()
+	else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(san_dns))
+}
+
+#[get("/{client_id}/backchannel-client-notification-endpoint")]
+async fn get_client_backchannel_client_notification_endpoint(
+	client_id: web::Path<Uuid>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.as_ref();
+	let id = *client_id;
+
+	let Some(endpoint) = db::get_client_backchannel_client_notification_endpoint(db, id)
+		.await
+		.unwrap()
+	else {
+		yeet!(ClientNotFound::new(id))
+	};
+
+	Ok(HttpResponse::Ok().json(endpoint))
+}
+
 #[derive(Clone, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct ClientRequest {
@@ -191,6 +336,55 @@ struct ClientRequest {
 	allowed_scopes: Box<[Box<str>]>,
 	default_scopes: Option<Box<[Box<str>]>>,
 	trusted: bool,
+	/// Defaults to `true` for public clients, and `false` for confidential
+	/// clients
+	requires_pkce: Option<bool>,
+	/// If `true`, then authorization requests must be pushed first
+	#[serde(default)]
+	requires_par: bool,
+	/// The keys which the client signs request objects with
+	jwks: Option<JwkSet>,
+	/// Defaults to `client_secret_basic` for confidential clients, and `none`
+	/// for public clients
+	auth_method: Option<AuthMethod>,
+	/// The DNS name that the client's certificate is issued for, if the client
+	/// uses `tls_client_auth`
+	tls_client_auth_san_dns: Option<Box<str>>,
+	/// The endpoint which is pinged when a backchannel authentication request
+	/// is finished. If this isn't set, then the client polls instead.
+	backchannel_client_notification_endpoint: Option<Url>,
+}
+
+impl ClientRequest {
+	fn requires_pkce(&self) -> bool {
+		self.requires_pkce
+			.unwrap_or(self.client_type == ClientType::Public)
+	}
+
+	fn auth_method(&self) -> AuthMethod {
+		self.auth_method
+			.unwrap_or(AuthMethod::default_for(self.client_type))
+	}
+
+	fn client_options(&self) -> ClientOptions<'_> {
+		ClientOptions {
+			alias: &self.alias,
+			ty: self.client_type,
+			secret: self.secret.as_deref(),
+			allowed_scopes: self.allowed_scopes.clone(),
+			default_scopes: self.default_scopes.clone(),
+			redirect_uris: &self.redirect_uris,
+			trusted: self.trusted,
+			require_pkce: self.requires_pkce(),
+			require_par: self.requires_par,
+			jwks: self.jwks.clone(),
+			auth_method: self.auth_method(),
+			tls_client_auth_san_dns: self.tls_client_auth_san_dns.clone(),
+			backchannel_client_notification_endpoint: self
+				.backchannel_client_notification_endpoint
+				.clone(),
+		}
+	}
 }
 
 #[derive(Debug, Clone, Error)]
@@ -226,17 +420,7 @@ async fn create_client(
 	}
 
 	let id = id::new_id(db, db::client_id_exists).await.unwrap();
-	let client = Client::new(
-		id,
-		&alias,
-		body.client_type,
-		body.secret.as_deref(),
-		body.allowed_scopes.clone(),
-		body.default_scopes.clone(),
-		&body.redirect_uris,
-		body.trusted,
-	)
-	.map_err(|e| e.unwrap())?;
+	let client = Client::new(id, body.client_options()).map_err(|e| e.unwrap())?;
 
 	let transaction = db.begin().await.unwrap();
 	db::create_client(transaction, &client).await.unwrap();
@@ -284,17 +468,7 @@ async fn update_client(
 		yeet!(AliasTakenError::new(&alias).into());
 	}
 
-	let client = Client::new(
-		id,
-		&alias,
-		body.client_type,
-		body.secret.as_deref(),
-		body.allowed_scopes.clone(),
-		body.default_scopes.clone(),
-		&body.redirect_uris,
-		body.trusted,
-	)
-	.map_err(|e| e.unwrap())?;
+	let client = Client::new(id, body.client_options()).map_err(|e| e.unwrap())?;
 
 	let transaction = db.begin().await.unwrap();
 	db::update_client(transaction, &client).await.unwrap();
@@ -352,6 +526,10 @@ async fn update_client_type(
 	}
 
 	db::update_client_type(db, id, ty).await.unwrap();
+	// the authentication method has to match the new client type
+	db::update_client_auth_method(db, id, AuthMethod::default_for(ty))
+		.await
+		.unwrap();
 
 	Ok(HttpResponse::NoContent().finish())
 }
@@ -443,6 +621,177 @@ async fn update_client_is_trusted(
 	Ok(HttpResponse::NoContent().finish())
 }
 
+#[put("/{id}/requires-pkce")]
+async fn update_client_requires_pkce(
+	id: web::Path<Uuid>,
+	body: web::Json<bool>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let requires_pkce = *body;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id).into());
+	}
+
+	db::update_client_require_pkce(db, id, requires_pkce)
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("/{id}/requires-par")]
+async fn update_client_requires_par(
+	id: web::Path<Uuid>,
+	body: web::Json<bool>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let requires_par = *body;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id).into());
+	}
+
+	db::update_client_require_par(db, id, requires_par)
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("/{id}/jwks")]
+async fn update_client_jwks(
+	id: web::Path<Uuid>,
+	body: web::Json<Option<JwkSet>>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let jwks = body.0;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id).into());
+	}
+
+	if jwks.as_ref().is_some_and(|jwks| !jwks.is_valid()) {
+		yeet!(CreateClientError::UnsupportedKey.into());
+	}
+
+	let auth_method = db::get_client_auth_method(db, id).await.unwrap().unwrap();
+	if auth_method == AuthMethod::SelfSignedTlsClientAuth
+		&& !jwks.as_ref().is_some_and(JwkSet::has_certificates)
+	{
+		yeet!(CreateClientError::NoCertificate.into());
+	}
+	if auth_method == AuthMethod::PrivateKeyJwt && jwks.is_none() {
+		yeet!(CreateClientError::NoClientKeys.into());
+	}
+
+	db::update_client_jwks(db, id, jwks.as_ref()).await.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("/{id}/auth-method")]
+async fn update_client_auth_method(
+	id: web::Path<Uuid>,
+	body: web::Json<AuthMethod>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let auth_method = body.0;
+
+	let Some(client_type) = db::get_client_type(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id).into())
+	};
+
+	if (client_type == ClientType::Public) != (auth_method == AuthMethod::None) {
+		yeet!(CreateClientError::AuthMethodMismatch.into())
+	}
+
+	match auth_method {
+		AuthMethod::ClientSecretBasic | AuthMethod::ClientSecretPost => {
+			if db::get_client_secret(db, id).await.unwrap().is_none() {
+				yeet!(CreateClientError::NoSecret.into())
+			}
+		}
+		// the secret is only kept in a recoverable form for this method
+		AuthMethod::ClientSecretJwt => {
+			let ciphertext = db::get_client_secret_ciphertext(db, id).await.unwrap();
+			if ciphertext.is_none() {
+				yeet!(CreateClientError::NoSecretCiphertext.into())
+			}
+		}
+		AuthMethod::TlsClientAuth => {
+			let san_dns = db::get_client_tls_client_auth_san_dns(db, id)
+				.await
+				.unwrap()
+				.unwrap();
+			if san_dns.is_none() {
+				yeet!(CreateClientError::NoTlsSubject.into())
+			}
+		}
+		AuthMethod::SelfSignedTlsClientAuth => {
+			let jwks = db::get_client_jwks(db, id).await.unwrap().unwrap();
+			if !jwks.as_ref().is_some_and(JwkSet::has_certificates) {
+				yeet!(CreateClientError::NoCertificate.into())
+			}
+		}
+		AuthMethod::PrivateKeyJwt => {
+			if db::get_client_jwks(db, id)
+				.await
+				.unwrap()
+				.unwrap()
+				.is_none()
+			{
+				yeet!(CreateClientError::NoClientKeys.into())
+			}
+		}
+		AuthMethod::None => (),
+	}
+
+	db::update_client_auth_method(db, id, auth_method)
+		.await
+		.unwrap();
+	if auth_method != AuthMethod::ClientSecretJwt {
+		db::update_client_secret_ciphertext(db, id, None)
+			.await
+			.unwrap();
+	}
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("/{id}/tls-client-auth-san-dns")]
+async fn update_client_tls_client_auth_san_dns(
+	id: web::Path<Uuid>,
+	body: web::Json<Option<Box<str>>>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let san_dns = body.0;
+
+	let Some(auth_method) = db::get_client_auth_method(db, id).await.unwrap() else {
+		yeet!(ClientNotFound::new(id).into())
+	};
+
+	if auth_method == AuthMethod::TlsClientAuth && san_dns.is_none() {
+		yeet!(CreateClientError::NoTlsSubject.into())
+	}
+
+	db::update_client_tls_client_auth_san_dns(db, id, san_dns.as_deref())
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
 #[put("/{id}/redirect-uris")]
 async fn update_client_redirect_uris(
 	id: web::Path<Uuid>,
@@ -474,6 +823,57 @@ async fn update_client_redirect_uris(
 	Ok(HttpResponse::NoContent().finish())
 }
 
+/// Set which clients' tokens this client may exchange for new tokens. A client
+/// can always exchange its own tokens.
+#[put("/{id}/token-exchange-sources")]
+async fn update_client_token_exchange_sources(
+	id: web::Path<Uuid>,
+	body: web::Json<Box<[Uuid]>>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.get_ref();
+	let id = *id;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id));
+	}
+
+	for source in body.0.iter() {
+		if !db::client_id_exists(db, *source).await.unwrap() {
+			yeet!(ClientNotFound::new(*source));
+		}
+	}
+
+	let transaction = db.begin().await.unwrap();
+	db::update_client_token_exchange_sources(transaction, id, &body.0)
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+/// Set which types of authorization details this client may request
+#[put("/{id}/authorization-details-types")]
+async fn update_client_authorization_details_types(
+	id: web::Path<Uuid>,
+	body: web::Json<Box<[Box<str>]>>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ClientNotFound> {
+	let db = db.get_ref();
+	let id = *id;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id));
+	}
+
+	let transaction = db.begin().await.unwrap();
+	db::update_client_authorization_details_types(transaction, id, &body.0)
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
 #[put("{id}/secret")]
 async fn update_client_secret(
 	id: web::Path<Uuid>,
@@ -483,16 +883,51 @@ async fn update_client_secret(
 	let db = db.get_ref();
 	let id = *id;
 
-	let Some(client_type) = db::get_client_type(db, id).await.unwrap() else {
+	let Some(auth_method) = db::get_client_auth_method(db, id).await.unwrap() else {
 		yeet!(ClientNotFound::new(id).into())
 	};
 
-	if client_type == ClientType::Confidential && body.is_none() {
+	if auth_method.uses_secret() && body.is_none() {
 		yeet!(CreateClientError::NoSecret.into())
 	}
 
+	let ciphertext = body
+		.as_deref()
+		.filter(|_| auth_method == AuthMethod::ClientSecretJwt)
+		.map(|s| crypto::encrypt_secret(s).unwrap());
 	let secret = body.0.map(|s| PasswordHash::new(&s).unwrap());
 	db::update_client_secret(db, id, secret).await.unwrap();
+	db::update_client_secret_ciphertext(db, id, ciphertext.as_deref())
+		.await
+		.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+#[put("/{id}/backchannel-client-notification-endpoint")]
+async fn update_client_backchannel_client_notification_endpoint(
+	id: web::Path<Uuid>,
+	body: web::Json<Option<Url>>,
+	db: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UpdateClientError> {
+	let db = db.get_ref();
+	let id = *id;
+	let endpoint = body.0;
+
+	if !db::client_id_exists(db, id).await.unwrap() {
+		yeet!(ClientNotFound::new(id).into());
+	}
+
+	if endpoint
+		.as_ref()
+		.is_some_and(|endpoint| endpoint.scheme() != "https")
+	{
+		yeet!(CreateClientError::NonHttpsNotificationEndpoint.into());
+	}
+
+	db::update_client_backchannel_client_notification_endpoint(db, id, endpoint.as_ref())
+		.await
+		.unwrap();
 
 	Ok(HttpResponse::NoContent().finish())
 }
@@ -505,7 +940,15 @@ pub fn service() -> Scope {
 		.service(get_client_allowed_scopes)
 		.service(get_client_default_scopes)
 		.service(get_client_redirect_uris)
+		.service(get_client_token_exchange_sources)
+		.service(get_client_authorization_details_types)
 		.service(get_client_is_trusted)
+		.service(get_client_requires_pkce)
+		.service(get_client_requires_par)
+		.service(get_client_jwks)
+		.service(get_client_auth_method)
+		.service(get_client_tls_client_auth_san_dns)
+		.service(get_client_backchannel_client_notification_endpoint)
 		.service(create_client)
 		.service(update_client)
 		.service(update_client_alias)
@@ -513,6 +956,14 @@ pub fn service() -> Scope {
 		.service(update_client_allowed_scopes)
 		.service(update_client_default_scopes)
 		.service(update_client_is_trusted)
+		.service(update_client_requires_pkce)
+		.service(update_client_requires_par)
+		.service(update_client_jwks)
+		.service(update_client_auth_method)
+		.service(update_client_tls_client_auth_san_dns)
+		.service(update_client_backchannel_client_notification_endpoint)
 		.service(update_client_redirect_uris)
+		.service(update_client_token_exchange_sources)
+		.service(update_client_authorization_details_types)
 		.service(update_client_secret)
 }
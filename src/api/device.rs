@@ -0,0 +1,163 @@
+use std::str::FromStr;
+
+use actix_web::{get, post, web, HttpRequest, HttpResponse, Scope};
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use tera::Tera;
+use unic_langid::subtags::Language;
+
+use crate::resources::{languages, templates};
+use crate::services::device_code::{self, DeviceCodeStatus};
+use crate::services::{brute_force_detection, db};
+
+#[derive(Debug, Clone, Deserialize)]
+struct DevicePageParameters {
+	user_code: Option<Box<str>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum DeviceDecision {
+	Approve,
+	Deny,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeviceForm {
+	user_code: Box<str>,
+	username: Box<str>,
+	password: Box<str>,
+	decision: DeviceDecision,
+}
+
+/// The page where a user enters the code shown on their device. If the
+/// verification URI included the code, then it's filled in for them. The code
+/// isn't looked up until the user logs in, so that this page can't be used to
+/// guess user codes.
+#[get("")]
+async fn device_page(
+	params: web::Query<DevicePageParameters>,
+	tera: web::Data<Tera>,
+	translations: web::Data<languages::Translations>,
+) -> HttpResponse {
+	// TODO find a better way of doing languages
+	let language = Language::from_str("en").unwrap();
+	let translations = translations.get_ref().clone();
+
+	let user_code = params.user_code.as_deref();
+	let page = templates::device_page(&tera, user_code, None, language, translations).unwrap();
+	HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+#[post("")]
+async fn approve_device(
+	db: web::Data<MySqlPool>,
+	http_req: HttpRequest,
+	form: web::Form<DeviceForm>,
+	tera: web::Data<Tera>,
+	translations: web::Data<languages::Translations>,
+) -> HttpResponse {
+	// TODO find a better way of doing languages
+	let language = Language::from_str("en").unwrap();
+	let translations = translations.get_ref().clone();
+	let db = db.get_ref();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	// check for brute force attack
+	if brute_force_detection::brute_force_detected(db, &form.username, ip)
+		.await
+		.unwrap()
+	{
+		let page = templates::error_page(
+			&tera,
+			language,
+			translations,
+			templates::ErrorPage::TooManyRequests,
+		)
+		.unwrap();
+		return HttpResponse::TooManyRequests()
+			.content_type("text/html")
+			.body(page);
+	}
+
+	// authenticate user
+	let Some(user) = db::get_user_by_username(db, &form.username).await.unwrap() else {
+		db::add_failed_login_attempt(db, &form.username, ip)
+			.await
+			.unwrap();
+		let page = templates::device_page(
+			&tera,
+			Some(&form.user_code),
+			Some("loginErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	};
+	if !user.check_password(&form.password).unwrap() {
+		db::add_failed_login_attempt(db, &form.username, ip)
+			.await
+			.unwrap();
+		let page = templates::device_page(
+			&tera,
+			Some(&form.user_code),
+			Some("loginErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	}
+
+	// find the device code. Wrong guesses count as failed attempts, so the
+	// user codes can't be brute forced.
+	let user_code = device_code::normalize_user_code(&form.user_code);
+	let Some(device_code) = db::get_pending_device_code_by_user_code(db, &user_code)
+		.await
+		.unwrap()
+	else {
+		db::add_failed_login_attempt(db, &form.username, ip)
+			.await
+			.unwrap();
+		let page = templates::device_page(
+			&tera,
+			Some(&form.user_code),
+			Some("deviceErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	};
+
+	let approved = form.decision == DeviceDecision::Approve;
+	let status = if approved {
+		DeviceCodeStatus::Approved
+	} else {
+		DeviceCodeStatus::Denied
+	};
+	if !db::update_device_code_status(db, device_code.id, status, user.id)
+		.await
+		.unwrap()
+	{
+		let page = templates::device_page(
+			&tera,
+			Some(&form.user_code),
+			Some("deviceErrorMessage"),
+			language,
+			translations,
+		)
+		.unwrap();
+		return HttpResponse::Ok().content_type("text/html").body(page);
+	}
+
+	let page = templates::device_complete_page(&tera, approved, language, translations).unwrap();
+	HttpResponse::Ok().content_type("text/html").body(page)
+}
+
+pub fn service() -> Scope {
+	web::scope("/device")
+		.service(device_page)
+		.service(approve_device)
+}
@@ -1,11 +1,21 @@
+mod backchannel;
 mod clients;
+mod device;
 mod liveops;
 mod oauth;
+mod registration;
+mod resource_servers;
 mod users;
+mod well_known;
 
+pub use backchannel::service as backchannel;
 pub use clients::service as clients;
+pub use device::service as device;
 pub use liveops::service as liveops;
 pub use oauth::service as oauth;
+pub use registration::service as registration;
+pub use resource_servers::service as resource_servers;
 pub use users::service as users;
+pub use well_known::service as well_known;
 
-pub use oauth::AuthorizationParameters;
+pub use oauth::{AuthorizationRequest, ServerMetadata};
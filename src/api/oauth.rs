@@ -1,11 +1,12 @@
-use std::ops::Deref;
+use std::collections::HashMap;
+use std::net::IpAddr;
 use std::str::FromStr;
 
 use actix_web::http::{header, StatusCode};
 use actix_web::{
 	get, post, web, HttpRequest, HttpResponse, HttpResponseBuilder, ResponseError, Scope,
 };
-use chrono::Duration;
+use chrono::{Duration, Utc};
 use exun::{Expect, RawUnexpected, ResultErrorExt, UnexpectedError};
 use raise::yeet;
 use serde::{Deserialize, Serialize};
@@ -13,26 +14,128 @@ use sqlx::MySqlPool;
 use tera::Tera;
 use thiserror::Error;
 use unic_langid::subtags::Language;
-use url::Url;
+use url::{form_urlencoded, Url};
 use uuid::Uuid;
 
-use crate::models::client::ClientType;
+use crate::models::client::{AuthMethod, ClientType};
+use crate::models::resource_server::ResourceServer;
 use crate::resources::{languages, templates};
 use crate::scopes;
+use crate::services::authorization_details::{self, AuthorizationDetails};
+use crate::services::backchannel_authentication::{
+	BackchannelAuthentication, BackchannelAuthenticationStatus, MAX_BINDING_MESSAGE_LENGTH,
+};
+use crate::services::backchannel_notifier::{BackchannelNotifier, UserNotification};
+use crate::services::client_assertion::{self, VerifyClientAssertionError};
+use crate::services::consent::{self, ConsentRequest};
+use crate::services::device_code::{self, DeviceCode, DeviceCodeStatus};
+use crate::services::dpop::{self, VerifyProofError};
+use crate::services::id_token::IdTokenClaims;
+use crate::services::jarm;
+use crate::services::jws::JwsError;
 use crate::services::jwt::VerifyJwtError;
-use crate::services::{authorization, brute_force_detection, config, db, jwt};
+use crate::services::jwt_bearer::{self, TrustedIssuers, VerifyAssertionGrantError};
+use crate::services::keys::Algorithm;
+use crate::services::pkce::{CodeChallenge, CodeChallengeMethod};
+use crate::services::request_object;
+use crate::services::tls::ClientCertificate;
+use crate::services::{authorization, brute_force_detection, config, db, id, jwt};
 
 const REALLY_BAD_ERROR_PAGE: &str = "<!DOCTYPE html><html><head><title>Internal Server Error</title></head><body>Internal Server Error</body></html>";
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
-enum ResponseType {
+pub(super) enum ResponseType {
 	Code,
 	Token,
+	IdToken,
+	#[serde(rename = "id_token token")]
+	IdTokenToken,
+	#[serde(rename = "code id_token")]
+	CodeIdToken,
+	#[serde(rename = "code token")]
+	CodeToken,
+	#[serde(rename = "code id_token token")]
+	CodeIdTokenToken,
 	#[serde(other)]
 	Unsupported,
 }
 
+impl ResponseType {
+	/// Every response type that the authorization endpoint accepts
+	pub(super) const SUPPORTED: &'static [Self] = &[
+		Self::Code,
+		Self::Token,
+		Self::IdToken,
+		Self::IdTokenToken,
+		Self::CodeIdToken,
+		Self::CodeToken,
+		Self::CodeIdTokenToken,
+	];
+
+	fn includes_code(self) -> bool {
+		matches!(
+			self,
+			Self::Code | Self::CodeIdToken | Self::CodeToken | Self::CodeIdTokenToken
+		)
+	}
+
+	fn includes_token(self) -> bool {
+		matches!(
+			self,
+			Self::Token | Self::IdTokenToken | Self::CodeToken | Self::CodeIdTokenToken
+		)
+	}
+
+	fn includes_id_token(self) -> bool {
+		matches!(
+			self,
+			Self::IdToken | Self::IdTokenToken | Self::CodeIdToken | Self::CodeIdTokenToken
+		)
+	}
+}
+
+/// How the authorization response is sent to the redirect URI. The JWT modes
+/// sign the response first, as described in JARM.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) enum ResponseMode {
+	#[serde(rename = "query")]
+	Query,
+	#[serde(rename = "fragment")]
+	Fragment,
+	#[serde(rename = "form_post")]
+	FormPost,
+	/// Uses the default mode for the response type
+	#[serde(rename = "jwt")]
+	Jwt,
+	#[serde(rename = "query.jwt")]
+	QueryJwt,
+	#[serde(rename = "fragment.jwt")]
+	FragmentJwt,
+	#[serde(rename = "form_post.jwt")]
+	FormPostJwt,
+}
+
+impl ResponseMode {
+	/// Every response mode that the authorization endpoint accepts
+	pub(super) const SUPPORTED: &'static [Self] = &[
+		Self::Query,
+		Self::Fragment,
+		Self::FormPost,
+		Self::Jwt,
+		Self::QueryJwt,
+		Self::FragmentJwt,
+		Self::FormPostJwt,
+	];
+
+	fn is_jwt(self) -> bool {
+		matches!(
+			self,
+			Self::Jwt | Self::QueryJwt | Self::FragmentJwt | Self::FormPostJwt
+		)
+	}
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AuthorizationParameters {
 	response_type: ResponseType,
@@ -40,26 +143,115 @@ pub struct AuthorizationParameters {
 	redirect_uri: Option<Url>,
 	scope: Option<Box<str>>,
 	state: Option<Box<str>>,
+	code_challenge: Option<Box<str>>,
+	code_challenge_method: Option<CodeChallengeMethod>,
+	nonce: Option<Box<str>>,
+	#[serde(default, with = "authorization_details::form")]
+	authorization_details: Option<AuthorizationDetails>,
+	/// The resource server that the access token is meant for, as described in
+	/// RFC 8707
+	resource: Option<Url>,
+	response_mode: Option<ResponseMode>,
 }
 
-#[derive(Clone, Deserialize)]
-struct AuthorizeCredentials {
-	username: Box<str>,
-	password: Box<str>,
+impl AuthorizationParameters {
+	/// Only the authorization code is sent in the query by default. Any tokens
+	/// are sent in the fragment instead.
+	fn response_mode(&self) -> ResponseMode {
+		let response_type = self.response_type;
+		let uses_fragment = response_type.includes_token() || response_type.includes_id_token();
+		match (self.response_mode, uses_fragment) {
+			(None, false) => ResponseMode::Query,
+			(None, true) => ResponseMode::Fragment,
+			(Some(ResponseMode::Jwt), false) => ResponseMode::QueryJwt,
+			(Some(ResponseMode::Jwt), true) => ResponseMode::FragmentJwt,
+			(Some(response_mode), _) => response_mode,
+		}
+	}
 }
 
-#[derive(Clone, Serialize)]
-struct AuthCodeResponse {
-	code: Box<str>,
-	state: Option<Box<str>>,
+/// The prefix of every request URI issued by the pushed authorization request
+/// endpoint
+const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+/// How many seconds a client has to use a pushed authorization request
+const PUSHED_AUTHORIZATION_LIFETIME: i64 = 60;
+
+/// Refers to authorization parameters that were pushed to the server earlier,
+/// as described in RFC 9126
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PushedAuthorizationReference {
+	client_id: Box<str>,
+	request_uri: Box<str>,
 }
 
-#[derive(Clone, Serialize)]
-struct AuthTokenResponse {
-	access_token: Box<str>,
-	token_type: &'static str,
-	expires_in: i64,
-	scope: Box<str>,
+/// Authorization parameters inside of a JWT signed by the client, as described
+/// in RFC 9101
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RequestObjectReference {
+	client_id: Box<str>,
+	request: Box<str>,
+	/// Any other parameters in the query, which must match the request object
+	#[serde(flatten)]
+	query: HashMap<Box<str>, Box<str>>,
+}
+
+/// The parameters of an authorization request can either be in the query, in
+/// a signed request object, or be pushed to the server before the user is
+/// redirected.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum AuthorizationRequest {
+	Pushed(PushedAuthorizationReference),
+	Signed(RequestObjectReference),
+	Inline(AuthorizationParameters),
+}
+
+impl AuthorizationRequest {
+	pub fn client_id(&self) -> &str {
+		match self {
+			Self::Pushed(reference) => &reference.client_id,
+			Self::Signed(reference) => &reference.client_id,
+			Self::Inline(params) => &params.client_id,
+		}
+	}
+}
+
+fn pushed_authorization_id(request_uri: &str) -> Option<Uuid> {
+	request_uri.strip_prefix(REQUEST_URI_PREFIX)?.parse().ok()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ConsentDecision {
+	Approve,
+	Deny,
+}
+
+/// The login page sends the user's credentials. The consent page sends the
+/// user's decision instead, since the user has already logged in by then.
+#[derive(Clone, Deserialize)]
+#[serde(untagged)]
+enum AuthorizeCredentials {
+	Login {
+		username: Box<str>,
+		password: Box<str>,
+	},
+	Consent {
+		consent_request: Uuid,
+		decision: ConsentDecision,
+	},
+}
+
+/// Which of these are included depends on the response type
+#[derive(Clone, Default, Serialize)]
+struct AuthorizationResponse {
+	code: Option<Box<str>>,
+	access_token: Option<Box<str>>,
+	token_type: Option<&'static str>,
+	expires_in: Option<i64>,
+	scope: Option<Box<str>>,
+	id_token: Option<Box<str>>,
 	state: Option<Box<str>>,
 }
 
@@ -73,6 +265,8 @@ enum AuthorizeErrorType {
 	InvalidScope,
 	ServerError,
 	TemporarilyUnavailable,
+	InvalidAuthorizationDetails,
+	InvalidTarget,
 }
 
 #[derive(Debug, Clone, Error, Serialize)]
@@ -116,6 +310,82 @@ impl AuthorizeError {
 		}
 	}
 
+	fn missing_code_challenge(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidRequest,
+			error_description: Box::from("This client must provide a code challenge"),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn invalid_code_challenge(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidRequest,
+			error_description: Box::from(
+				"The code challenge must be between 43 and 128 unreserved characters",
+			),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn missing_openid_scope(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidScope,
+			error_description: Box::from("This response type requires the openid scope"),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn missing_nonce(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidRequest,
+			error_description: Box::from("A nonce is required when requesting an ID token"),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn invalid_authorization_details(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidAuthorizationDetails,
+			error_description: Box::from(
+				"The client is not allowed to request this type of authorization details",
+			),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn tokens_in_query(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidRequest,
+			error_description: Box::from("Tokens cannot be sent in the query"),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn access_denied(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::AccessDenied,
+			error_description: Box::from("The user denied the request"),
+			state,
+			redirect_uri,
+		}
+	}
+
+	fn invalid_target(redirect_uri: Url, state: Option<Box<str>>) -> Self {
+		Self {
+			error: AuthorizeErrorType::InvalidTarget,
+			error_description: Box::from("The requested resource is not a known resource server"),
+			state,
+			redirect_uri,
+		}
+	}
+
 	fn internal_server_error(redirect_uri: Url, state: Option<Box<str>>) -> Self {
 		Self {
 			error: AuthorizeErrorType::ServerError,
@@ -126,17 +396,87 @@ impl AuthorizeError {
 	}
 }
 
-impl ResponseError for AuthorizeError {
-	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-		let query = Some(serde_urlencoded::to_string(self).unwrap());
-		let query = query.as_deref();
-		let mut url = self.redirect_uri.clone();
-		url.set_query(query);
-
-		HttpResponse::Found()
-			.insert_header((header::LOCATION, url.as_str()))
+/// Send the result of an authorization request to the redirect URI, in the
+/// way that the client asked for. The issuer is always included, so that a
+/// client which uses more than one authorization server can tell which one the
+/// response came from, as described in RFC 9207.
+async fn send_authorization_response(
+	db: &MySqlPool,
+	tera: &Tera,
+	translations: &languages::Translations,
+	client_alias: &str,
+	mut redirect_uri: Url,
+	response_mode: ResponseMode,
+	response: &impl Serialize,
+) -> Result<HttpResponse, RawUnexpected> {
+	let self_id = config::get_config()?.url;
+	let encoded = if response_mode.is_jwt() {
+		// signed responses already have an issuer claim
+		let response = jarm::encode_response(db, self_id, client_alias, response).await?;
+		serde_urlencoded::to_string([("response", response)])?
+	} else {
+		let encoded = serde_urlencoded::to_string(response)?;
+		form_urlencoded::Serializer::new(encoded)
+			.append_pair("iss", self_id.as_str())
 			.finish()
+	};
+
+	match response_mode {
+		ResponseMode::Query | ResponseMode::QueryJwt => redirect_uri.set_query(Some(&encoded)),
+		ResponseMode::Fragment | ResponseMode::FragmentJwt => {
+			redirect_uri.set_fragment(Some(&encoded))
+		}
+		ResponseMode::FormPost | ResponseMode::FormPostJwt => {
+			// the browser posts the response to the client as soon as the page loads
+			let params = form_urlencoded::parse(encoded.as_bytes())
+				.into_owned()
+				.collect();
+			// TODO find a better way of doing languages
+			let language = Language::from_str("en").unwrap();
+			let page = templates::form_post_page(
+				tera,
+				&redirect_uri,
+				&params,
+				language,
+				translations.clone(),
+			)?;
+			return Ok(HttpResponse::Ok().content_type("text/html").body(page));
+		}
+		// this is resolved by AuthorizationParameters::response_mode
+		ResponseMode::Jwt => unreachable!(),
 	}
+
+	Ok(HttpResponse::Found()
+		.append_header((header::LOCATION, redirect_uri.as_str()))
+		.finish())
+}
+
+/// Errors are sent to the client the same way that a successful response would
+/// have been
+async fn send_authorize_error(
+	db: &MySqlPool,
+	tera: &Tera,
+	translations: &languages::Translations,
+	client_alias: &str,
+	response_mode: ResponseMode,
+	error: AuthorizeError,
+) -> HttpResponse {
+	let redirect_uri = error.redirect_uri.clone();
+	send_authorization_response(
+		db,
+		tera,
+		translations,
+		client_alias,
+		redirect_uri,
+		response_mode,
+		&error,
+	)
+	.await
+	.unwrap_or_else(|_| {
+		HttpResponse::InternalServerError()
+			.content_type("text/html")
+			.body(REALLY_BAD_ERROR_PAGE)
+	})
 }
 
 fn error_page(
@@ -151,6 +491,114 @@ fn error_page(
 	Ok(page)
 }
 
+async fn get_authorization_parameters(
+	request: &AuthorizationRequest,
+	db: &MySqlPool,
+	client_id: Uuid,
+) -> Result<AuthorizationParameters, Expect<templates::ErrorPage>> {
+	let is_pushed = matches!(request, AuthorizationRequest::Pushed(_));
+	if !is_pushed
+		&& db::client_requires_par(db, client_id)
+			.await?
+			.unwrap_or(false)
+	{
+		yeet!(Expect::Expected(
+			templates::ErrorPage::PushedAuthorizationRequired
+		));
+	}
+
+	let reference = match request {
+		AuthorizationRequest::Inline(params) => return Ok(params.clone()),
+		AuthorizationRequest::Signed(reference) => {
+			return get_request_object_parameters(reference, db, client_id).await
+		}
+		AuthorizationRequest::Pushed(reference) => reference,
+	};
+
+	let Some(id) = pushed_authorization_id(&reference.request_uri) else {
+		yeet!(Expect::Expected(templates::ErrorPage::InvalidRequestUri));
+	};
+	let Some(pushed) = db::get_pushed_authorization(db, id).await? else {
+		yeet!(Expect::Expected(templates::ErrorPage::InvalidRequestUri));
+	};
+	if pushed.client_id != client_id {
+		yeet!(Expect::Expected(templates::ErrorPage::InvalidRequestUri));
+	}
+
+	let params = serde_json::from_str(&pushed.parameters).unexpect()?;
+	Ok(params)
+}
+
+/// The user agreed to the parameters that were stored with the consent
+/// request, so those are used instead of the ones in the query. Returns the
+/// user who is giving consent, along with the parameters.
+async fn use_consent_request(
+	id: Uuid,
+	db: &MySqlPool,
+	client_id: Uuid,
+) -> Result<(Uuid, AuthorizationParameters), Expect<templates::ErrorPage>> {
+	let Some(request) = db::get_consent_request(db, id).await? else {
+		yeet!(Expect::Expected(
+			templates::ErrorPage::InvalidConsentRequest
+		));
+	};
+	if request.client_id != client_id || request.is_expired() {
+		yeet!(Expect::Expected(
+			templates::ErrorPage::InvalidConsentRequest
+		));
+	}
+
+	// consent requests can only be used once
+	if !db::delete_consent_request(db, id).await? {
+		yeet!(Expect::Expected(
+			templates::ErrorPage::InvalidConsentRequest
+		));
+	}
+
+	let params = serde_json::from_str(&request.parameters).unexpect()?;
+	Ok((request.user_id, params))
+}
+
+/// Verify the request object, and make sure that it doesn't contradict the
+/// parameters in the query
+async fn get_request_object_parameters(
+	reference: &RequestObjectReference,
+	db: &MySqlPool,
+	client_id: Uuid,
+) -> Result<AuthorizationParameters, Expect<templates::ErrorPage>> {
+	let self_id = config::get_config()?.url;
+	let claims = request_object::verify_request_object(
+		db,
+		&reference.request,
+		&self_id,
+		client_id,
+		&reference.client_id,
+	)
+	.await
+	.map_err(|e| e.map(|_| templates::ErrorPage::InvalidRequestObject))?;
+
+	// every authorization parameter is a string
+	let conflicts = |name: &str, value: &str| {
+		claims
+			.get(name)
+			.is_some_and(|claim| claim.as_str() != Some(value))
+	};
+	let query_conflicts = reference
+		.query
+		.iter()
+		.any(|(name, value)| conflicts(name, value));
+	if query_conflicts || conflicts("client_id", &reference.client_id) {
+		yeet!(Expect::Expected(
+			templates::ErrorPage::ConflictingRequestObject
+		));
+	}
+
+	let Ok(params) = serde_json::from_value(serde_json::Value::Object(claims)) else {
+		yeet!(Expect::Expected(templates::ErrorPage::InvalidRequestObject));
+	};
+	Ok(params)
+}
+
 async fn get_redirect_uri(
 	redirect_uri: &Option<Url>,
 	db: &MySqlPool,
@@ -212,6 +660,116 @@ async fn get_scope(
 	Ok(scope)
 }
 
+async fn get_code_challenge(
+	params: &AuthorizationParameters,
+	db: &MySqlPool,
+	client_id: Uuid,
+	redirect_uri: &Url,
+) -> Result<Option<CodeChallenge>, Expect<AuthorizeError>> {
+	let state = &params.state;
+	let Some(challenge) = &params.code_challenge else {
+		let requires_pkce = db::client_requires_pkce(db, client_id)
+			.await?
+			.unwrap_or(true);
+		if requires_pkce && params.response_type.includes_code() {
+			yeet!(
+				AuthorizeError::missing_code_challenge(redirect_uri.clone(), state.clone()).into()
+			);
+		}
+
+		return Ok(None);
+	};
+
+	let method = params.code_challenge_method.unwrap_or_default();
+	let Some(challenge) = CodeChallenge::new(challenge, method) else {
+		yeet!(AuthorizeError::invalid_code_challenge(redirect_uri.clone(), state.clone()).into());
+	};
+
+	Ok(Some(challenge))
+}
+
+async fn get_authorization_details(
+	params: &AuthorizationParameters,
+	db: &MySqlPool,
+	client_id: Uuid,
+	redirect_uri: &Url,
+) -> Result<Option<AuthorizationDetails>, Expect<AuthorizeError>> {
+	let Some(details) = &params.authorization_details else {
+		return Ok(None);
+	};
+
+	if !authorization_details::types_are_allowed(db, client_id, details).await? {
+		yeet!(AuthorizeError::invalid_authorization_details(
+			redirect_uri.clone(),
+			params.state.clone()
+		)
+		.into());
+	}
+
+	Ok(Some(details.clone()))
+}
+
+async fn get_resource_server(
+	params: &AuthorizationParameters,
+	db: &MySqlPool,
+	redirect_uri: &Url,
+) -> Result<Option<ResourceServer>, Expect<AuthorizeError>> {
+	let Some(resource) = &params.resource else {
+		return Ok(None);
+	};
+
+	let Some(resource_server) = db::get_resource_server_by_uri(db, resource).await? else {
+		yeet!(AuthorizeError::invalid_target(redirect_uri.clone(), params.state.clone()).into());
+	};
+
+	Ok(Some(resource_server))
+}
+
+/// Tokens can't be sent in the query, because it might end up in logs or the
+/// browser history. Returns the error if the response mode would do that.
+fn response_mode_error(
+	params: &AuthorizationParameters,
+	response_mode: ResponseMode,
+	redirect_uri: &Url,
+) -> Option<AuthorizeError> {
+	let response_type = params.response_type;
+	let has_tokens = response_type.includes_token() || response_type.includes_id_token();
+	let uses_query = matches!(response_mode, ResponseMode::Query | ResponseMode::QueryJwt);
+	if has_tokens && uses_query {
+		return Some(AuthorizeError::tokens_in_query(
+			redirect_uri.clone(),
+			params.state.clone(),
+		));
+	}
+
+	None
+}
+
+/// An ID token can only be requested from the authorization endpoint with the
+/// `openid` scope and a nonce. Returns the error if the request is invalid.
+fn id_token_request_error(
+	params: &AuthorizationParameters,
+	scope: &str,
+	redirect_uri: &Url,
+) -> Option<AuthorizeError> {
+	if !params.response_type.includes_id_token() {
+		return None;
+	}
+
+	let state = params.state.clone();
+	if !scopes::is_subset_of(scopes::OPENID, scope) {
+		return Some(AuthorizeError::missing_openid_scope(
+			redirect_uri.clone(),
+			state,
+		));
+	}
+	if params.nonce.is_none() {
+		return Some(AuthorizeError::missing_nonce(redirect_uri.clone(), state));
+	}
+
+	None
+}
+
 async fn authenticate_user(
 	db: &MySqlPool,
 	username: &str,
@@ -232,34 +790,83 @@ async fn authenticate_user(
 async fn authorize(
 	db: web::Data<MySqlPool>,
 	http_req: HttpRequest,
-	req: web::Query<AuthorizationParameters>,
+	req: web::Query<AuthorizationRequest>,
 	credentials: web::Form<AuthorizeCredentials>,
 	tera: web::Data<Tera>,
 	translations: web::Data<languages::Translations>,
-) -> Result<HttpResponse, AuthorizeError> {
+) -> HttpResponse {
 	let db = db.get_ref();
-	let Ok(client_id) = db::get_client_id_by_alias(db, &req.client_id).await else {
-		let page = error_page(&tera, &translations, templates::ErrorPage::InternalServerError).unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::InternalServerError().content_type("text/html").body(page));
+	let Ok(client_id) = db::get_client_id_by_alias(db, req.client_id()).await else {
+		let page = error_page(
+			&tera,
+			&translations,
+			templates::ErrorPage::InternalServerError,
+		)
+		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+		return HttpResponse::InternalServerError()
+			.content_type("text/html")
+			.body(page);
 	};
 	let Some(client_id) = client_id else {
-		let page = error_page(&tera, &translations, templates::ErrorPage::ClientNotFound).unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::NotFound().content_type("text/html").body(page));
+		let page = error_page(&tera, &translations, templates::ErrorPage::ClientNotFound)
+			.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+		return HttpResponse::NotFound()
+			.content_type("text/html")
+			.body(page);
 	};
 	let Ok(config) = config::get_config() else {
-		let page = error_page(&tera, &translations, templates::ErrorPage::InternalServerError).unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::InternalServerError().content_type("text/html").body(page));
+		let page = error_page(
+			&tera,
+			&translations,
+			templates::ErrorPage::InternalServerError,
+		)
+		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+		return HttpResponse::InternalServerError()
+			.content_type("text/html")
+			.body(page);
 	};
 	let Some(addr) = http_req.peer_addr() else {
-		let page = error_page(&tera, &translations, templates::ErrorPage::InternalServerError).unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::InternalServerError().content_type("text/html").body(page));
+		let page = error_page(
+			&tera,
+			&translations,
+			templates::ErrorPage::InternalServerError,
+		)
+		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+		return HttpResponse::InternalServerError()
+			.content_type("text/html")
+			.body(page);
 	};
 
 	let self_id = config.url;
-	let state = req.state.clone();
+
+	// get the parameters, if they were pushed or agreed to by the user
+	let params = match &*credentials {
+		AuthorizeCredentials::Login { .. } => get_authorization_parameters(&req, db, client_id)
+			.await
+			.map(|params| (None, params)),
+		AuthorizeCredentials::Consent {
+			consent_request, ..
+		} => use_consent_request(*consent_request, db, client_id)
+			.await
+			.map(|(user_id, params)| (Some(user_id), params)),
+	};
+	let (consenting_user, params) = match params {
+		Ok(params) => params,
+		Err(e) => {
+			let e = e
+				.expected()
+				.unwrap_or(templates::ErrorPage::InternalServerError);
+			let page = error_page(&tera, &translations, e)
+				.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+			return HttpResponse::BadRequest()
+				.content_type("text/html")
+				.body(page);
+		}
+	};
+	let state = params.state.clone();
 
 	// get redirect uri
-	let mut redirect_uri = match get_redirect_uri(&req.redirect_uri, db, client_id).await {
+	let redirect_uri = match get_redirect_uri(&params.redirect_uri, db, client_id).await {
 		Ok(uri) => uri,
 		Err(e) => {
 			let e = e
@@ -267,102 +874,292 @@ async fn authorize(
 				.unwrap_or(templates::ErrorPage::InternalServerError);
 			let page = error_page(&tera, &translations, e)
 				.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-			return Ok(HttpResponse::BadRequest()
+			return HttpResponse::BadRequest()
 				.content_type("text/html")
-				.body(page));
+				.body(page);
 		}
 	};
 
-	let internal_server_error =
-		AuthorizeError::internal_server_error(redirect_uri.clone(), state.clone());
+	// errors after this point are sent back to the client
+	let response_mode = params.response_mode();
+	let response = async {
+		let internal_server_error =
+			AuthorizeError::internal_server_error(redirect_uri.clone(), state.clone());
 
-	// check for brute force attack
-	let Ok(brute_force_detected) = brute_force_detection::brute_force_detected(db, &credentials.username, addr.ip()).await else {
-		yeet!(internal_server_error.clone());
-	};
-	if brute_force_detected {
-		let Ok(page) = error_page(&tera, &translations, templates::ErrorPage::TooManyRequests) else {
-			yeet!(internal_server_error.clone());
-		};
-		return Ok(HttpResponse::TooManyRequests()
-			.content_type("text/html")
-			.body(page));
-	}
+		let user_id = match (&*credentials, consenting_user) {
+			(AuthorizeCredentials::Login { username, password }, _) => {
+				// check for brute force attack
+				let Ok(brute_force_detected) =
+					brute_force_detection::brute_force_detected(db, username, addr.ip()).await
+				else {
+					yeet!(internal_server_error.clone());
+				};
+				if brute_force_detected {
+					let Ok(page) =
+						error_page(&tera, &translations, templates::ErrorPage::TooManyRequests)
+					else {
+						yeet!(internal_server_error.clone());
+					};
+					return Ok(HttpResponse::TooManyRequests()
+						.content_type("text/html")
+						.body(page));
+				}
 
-	// authenticate user
-	let Some(user_id) = authenticate_user(db, &credentials.username, &credentials.password)
-		.await
-		.unwrap() else // TODO remove unwrap
-	{
-		if db::add_failed_login_attempt(db, &credentials.username, addr.ip()).await.is_err() {
-			yeet!(internal_server_error.clone());
+				// authenticate user
+				let Some(user_id) = authenticate_user(db, username, password).await.unwrap() else
+				// TODO remove unwrap
+				{
+					if db::add_failed_login_attempt(db, username, addr.ip())
+						.await
+						.is_err()
+					{
+						yeet!(internal_server_error.clone());
+					}
+					let language = Language::from_str("en").unwrap();
+					let translations = translations.get_ref().clone();
+					let page = templates::login_error_page(
+						&tera,
+						&req,
+						params.authorization_details.as_ref(),
+						language,
+						translations,
+					)
+					.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+					return Ok(HttpResponse::Ok().content_type("text/html").body(page));
+				};
+
+				user_id
+			}
+			(AuthorizeCredentials::Consent { .. }, Some(user_id)) => user_id,
+			(AuthorizeCredentials::Consent { .. }, None) => unreachable!(),
+		};
+
+		// pushed authorization requests can only be used once, and the
+		// parameters are stored with the consent request if they're needed again
+		if let AuthorizationRequest::Pushed(reference) = &*req {
+			if let Some(id) = pushed_authorization_id(&reference.request_uri) {
+				db::delete_pushed_authorization(db, id)
+					.await
+					.map_err(|_| internal_server_error.clone())?;
+			}
 		}
-		let language = Language::from_str("en").unwrap();
-		let translations = translations.get_ref().clone();
-		let page = templates::login_error_page(&tera, &req, language, translations).unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::Ok().content_type("text/html").body(page));
-	};
 
-	// get scope
-	let scope = match get_scope(&req.scope, db, client_id, &redirect_uri, &state).await {
-		Ok(scope) => scope,
-		Err(e) => {
-			let e = e.expected().unwrap_or(internal_server_error);
+		// get scope
+		let scope = match get_scope(&params.scope, db, client_id, &redirect_uri, &state).await {
+			Ok(scope) => scope,
+			Err(e) => {
+				let e = e.expected().unwrap_or(internal_server_error);
+				return Err(e);
+			}
+		};
+
+		// get code challenge
+		let code_challenge = match get_code_challenge(&params, db, client_id, &redirect_uri).await {
+			Ok(challenge) => challenge,
+			Err(e) => {
+				let e = e.expected().unwrap_or(internal_server_error);
+				return Err(e);
+			}
+		};
+
+		// get authorization details
+		let authorization_details =
+			match get_authorization_details(&params, db, client_id, &redirect_uri).await {
+				Ok(details) => details,
+				Err(e) => {
+					let e = e.expected().unwrap_or(internal_server_error);
+					return Err(e);
+				}
+			};
+
+		// get resource server
+		let resource_server = match get_resource_server(&params, db, &redirect_uri).await {
+			Ok(resource_server) => resource_server,
+			Err(e) => {
+				let e = e.expected().unwrap_or(internal_server_error);
+				return Err(e);
+			}
+		};
+
+		// verify openid parameters
+		if let Some(e) = id_token_request_error(&params, &scope, &redirect_uri) {
 			return Err(e);
 		}
-	};
 
-	match req.response_type {
-		ResponseType::Code => {
+		let response_type = params.response_type;
+		if response_type == ResponseType::Unsupported {
+			yeet!(AuthorizeError::unsupported_response_type(
+				redirect_uri.clone(),
+				state
+			));
+		}
+		if let Some(e) = response_mode_error(&params, response_mode, &redirect_uri) {
+			return Err(e);
+		}
+
+		// the user must allow untrusted clients to have these scopes
+		let is_trusted = db::is_client_trusted(db, client_id)
+			.await
+			.map_err(|_| internal_server_error.clone())?
+			.unwrap_or_default();
+		match &*credentials {
+			AuthorizeCredentials::Consent {
+				decision: ConsentDecision::Deny,
+				..
+			} => yeet!(AuthorizeError::access_denied(redirect_uri.clone(), state)),
+			AuthorizeCredentials::Consent {
+				decision: ConsentDecision::Approve,
+				..
+			} => consent::grant(db, user_id, client_id, &scope)
+				.await
+				.map_err(|_| internal_server_error.clone())?,
+			AuthorizeCredentials::Login { .. } => {
+				let is_granted = consent::is_granted(
+					db,
+					user_id,
+					client_id,
+					&scope,
+					authorization_details.as_ref(),
+				)
+				.await
+				.map_err(|_| internal_server_error.clone())?;
+				if !is_trusted && !is_granted {
+					// the user is asked about exactly these parameters
+					let parameters = AuthorizationParameters {
+						scope: Some(scope.clone()),
+						redirect_uri: Some(redirect_uri.clone()),
+						..params.clone()
+					};
+					let parameters = serde_json::to_string(&parameters)
+						.map_err(|_| internal_server_error.clone())?;
+					let consent_request = ConsentRequest::new(db, client_id, user_id, &parameters)
+						.await
+						.map_err(|_| internal_server_error.clone())?;
+					let language = Language::from_str("en").unwrap();
+					let translations = translations.get_ref().clone();
+					let page = templates::consent_page(
+						&tera,
+						&req,
+						&consent_request.id.to_string(),
+						&scope,
+						authorization_details.as_ref(),
+						language,
+						translations,
+					)
+					.map_err(|_| internal_server_error.clone())?;
+					return Ok(HttpResponse::Ok().content_type("text/html").body(page));
+				}
+			}
+		}
+
+		let mut response = AuthorizationResponse {
+			state,
+			..Default::default()
+		};
+
+		if response_type.includes_code() {
 			// create auth code
-			let code =
-				jwt::Claims::auth_code(db, self_id, client_id, user_id, &scope, &redirect_uri)
+			let code = jwt::Claims::auth_code(
+				db,
+				self_id.clone(),
+				client_id,
+				user_id,
+				&scope,
+				&redirect_uri,
+				code_challenge,
+			)
+			.await
+			.map_err(|_| internal_server_error.clone())?
+			.with_nonce(params.nonce.as_deref())
+			.with_authorization_details(authorization_details.as_ref())
+			.with_resource(params.resource.as_ref());
+
+			// revoking the consent also revokes the tokens issued with this code
+			if !is_trusted {
+				db::add_consent_token(db, user_id, client_id, code.grant_id())
 					.await
 					.map_err(|_| internal_server_error.clone())?;
-			let code = code.to_jwt().map_err(|_| internal_server_error.clone())?;
-
-			let response = AuthCodeResponse { code, state };
-			let query =
-				Some(serde_urlencoded::to_string(response).map_err(|_| internal_server_error)?);
-			let query = query.as_deref();
-			redirect_uri.set_query(query);
+			}
 
-			Ok(HttpResponse::Found()
-				.append_header((header::LOCATION, redirect_uri.as_str()))
-				.finish())
+			let code = code
+				.to_jwt(db)
+				.await
+				.map_err(|_| internal_server_error.clone())?;
+			response.code = Some(code);
 		}
-		ResponseType::Token => {
+
+		if response_type.includes_token() {
 			// create access token
 			let duration = Duration::hours(1);
-			let access_token =
-				jwt::Claims::access_token(db, None, self_id, client_id, user_id, duration, &scope)
+			let access_token = jwt::Claims::access_token(
+				db,
+				None,
+				self_id.clone(),
+				client_id,
+				user_id,
+				duration,
+				&scope,
+			)
+			.await
+			.map_err(|_| internal_server_error.clone())?
+			.with_authorization_details(authorization_details.as_ref())
+			.restrict_to(resource_server.as_ref());
+
+			if !is_trusted {
+				db::add_consent_token(db, user_id, client_id, access_token.grant_id())
 					.await
 					.map_err(|_| internal_server_error.clone())?;
+			}
 
+			let token_scope = access_token.scopes().into();
 			let access_token = access_token
-				.to_jwt()
+				.to_jwt(db)
+				.await
 				.map_err(|_| internal_server_error.clone())?;
-			let expires_in = duration.num_seconds();
-			let token_type = "bearer";
-			let response = AuthTokenResponse {
-				access_token,
-				expires_in,
-				token_type,
-				scope,
-				state,
-			};
+			response.access_token = Some(access_token);
+			response.token_type = Some("bearer");
+			response.expires_in = Some(duration.num_seconds());
+			response.scope = Some(token_scope);
+		}
 
-			let fragment = Some(
-				serde_urlencoded::to_string(response).map_err(|_| internal_server_error.clone())?,
-			);
-			let fragment = fragment.as_deref();
-			redirect_uri.set_fragment(fragment);
+		// only the response types which name an ID token get one
+		if response_type.includes_id_token() {
+			let id_token = IdTokenClaims::new(
+				self_id,
+				&params.client_id,
+				user_id,
+				Some(Utc::now()),
+				params.nonce.as_deref(),
+			)
+			.to_jwt(
+				db,
+				response.access_token.as_deref(),
+				response.code.as_deref(),
+			)
+			.await
+			.map_err(|_| internal_server_error.clone())?;
+			response.id_token = Some(id_token);
+		}
+
+		send_authorization_response(
+			db,
+			&tera,
+			&translations,
+			req.client_id(),
+			redirect_uri.clone(),
+			response_mode,
+			&response,
+		)
+		.await
+		.map_err(|_| internal_server_error)
+	}
+	.await;
 
-			Ok(HttpResponse::Found()
-				.append_header((header::LOCATION, redirect_uri.as_str()))
-				.finish())
+	match response {
+		Ok(response) => response,
+		Err(e) => {
+			send_authorize_error(db, &tera, &translations, req.client_id(), response_mode, e).await
 		}
-		_ => Err(AuthorizeError::invalid_scope(redirect_uri, state)),
 	}
 }
 
@@ -372,31 +1169,31 @@ async fn authorize_page(
 	tera: web::Data<Tera>,
 	translations: web::Data<languages::Translations>,
 	request: HttpRequest,
-) -> Result<HttpResponse, AuthorizeError> {
+) -> HttpResponse {
 	let Ok(language) = Language::from_str("en") else {
 		let page = String::from(REALLY_BAD_ERROR_PAGE);
-		return Ok(HttpResponse::InternalServerError()
+		return HttpResponse::InternalServerError()
 			.content_type("text/html")
-			.body(page));
+			.body(page);
 	};
 	let translations = translations.get_ref().clone();
 
-	let params = request.query_string();
-	let params = serde_urlencoded::from_str::<AuthorizationParameters>(params);
-	let Ok(params) = params else {
+	let auth_request = request.query_string();
+	let auth_request = serde_urlencoded::from_str::<AuthorizationRequest>(auth_request);
+	let Ok(auth_request) = auth_request else {
 		let page = error_page(
 			&tera,
 			&translations,
 			templates::ErrorPage::InvalidRequest,
 		)
 		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::BadRequest()
+		return HttpResponse::BadRequest()
 			.content_type("text/html")
-			.body(page));
+			.body(page);
 	};
 
 	let db = db.get_ref();
-	let Ok(client_id) = db::get_client_id_by_alias(db, &params.client_id).await else {
+	let Ok(client_id) = db::get_client_id_by_alias(db, auth_request.client_id()).await else {
 		let page = templates::error_page(
 			&tera,
 			language,
@@ -404,9 +1201,9 @@ async fn authorize_page(
 			templates::ErrorPage::InternalServerError,
 		)
 		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::InternalServerError()
+		return HttpResponse::InternalServerError()
 			.content_type("text/html")
-			.body(page));
+			.body(page);
 	};
 	let Some(client_id) = client_id else {
 		let page = templates::error_page(
@@ -416,9 +1213,24 @@ async fn authorize_page(
 			templates::ErrorPage::ClientNotFound,
 		)
 		.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-		return Ok(HttpResponse::NotFound()
+		return HttpResponse::NotFound()
 			.content_type("text/html")
-			.body(page));
+			.body(page);
+	};
+
+	// get the parameters, if they were pushed
+	let params = match get_authorization_parameters(&auth_request, db, client_id).await {
+		Ok(params) => params,
+		Err(e) => {
+			let e = e
+				.expected()
+				.unwrap_or(templates::ErrorPage::InternalServerError);
+			let page = error_page(&tera, &translations, e)
+				.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
+			return HttpResponse::BadRequest()
+				.content_type("text/html")
+				.body(page);
+		}
 	};
 
 	// verify redirect uri
@@ -430,70 +1242,185 @@ async fn authorize_page(
 				.unwrap_or(templates::ErrorPage::InternalServerError);
 			let page = error_page(&tera, &translations, e)
 				.unwrap_or_else(|_| String::from(REALLY_BAD_ERROR_PAGE));
-			return Ok(HttpResponse::BadRequest()
+			return HttpResponse::BadRequest()
 				.content_type("text/html")
-				.body(page));
+				.body(page);
 		}
 	};
 
-	let state = &params.state;
-	let internal_server_error =
-		AuthorizeError::internal_server_error(redirect_uri.clone(), state.clone());
+	// errors after this point are sent back to the client
+	let response_mode = params.response_mode();
+	let authorization_details = async {
+		let state = &params.state;
+		let internal_server_error =
+			AuthorizeError::internal_server_error(redirect_uri.clone(), state.clone());
 
-	// verify scope
-	let _ = match get_scope(&params.scope, db, client_id, &redirect_uri, &params.state).await {
-		Ok(scope) => scope,
-		Err(e) => {
+		// verify scope
+		let scope =
+			match get_scope(&params.scope, db, client_id, &redirect_uri, &params.state).await {
+				Ok(scope) => scope,
+				Err(e) => {
+					let e = e.expected().unwrap_or(internal_server_error);
+					return Err(e);
+				}
+			};
+
+		// verify response type
+		if params.response_type == ResponseType::Unsupported {
+			return Err(AuthorizeError::unsupported_response_type(
+				redirect_uri.clone(),
+				params.state.clone(),
+			));
+		}
+		if let Some(e) = response_mode_error(&params, response_mode, &redirect_uri) {
+			return Err(e);
+		}
+
+		// verify code challenge
+		if let Err(e) = get_code_challenge(&params, db, client_id, &redirect_uri).await {
 			let e = e.expected().unwrap_or(internal_server_error);
 			return Err(e);
 		}
-	};
 
-	// verify response type
-	if params.response_type == ResponseType::Unsupported {
-		return Err(AuthorizeError::unsupported_response_type(
-			redirect_uri,
-			params.state,
-		));
+		// verify authorization details
+		let authorization_details =
+			match get_authorization_details(&params, db, client_id, &redirect_uri).await {
+				Ok(details) => details,
+				Err(e) => {
+					let e = e.expected().unwrap_or(internal_server_error);
+					return Err(e);
+				}
+			};
+
+		// verify resource
+		if let Err(e) = get_resource_server(&params, db, &redirect_uri).await {
+			let e = e.expected().unwrap_or(internal_server_error);
+			return Err(e);
+		}
+
+		// verify openid parameters
+		if let Some(e) = id_token_request_error(&params, &scope, &redirect_uri) {
+			return Err(e);
+		}
+
+		Ok(authorization_details)
 	}
+	.await;
+	let authorization_details = match authorization_details {
+		Ok(details) => details,
+		Err(e) => {
+			let client_alias = auth_request.client_id();
+			return send_authorize_error(db, &tera, &translations, client_alias, response_mode, e)
+				.await;
+		}
+	};
 
 	// TODO find a better way of doing languages
 	let language = Language::from_str("en").unwrap();
-	let page = templates::login_page(&tera, &params, language, translations).unwrap();
-	Ok(HttpResponse::Ok().content_type("text/html").body(page))
+	let page = templates::login_page(
+		&tera,
+		&auth_request,
+		authorization_details.as_ref(),
+		language,
+		translations,
+	)
+	.unwrap();
+	HttpResponse::Ok().content_type("text/html").body(page)
 }
 
-#[derive(Debug, Clone, Deserialize)]
-#[serde(tag = "grant_type")]
-#[serde(rename_all = "snake_case")]
-enum GrantType {
-	AuthorizationCode {
+/// Declares the grant types that the token endpoint accepts, so that the list
+/// of supported grant types comes from the same names that requests are parsed
+/// with
+macro_rules! grant_types {
+	($($name:literal => $variant:ident { $($fields:tt)* },)*) => {
+		#[derive(Debug, Clone, Deserialize)]
+		#[serde(tag = "grant_type")]
+		pub(super) enum GrantType {
+			$(
+				#[serde(rename = $name)]
+				$variant { $($fields)* },
+			)*
+			#[serde(other)]
+			Unsupported,
+		}
+
+		impl GrantType {
+			/// Every grant type that the token endpoint accepts
+			pub(super) const SUPPORTED: &'static [&'static str] = &[$($name),*];
+		}
+	};
+}
+
+grant_types! {
+	"authorization_code" => AuthorizationCode {
 		code: Box<str>,
 		redirect_uri: Url,
 		#[serde(rename = "client_id")]
 		client_alias: Box<str>,
+		code_verifier: Option<Box<str>>,
 	},
-	Password {
+	"password" => Password {
 		username: Box<str>,
 		password: Box<str>,
 		scope: Option<Box<str>>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
 	},
-	ClientCredentials {
+	"client_credentials" => ClientCredentials {
 		scope: Option<Box<str>>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
 	},
-	RefreshToken {
+	"refresh_token" => RefreshToken {
 		refresh_token: Box<str>,
 		scope: Option<Box<str>>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
+	},
+	"urn:ietf:params:oauth:grant-type:device_code" => DeviceCode {
+		device_code: Box<str>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
+	},
+	"urn:ietf:params:oauth:grant-type:token-exchange" => TokenExchange {
+		subject_token: Box<str>,
+		subject_token_type: Box<str>,
+		actor_token: Option<Box<str>>,
+		actor_token_type: Option<Box<str>>,
+		requested_token_type: Option<Box<str>>,
+		audience: Option<Box<str>>,
+		scope: Option<Box<str>>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
+	},
+	"urn:openid:params:grant-type:ciba" => Ciba {
+		auth_req_id: Box<str>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
+	},
+	"urn:ietf:params:oauth:grant-type:jwt-bearer" => JwtBearer {
+		assertion: Box<str>,
+		scope: Option<Box<str>>,
+		#[serde(rename = "client_id")]
+		client_alias: Option<Box<str>>,
 	},
-	#[serde(other)]
-	Unsupported,
 }
 
+/// The only type of token which can be exchanged, or issued by an exchange
+const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+
 #[derive(Debug, Clone, Deserialize)]
 struct TokenRequest {
 	#[serde(flatten)]
 	grant_type: GrantType,
-	// TODO support optional client credentials in here
+	client_secret: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+	#[serde(default, with = "authorization_details::form")]
+	authorization_details: Option<AuthorizationDetails>,
+	/// The resource server that the access token is meant for, as described in
+	/// RFC 8707
+	resource: Option<Url>,
 }
 
 #[derive(Clone, Serialize)]
@@ -503,6 +1430,12 @@ struct TokenResponse {
 	expires_in: i64,
 	refresh_token: Option<Box<str>>,
 	scope: Box<str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	id_token: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	issued_token_type: Option<&'static str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	authorization_details: Option<AuthorizationDetails>,
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -514,6 +1447,17 @@ enum TokenErrorType {
 	UnauthorizedClient,
 	UnsupportedGrantType,
 	InvalidScope,
+	UnsupportedTokenType,
+	AuthorizationPending,
+	SlowDown,
+	AccessDenied,
+	ExpiredToken,
+	InvalidTarget,
+	UnsupportedResponseType,
+	InvalidDpopProof,
+	UnknownUserId,
+	InvalidBindingMessage,
+	InvalidAuthorizationDetails,
 }
 
 #[derive(Debug, Clone, Error, Serialize)]
@@ -552,12 +1496,40 @@ impl TokenError {
 		}
 	}
 
+	fn missing_code_verifier() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: Box::from(
+				"A code verifier is required because the authorization request had a code challenge",
+			),
+		}
+	}
+
+	fn unexpected_code_verifier() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: Box::from(
+				"A code verifier was given, but the authorization request had no code challenge",
+			),
+		}
+	}
+
+	fn incorrect_code_verifier() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: Box::from("The code verifier does not match the code challenge"),
+		}
+	}
+
 	fn no_authorization() -> Self {
 		Self {
 			status_code: StatusCode::UNAUTHORIZED,
 			error: TokenErrorType::InvalidClient,
 			error_description: Box::from(
-				"Client credentials must be provided in the HTTP Authorization header",
+				"Client credentials must be provided in the HTTP Authorization header or the request body",
 			),
 		}
 	}
@@ -595,6 +1567,79 @@ impl TokenError {
 		}
 	}
 
+	fn multiple_auth_methods() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: Box::from(
+				"The client must not use more than one authentication method in a request",
+			),
+		}
+	}
+
+	fn wrong_auth_method() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: Box::from(
+				"The client must authenticate with the method that it was registered with",
+			),
+		}
+	}
+
+	fn unsupported_client_assertion_type() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: Box::from(
+				"The only supported client assertion type is urn:ietf:params:oauth:client-assertion-type:jwt-bearer",
+			),
+		}
+	}
+
+	fn no_client_assertion() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: "The client must authenticate with a client assertion".into(),
+		}
+	}
+
+	fn invalid_client_assertion(err: VerifyClientAssertionError) -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: err.to_string().into_boxed_str(),
+		}
+	}
+
+	fn no_client_certificate() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: "The client must present its certificate during the TLS handshake"
+				.into(),
+		}
+	}
+
+	fn incorrect_client_certificate() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: TokenErrorType::InvalidClient,
+			error_description: "The client certificate doesn't match the registered certificate"
+				.into(),
+		}
+	}
+
+	fn wrong_client_certificate() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: "The refresh token is bound to a different client certificate"
+				.into(),
+		}
+	}
+
 	fn client_not_confidential(alias: &str) -> Self {
 		Self {
 			status_code: StatusCode::BAD_REQUEST,
@@ -632,6 +1677,22 @@ impl TokenError {
 		}
 	}
 
+	fn bad_assertion(err: VerifyAssertionGrantError) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: err.to_string().into_boxed_str(),
+		}
+	}
+
+	fn unknown_assertion_subject() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: "The subject of the assertion is not a user".into(),
+		}
+	}
+
 	fn untrusted_client() -> Self {
 		Self {
 			status_code: StatusCode::UNAUTHORIZED,
@@ -640,20 +1701,268 @@ impl TokenError {
 		}
 	}
 
-	fn incorrect_user_credentials() -> Self {
+	fn unsupported_token_type() -> Self {
 		Self {
 			status_code: StatusCode::BAD_REQUEST,
-			error: TokenErrorType::InvalidRequest,
-			error_description: "The given credentials are incorrect".into(),
+			error: TokenErrorType::UnsupportedTokenType,
+			error_description: "Only access tokens and refresh tokens may be revoked".into(),
 		}
 	}
-}
-
-impl ResponseError for TokenError {
-	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
-		let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
 
-		let mut builder = HttpResponseBuilder::new(self.status_code);
+	fn token_for_other_client() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::UnauthorizedClient,
+			error_description: "The given token was issued to a different client".into(),
+		}
+	}
+
+	fn incorrect_user_credentials() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "The given credentials are incorrect".into(),
+		}
+	}
+
+	fn bad_device_code() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: "The device code is invalid".into(),
+		}
+	}
+
+	fn authorization_pending() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::AuthorizationPending,
+			error_description: "The user hasn't approved the request yet".into(),
+		}
+	}
+
+	fn slow_down() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::SlowDown,
+			error_description: "Polling too quickly. Please increase the interval by five seconds."
+				.into(),
+		}
+	}
+
+	fn access_denied() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::AccessDenied,
+			error_description: "The user denied the request".into(),
+		}
+	}
+
+	fn unsupported_exchange_token_type() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "Only access tokens may be exchanged".into(),
+		}
+	}
+
+	fn missing_actor_token_type() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "An actor_token_type is required when an actor token is given"
+				.into(),
+		}
+	}
+
+	fn bad_subject_token(err: VerifyJwtError) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: format!("Invalid subject token: {err}").into_boxed_str(),
+		}
+	}
+
+	fn bad_actor_token(err: VerifyJwtError) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: format!("Invalid actor token: {err}").into_boxed_str(),
+		}
+	}
+
+	fn exchange_not_allowed() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::UnauthorizedClient,
+			error_description: "This client may not exchange tokens issued to that client".into(),
+		}
+	}
+
+	fn unknown_audience(audience: &str) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidTarget,
+			error_description: format!("No client with the client id: {audience} was found")
+				.into_boxed_str(),
+		}
+	}
+
+	fn unknown_resource(resource: &Url) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidTarget,
+			error_description: format!("No resource server with the URI: {resource} was found")
+				.into_boxed_str(),
+		}
+	}
+
+	fn resource_not_granted() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidTarget,
+			error_description: Box::from(
+				"The grant was made for a different resource than the one requested",
+			),
+		}
+	}
+
+	fn unknown_redirect_uri() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "The redirect URI is missing or not registered for this client"
+				.into(),
+		}
+	}
+
+	fn unsupported_response_type() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::UnsupportedResponseType,
+			error_description: "The given response type is not supported".into(),
+		}
+	}
+
+	fn nested_request_uri() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "A pushed authorization request cannot include a request URI".into(),
+		}
+	}
+
+	fn invalid_dpop_proof(err: VerifyProofError) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidDpopProof,
+			error_description: err.to_string().into_boxed_str(),
+		}
+	}
+
+	fn wrong_dpop_key() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidDpopProof,
+			error_description: "The refresh token is bound to a different key".into(),
+		}
+	}
+
+	fn expired_device_code() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::ExpiredToken,
+			error_description: "The device code has expired".into(),
+		}
+	}
+
+	fn invalid_authorization_details() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidAuthorizationDetails,
+			error_description: Box::from(
+				"The client is not allowed to request this type of authorization details",
+			),
+		}
+	}
+
+	fn excessive_authorization_details() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidAuthorizationDetails,
+			error_description: Box::from(
+				"The given authorization details exceed what was granted to the client",
+			),
+		}
+	}
+
+	fn no_login_hint() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: "The user must be identified with the login_hint parameter".into(),
+		}
+	}
+
+	fn unknown_user_id() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::UnknownUserId,
+			error_description: "The login hint does not identify a user".into(),
+		}
+	}
+
+	fn invalid_binding_message() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidBindingMessage,
+			error_description: format!(
+				"The binding message must not be longer than {MAX_BINDING_MESSAGE_LENGTH} characters"
+			)
+			.into_boxed_str(),
+		}
+	}
+
+	fn no_client_notification_token() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidRequest,
+			error_description: Box::from(
+				"Clients using the ping mode must send a client_notification_token",
+			),
+		}
+	}
+
+	fn no_openid_scope() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidScope,
+			error_description: "Backchannel authentication requires the openid scope".into(),
+		}
+	}
+
+	fn bad_auth_req_id() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::InvalidGrant,
+			error_description: "The authentication request ID is invalid".into(),
+		}
+	}
+
+	fn expired_auth_req_id() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: TokenErrorType::ExpiredToken,
+			error_description: "The authentication request has expired".into(),
+		}
+	}
+}
+
+impl ResponseError for TokenError {
+	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+		let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+		let mut builder = HttpResponseBuilder::new(self.status_code);
 
 		if self.status_code.as_u16() == 401 {
 			builder.insert_header((header::WWW_AUTHENTICATE, "Basic charset=\"UTF-8\""));
@@ -666,12 +1975,245 @@ impl ResponseError for TokenError {
 	}
 }
 
+/// The ways a client can authenticate to the token and revocation endpoints.
+/// Public clients use `none`.
+pub(super) const TOKEN_ENDPOINT_AUTH_METHODS: &[&str] = &[
+	"client_secret_basic",
+	"client_secret_post",
+	"tls_client_auth",
+	"self_signed_tls_client_auth",
+	"client_secret_jwt",
+	"private_key_jwt",
+	"none",
+];
+
+/// The ways a client can authenticate to the introspection endpoint. Only
+/// clients with credentials may introspect tokens.
+const INTROSPECTION_ENDPOINT_AUTH_METHODS: &[&str] = &[
+	"client_secret_basic",
+	"client_secret_post",
+	"client_secret_jwt",
+	"private_key_jwt",
+];
+
+/// The algorithms which clients can use for `private_key_jwt` and
+/// `client_secret_jwt` assertions
+const TOKEN_ENDPOINT_AUTH_SIGNING_ALGS: &[&str] = &["RS256", "ES256", "EdDSA", "HS256"];
+
+/// A JWT which authenticates the client, as described in RFC 7523
+#[derive(Debug, Clone, Deserialize)]
+struct ClientAssertion {
+	client_assertion_type: Box<str>,
+	client_assertion: Box<str>,
+}
+
+/// Authenticate a client, using the method that it was registered with.
+/// Clients with a secret send it in the Authorization header or the request
+/// body, clients with a certificate must present it during the TLS handshake,
+/// and clients using a JWT must send it as a client assertion. Clients which
+/// don't use the Authorization header identify themselves with the
+/// `client_id` parameter instead. Only one method may be used in a request.
+async fn authenticate_client(
+	db: &MySqlPool,
+	authorization: Option<&authorization::BasicAuthorization>,
+	certificate: Option<&ClientCertificate>,
+	assertion: Option<&ClientAssertion>,
+	client_secret: Option<&str>,
+	client_alias: Option<&str>,
+	ip: IpAddr,
+) -> Result<Uuid, TokenError> {
+	let methods_used = [
+		authorization.is_some(),
+		assertion.is_some(),
+		client_secret.is_some(),
+	];
+	if methods_used.into_iter().filter(|used| *used).count() > 1 {
+		yeet!(TokenError::multiple_auth_methods());
+	}
+
+	let assertion_subject = match assertion {
+		Some(assertion) => {
+			if &*assertion.client_assertion_type != client_assertion::CLIENT_ASSERTION_TYPE {
+				yeet!(TokenError::unsupported_client_assertion_type());
+			}
+			let Some(subject) = client_assertion::assertion_subject(&assertion.client_assertion)
+			else {
+				yeet!(TokenError::invalid_client_assertion(
+					VerifyClientAssertionError::ParseJwtError(JwsError::Malformed)
+				));
+			};
+			Some(subject)
+		}
+		None => None,
+	};
+	let client_alias = match (assertion_subject.as_deref(), client_alias) {
+		(Some(subject), Some(client_alias)) if subject != client_alias => {
+			yeet!(TokenError::mismatch_client_id())
+		}
+		(Some(subject), _) => Some(subject),
+		(None, client_alias) => client_alias,
+	};
+	let alias = match (authorization, client_alias) {
+		(Some(authorization), Some(client_alias)) if authorization.username() != client_alias => {
+			yeet!(TokenError::mismatch_client_id())
+		}
+		(Some(authorization), _) => authorization.username(),
+		(None, Some(client_alias)) => client_alias,
+		(None, None) => yeet!(TokenError::no_authorization()),
+	};
+	let Some(client_id) = db::get_client_id_by_alias(db, alias).await.unwrap() else {
+		yeet!(TokenError::client_not_found(alias));
+	};
+
+	// brute force detection
+	if brute_force_detection::brute_force_detected(db, &client_id.to_string(), ip)
+		.await
+		.unwrap()
+	{
+		yeet!(TokenError::too_many_requests());
+	}
+
+	let auth_method = db::get_client_auth_method(db, client_id)
+		.await
+		.unwrap()
+		.unwrap();
+
+	// clients may only use the method that they registered, although the
+	// request body may be used instead of HTTP Basic, as in RFC 6749 2.3.1
+	let uses_secret = matches!(
+		auth_method,
+		AuthMethod::ClientSecretBasic | AuthMethod::ClientSecretPost
+	);
+	let uses_assertion = matches!(
+		auth_method,
+		AuthMethod::ClientSecretJwt | AuthMethod::PrivateKeyJwt
+	);
+	if (authorization.is_some() && auth_method != AuthMethod::ClientSecretBasic)
+		|| (client_secret.is_some() && !uses_secret)
+		|| (assertion.is_some() && !uses_assertion)
+	{
+		yeet!(TokenError::wrong_auth_method());
+	}
+
+	let authenticated = match auth_method {
+		AuthMethod::ClientSecretBasic | AuthMethod::ClientSecretPost => {
+			let secret = authorization.map(|a| a.password()).or(client_secret);
+			let Some(secret) = secret else {
+				yeet!(TokenError::no_authorization());
+			};
+			let Some(hash) = db::get_client_secret(db, client_id).await.unwrap() else {
+				yeet!(TokenError::incorrect_client_secret());
+			};
+			hash.check_password(secret).unwrap()
+		}
+		AuthMethod::TlsClientAuth => {
+			let Some(certificate) = certificate else {
+				yeet!(TokenError::no_client_certificate());
+			};
+			let san_dns = db::get_client_tls_client_auth_san_dns(db, client_id)
+				.await
+				.unwrap()
+				.flatten();
+			san_dns.is_some_and(|san_dns| certificate.is_issued_for(&san_dns).unwrap())
+		}
+		AuthMethod::SelfSignedTlsClientAuth => {
+			let Some(certificate) = certificate else {
+				yeet!(TokenError::no_client_certificate());
+			};
+			let jwks = db::get_client_jwks(db, client_id).await.unwrap().flatten();
+			jwks.is_some_and(|jwks| jwks.contains_certificate(certificate.der()))
+		}
+		AuthMethod::ClientSecretJwt | AuthMethod::PrivateKeyJwt => {
+			let Some(assertion) = assertion else {
+				yeet!(TokenError::no_client_assertion());
+			};
+			let self_id = config::get_config().unwrap().url;
+			match client_assertion::verify_client_assertion(
+				db,
+				&assertion.client_assertion,
+				&self_id,
+				client_id,
+				alias,
+				auth_method,
+			)
+			.await
+			{
+				Ok(()) => true,
+				Err(e) => {
+					db::add_failed_login_attempt(db, &client_id.to_string(), ip)
+						.await
+						.unwrap();
+					yeet!(TokenError::invalid_client_assertion(e.unwrap()));
+				}
+			}
+		}
+		// public clients don't have any credentials to check
+		AuthMethod::None => true,
+	};
+
+	if !authenticated {
+		db::add_failed_login_attempt(db, &client_id.to_string(), ip)
+			.await
+			.unwrap();
+		match auth_method {
+			AuthMethod::TlsClientAuth | AuthMethod::SelfSignedTlsClientAuth => {
+				yeet!(TokenError::incorrect_client_certificate())
+			}
+			_ => yeet!(TokenError::incorrect_client_secret()),
+		}
+	}
+
+	Ok(client_id)
+}
+
+/// The client can ask for some of the authorization details that were granted
+/// to it, but not for any new ones. If nothing is asked for, then every granted
+/// detail is used.
+fn narrow_authorization_details<'a>(
+	requested: Option<&'a AuthorizationDetails>,
+	granted: Option<&'a AuthorizationDetails>,
+) -> Result<Option<&'a AuthorizationDetails>, TokenError> {
+	let Some(requested) = requested else {
+		return Ok(granted);
+	};
+
+	if !granted.is_some_and(|granted| requested.is_subset_of(granted)) {
+		yeet!(TokenError::excessive_authorization_details());
+	}
+
+	Ok(Some(requested))
+}
+
+/// Tokens from a grant which was made for a resource server can only be used
+/// there. If the client doesn't ask for a resource, then the one from the grant
+/// is used.
+async fn granted_resource_server(
+	db: &MySqlPool,
+	requested: Option<ResourceServer>,
+	granted: Option<&Url>,
+) -> Result<Option<ResourceServer>, TokenError> {
+	let Some(granted) = granted else {
+		return Ok(requested);
+	};
+
+	match requested {
+		Some(requested) if requested.uri() != granted => yeet!(TokenError::resource_not_granted()),
+		Some(requested) => Ok(Some(requested)),
+		None => match db::get_resource_server_by_uri(db, granted).await.unwrap() {
+			Some(resource_server) => Ok(Some(resource_server)),
+			None => yeet!(TokenError::unknown_resource(granted)),
+		},
+	}
+}
+
 #[post("/token")]
 async fn token(
 	db: web::Data<MySqlPool>,
 	req: web::Bytes,
 	http_req: HttpRequest,
 	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+	dpop: Option<web::Header<authorization::DpopProof>>,
+	trusted_issuers: web::Data<TrustedIssuers>,
 ) -> HttpResponse {
 	let db = db.get_ref();
 	let request = serde_urlencoded::from_bytes::<TokenRequest>(&req);
@@ -683,14 +2225,47 @@ async fn token(
 
 	let self_id = config.url;
 	let duration = Duration::hours(1);
-	let token_type = Box::from("bearer");
 	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
 
-	match request.grant_type {
-		GrantType::AuthorizationCode {
+	// with a DPoP proof, the tokens are bound to the client's key
+	let jkt = match dpop.as_deref() {
+		Some(proof) => {
+			let token_uri = self_id.join("oauth/token").unwrap();
+			match dpop::verify_proof(db, proof.proof(), "POST", &token_uri, None).await {
+				Ok(jkt) => Some(jkt),
+				Err(e) => return TokenError::invalid_dpop_proof(e.unwrap()).error_response(),
+			}
+		}
+		// the header was sent, but couldn't be parsed
+		None if http_req.headers().contains_key("dpop") => {
+			return TokenError::invalid_request().error_response()
+		}
+		None => None,
+	};
+	let token_type = Box::from(if jkt.is_some() { "DPoP" } else { "bearer" });
+
+	let client_assertion = request.client_assertion;
+	let client_secret = request.client_secret;
+	let authorization_details = request.authorization_details;
+
+	// the access token can be restricted to a single resource server
+	let resource_server = match &request.resource {
+		Some(resource) => match db::get_resource_server_by_uri(db, resource).await.unwrap() {
+			Some(resource_server) => Some(resource_server),
+			None => return TokenError::unknown_resource(resource).error_response(),
+		},
+		None => None,
+	};
+
+	// with a client certificate, the tokens are bound to the certificate
+	let certificate = http_req.conn_data::<ClientCertificate>();
+	let x5t_s256 = certificate.map(ClientCertificate::thumbprint);
+	match request.grant_type {
+		GrantType::AuthorizationCode {
 			code,
 			redirect_uri,
 			client_alias,
+			code_verifier,
 		} => {
 			let Some(client_id) = db::get_client_id_by_alias(db, &client_alias).await.unwrap() else {
 				return TokenError::client_not_found(&client_alias).error_response();
@@ -707,50 +2282,197 @@ async fn token(
 				};
 
 			// verify client, if the client has credentials
-			if let Some(hash) = db::get_client_secret(db, client_id).await.unwrap() {
-				let Some(authorization) = authorization else {
-					return TokenError::no_authorization().error_response();
-				};
+			if let Err(e) = authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				Some(&client_alias),
+				ip,
+			)
+			.await
+			{
+				return e.error_response();
+			}
 
-				// brute force detection
-				if brute_force_detection::brute_force_detected(db, &client_id.to_string(), ip)
-					.await
-					.unwrap()
-				{
-					return TokenError::too_many_requests().error_response();
+			// verify code verifier
+			match (claims.code_challenge(), code_verifier) {
+				(Some(challenge), Some(verifier)) => {
+					if !challenge.verify(&verifier) {
+						return TokenError::incorrect_code_verifier().error_response();
+					}
 				}
+				(Some(_), None) => return TokenError::missing_code_verifier().error_response(),
+				(None, Some(_)) => return TokenError::unexpected_code_verifier().error_response(),
+				(None, None) => (),
+			}
 
-				if authorization.username() != client_alias.deref() {
-					return TokenError::mismatch_client_id().error_response();
-				}
-				if !hash.check_password(authorization.password()).unwrap() {
-					db::add_failed_login_attempt(db, &client_id.to_string(), ip)
-						.await
-						.unwrap();
-					return TokenError::incorrect_client_secret().error_response();
-				}
-			} else if authorization.is_some() {
-				return TokenError::incorrect_client_secret().error_response();
+			// the client can narrow down the authorization details that it was given
+			let authorization_details = match narrow_authorization_details(
+				authorization_details.as_ref(),
+				claims.authorization_details(),
+			) {
+				Ok(details) => details.cloned(),
+				Err(e) => return e.error_response(),
+			};
+
+			// the resource from the authorization request is used by default
+			let resource_server =
+				match granted_resource_server(db, resource_server, claims.resource()).await {
+					Ok(resource_server) => resource_server,
+					Err(e) => return e.error_response(),
+				};
+
+			// the request is valid, so the code can be used up
+			if let Err(e) = jwt::consume_auth_code(db, &claims).await {
+				let e = e.unwrap();
+				return TokenError::bad_auth_code(e).error_response();
 			}
 
 			let access_token = jwt::Claims::access_token(
 				db,
-				Some(claims.id()),
-				self_id,
+				Some(&claims),
+				self_id.clone(),
 				client_id,
 				claims.subject(),
 				duration,
 				claims.scopes(),
 			)
 			.await
-			.unwrap();
+			.unwrap()
+			.with_jkt(jkt.as_deref())
+			.with_x5t_s256(x5t_s256.as_deref())
+			.with_authorization_details(authorization_details.as_ref());
 
 			let expires_in = access_token.expires_in();
+			let refresh_token = jwt::Claims::refresh_token(db, &access_token)
+				.await
+				.unwrap()
+				.with_authorization_details(claims.authorization_details());
+			let access_token = access_token.restrict_to(resource_server.as_ref());
+			let scope: Box<str> = access_token.scopes().into();
+
+			let access_token = access_token.to_jwt(db).await.unwrap();
+			let refresh_token = Some(refresh_token.to_jwt(db).await.unwrap());
+
+			let id_token = if scopes::is_subset_of(scopes::OPENID, claims.scopes()) {
+				let id_token = IdTokenClaims::new(
+					self_id,
+					&client_alias,
+					claims.subject(),
+					claims.auth_time(),
+					claims.nonce(),
+				)
+				.to_jwt(db, Some(&access_token), None)
+				.await
+				.unwrap();
+				Some(id_token)
+			} else {
+				None
+			};
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token,
+				scope,
+				id_token,
+				issued_token_type: None,
+				authorization_details,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::Password {
+			username,
+			password,
+			scope,
+			client_alias,
+		} => {
+			// verify client
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			let trusted = db::is_client_trusted(db, client_id).await.unwrap().unwrap();
+			if !trusted {
+				return TokenError::untrusted_client().error_response();
+			}
+
+			// brute force detection
+			if brute_force_detection::brute_force_detected(db, &username, ip)
+				.await
+				.unwrap()
+			{
+				return TokenError::too_many_requests().error_response();
+			}
+
+			// authenticate user
+			let Some(user_id) = authenticate_user(db, &username, &password).await.unwrap() else {
+				db::add_failed_login_attempt(db, &username, ip).await.unwrap();
+				return TokenError::incorrect_user_credentials().error_response();
+			};
+
+			// verify scope
+			let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+				.await
+				.unwrap()
+				.unwrap();
+			let scope = if let Some(scope) = &scope {
+				scope.clone()
+			} else {
+				let default_scopes = db::get_client_default_scopes(db, client_id)
+					.await
+					.unwrap()
+					.unwrap();
+				let Some(scope) = default_scopes else {
+					return TokenError::no_scope().error_response();
+				};
+				scope
+			};
+			if !scopes::is_subset_of(&scope, &allowed_scopes) {
+				return TokenError::excessive_scope().error_response();
+			}
+
+			// verify authorization details
+			if let Some(details) = &authorization_details {
+				if !authorization_details::types_are_allowed(db, client_id, details)
+					.await
+					.unwrap()
+				{
+					return TokenError::invalid_authorization_details().error_response();
+				}
+			}
+
+			let access_token =
+				jwt::Claims::access_token(db, None, self_id, client_id, user_id, duration, &scope)
+					.await
+					.unwrap()
+					.with_jkt(jkt.as_deref())
+					.with_x5t_s256(x5t_s256.as_deref())
+					.with_authorization_details(authorization_details.as_ref());
 			let refresh_token = jwt::Claims::refresh_token(db, &access_token).await.unwrap();
-			let scope = access_token.scopes().into();
+			let access_token = access_token.restrict_to(resource_server.as_ref());
 
-			let access_token = access_token.to_jwt().unwrap();
-			let refresh_token = Some(refresh_token.to_jwt().unwrap());
+			let expires_in = access_token.expires_in();
+			let scope = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+			let refresh_token = Some(refresh_token.to_jwt(db).await.unwrap());
 
 			let response = TokenResponse {
 				access_token,
@@ -758,258 +2480,1690 @@ async fn token(
 				expires_in,
 				refresh_token,
 				scope,
+				id_token: None,
+				issued_token_type: None,
+				authorization_details,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::ClientCredentials {
+			scope,
+			client_alias,
+		} => {
+			// verify client
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			let ty = db::get_client_type(db, client_id).await.unwrap().unwrap();
+			if ty != ClientType::Confidential {
+				let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+				return TokenError::client_not_confidential(&client_alias).error_response();
+			}
+
+			// verify scope
+			let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+				.await
+				.unwrap()
+				.unwrap();
+			let scope = if let Some(scope) = &scope {
+				scope.clone()
+			} else {
+				let default_scopes = db::get_client_default_scopes(db, client_id)
+					.await
+					.unwrap()
+					.unwrap();
+				let Some(scope) = default_scopes else {
+					return TokenError::no_scope().error_response();
+				};
+				scope
+			};
+			if !scopes::is_subset_of(&scope, &allowed_scopes) {
+				return TokenError::excessive_scope().error_response();
+			}
+
+			// verify authorization details
+			if let Some(details) = &authorization_details {
+				if !authorization_details::types_are_allowed(db, client_id, details)
+					.await
+					.unwrap()
+				{
+					return TokenError::invalid_authorization_details().error_response();
+				}
+			}
+
+			let access_token = jwt::Claims::access_token(
+				db, None, self_id, client_id, client_id, duration, &scope,
+			)
+			.await
+			.unwrap()
+			.with_jkt(jkt.as_deref())
+			.with_x5t_s256(x5t_s256.as_deref())
+			.with_authorization_details(authorization_details.as_ref())
+			.restrict_to(resource_server.as_ref());
+
+			let expires_in = access_token.expires_in();
+			let scope = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token: None,
+				scope,
+				id_token: None,
+				issued_token_type: None,
+				authorization_details,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::RefreshToken {
+			refresh_token,
+			scope,
+			client_alias,
+		} => {
+			let claims = match jwt::verify_refresh_token(db, &refresh_token, &self_id).await {
+				Ok(claims) => claims,
+				Err(e) => {
+					let e = e.unwrap();
+					return TokenError::bad_refresh_token(e).error_response();
+				}
+			};
+
+			// a refresh token bound to a key can only be used with that key
+			if claims
+				.jkt()
+				.is_some_and(|bound| jkt.as_deref() != Some(bound))
+			{
+				return TokenError::wrong_dpop_key().error_response();
+			}
+			if claims
+				.x5t_s256()
+				.is_some_and(|bound| x5t_s256.as_deref() != Some(bound))
+			{
+				return TokenError::wrong_client_certificate().error_response();
+			}
+
+			// authenticate client. If the client doesn't identify itself, then it's
+			// assumed to be the client that the refresh token was issued to.
+			let client_id = claims.client_id();
+			let client_alias = match (&client_alias, &authorization) {
+				(None, None) => Some(db::get_client_alias(db, client_id).await.unwrap().unwrap()),
+				_ => client_alias,
+			};
+			match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) if id == client_id => (),
+				Ok(_) => return TokenError::token_for_other_client().error_response(),
+				Err(e) => return e.error_response(),
+			}
+
+			let scope = if let Some(scope) = scope {
+				if !scopes::is_subset_of(&scope, claims.scopes()) {
+					return TokenError::excessive_scope().error_response();
+				}
+
+				scope
+			} else {
+				claims.scopes().into()
+			};
+
+			let authorization_details = match narrow_authorization_details(
+				authorization_details.as_ref(),
+				claims.authorization_details(),
+			) {
+				Ok(details) => details.cloned(),
+				Err(e) => return e.error_response(),
+			};
+
+			let resource_server =
+				match granted_resource_server(db, resource_server, claims.resource()).await {
+					Ok(resource_server) => resource_server,
+					Err(e) => return e.error_response(),
+				};
+
+			// the request is valid, so the refresh token can be replaced
+			if let Err(e) = jwt::consume_refresh_token(db, &claims).await {
+				let e = e.unwrap();
+				return TokenError::bad_refresh_token(e).error_response();
+			}
+
+			let exp_time = Duration::hours(1);
+			let access_token = jwt::Claims::refreshed_access_token(db, &claims, exp_time, &scope)
+				.await
+				.unwrap()
+				.with_jkt(jkt.as_deref())
+				.with_x5t_s256(x5t_s256.as_deref())
+				.with_authorization_details(authorization_details.as_ref())
+				.restrict_to(resource_server.as_ref());
+			let refresh_token = jwt::Claims::refresh_token(db, &claims).await.unwrap();
+
+			// only the scopes which the resource server owns are kept
+			let token_scope: Box<str> = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+			let refresh_token = Some(refresh_token.to_jwt(db).await.unwrap());
+			let expires_in = exp_time.num_seconds();
+
+			let id_token = if scopes::is_subset_of(scopes::OPENID, &scope) {
+				let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+				let id_token = IdTokenClaims::new(
+					self_id,
+					&client_alias,
+					claims.subject(),
+					claims.auth_time(),
+					None,
+				)
+				.to_jwt(db, Some(&access_token), None)
+				.await
+				.unwrap();
+				Some(id_token)
+			} else {
+				None
+			};
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token,
+				scope: token_scope,
+				id_token,
+				issued_token_type: None,
+				authorization_details,
 			};
 			HttpResponse::Ok()
 				.insert_header(cache_control)
 				.insert_header((header::PRAGMA, "no-cache"))
 				.json(response)
 		}
-		GrantType::Password {
-			username,
-			password,
-			scope,
-		} => {
-			let Some(authorization) = authorization else {
-				return TokenError::no_authorization().error_response();
-			};
-			let client_alias = authorization.username();
-			let Some(client_id) = db::get_client_id_by_alias(db, client_alias).await.unwrap() else {
-				return TokenError::client_not_found(client_alias).error_response();
-			};
+		GrantType::DeviceCode {
+			device_code,
+			client_alias,
+		} => {
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			let Ok(device_code) = Uuid::from_str(&device_code) else {
+				return TokenError::bad_device_code().error_response();
+			};
+			let Some(device_code) = db::get_device_code(db, device_code).await.unwrap() else {
+				return TokenError::bad_device_code().error_response();
+			};
+			if device_code.client_id != client_id {
+				return TokenError::bad_device_code().error_response();
+			}
+			if device_code.is_expired() {
+				db::delete_device_code(db, device_code.id).await.unwrap();
+				return TokenError::expired_device_code().error_response();
+			}
+
+			// the client has to wait at least the interval between each poll
+			let now = Utc::now();
+			if device_code.polled_too_soon(now) {
+				let interval = device_code.interval + device_code::SLOW_DOWN_INCREMENT;
+				db::update_device_code_poll(db, device_code.id, now, interval)
+					.await
+					.unwrap();
+				return TokenError::slow_down().error_response();
+			}
+			db::update_device_code_poll(db, device_code.id, now, device_code.interval)
+				.await
+				.unwrap();
+
+			let user_id = match device_code.status {
+				DeviceCodeStatus::Pending => {
+					return TokenError::authorization_pending().error_response()
+				}
+				DeviceCodeStatus::Denied => {
+					db::delete_device_code(db, device_code.id).await.unwrap();
+					return TokenError::access_denied().error_response();
+				}
+				DeviceCodeStatus::Approved => device_code.user_id.unwrap(),
+			};
+
+			// the device code can only be used once
+			if !db::delete_device_code(db, device_code.id).await.unwrap() {
+				return TokenError::bad_device_code().error_response();
+			}
+
+			let access_token = jwt::Claims::access_token(
+				db,
+				None,
+				self_id.clone(),
+				client_id,
+				user_id,
+				duration,
+				&device_code.scope,
+			)
+			.await
+			.unwrap()
+			.with_jkt(jkt.as_deref())
+			.with_x5t_s256(x5t_s256.as_deref());
+
+			// approving the device code is how the user consents
+			let trusted = db::is_client_trusted(db, client_id).await.unwrap().unwrap();
+			if !trusted {
+				consent::grant(db, user_id, client_id, &device_code.scope)
+					.await
+					.unwrap();
+				db::add_consent_token(db, user_id, client_id, access_token.grant_id())
+					.await
+					.unwrap();
+			}
+
+			let refresh_token = jwt::Claims::refresh_token(db, &access_token).await.unwrap();
+			let access_token = access_token.restrict_to(resource_server.as_ref());
+
+			let expires_in = access_token.expires_in();
+			let scope: Box<str> = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+			let refresh_token = Some(refresh_token.to_jwt(db).await.unwrap());
+
+			let id_token = if scopes::is_subset_of(scopes::OPENID, &device_code.scope) {
+				let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+				let id_token = IdTokenClaims::new(self_id, &client_alias, user_id, None, None)
+					.to_jwt(db, Some(&access_token), None)
+					.await
+					.unwrap();
+				Some(id_token)
+			} else {
+				None
+			};
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token,
+				scope,
+				id_token,
+				issued_token_type: None,
+				authorization_details: None,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::TokenExchange {
+			subject_token,
+			subject_token_type,
+			actor_token,
+			actor_token_type,
+			requested_token_type,
+			audience,
+			scope,
+			client_alias,
+		} => {
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			let ty = db::get_client_type(db, client_id).await.unwrap().unwrap();
+			if ty != ClientType::Confidential {
+				let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+				return TokenError::client_not_confidential(&client_alias).error_response();
+			}
+
+			// verify token types
+			if &*subject_token_type != ACCESS_TOKEN_TYPE
+				|| requested_token_type.is_some_and(|ty| &*ty != ACCESS_TOKEN_TYPE)
+			{
+				return TokenError::unsupported_exchange_token_type().error_response();
+			}
+			match (&actor_token, &actor_token_type) {
+				(Some(_), None) => return TokenError::missing_actor_token_type().error_response(),
+				(_, Some(ty)) if &**ty != ACCESS_TOKEN_TYPE => {
+					return TokenError::unsupported_exchange_token_type().error_response()
+				}
+				_ => (),
+			}
+
+			// validate subject token
+			let subject = match jwt::verify_access_token(db, &subject_token, &self_id, None).await {
+				Ok(claims) => claims,
+				Err(err) => {
+					let err = err.unwrap();
+					return TokenError::bad_subject_token(err).error_response();
+				}
+			};
+
+			// a token bound to a key can only be exchanged with that key
+			if subject
+				.jkt()
+				.is_some_and(|bound| jkt.as_deref() != Some(bound))
+			{
+				return TokenError::wrong_dpop_key().error_response();
+			}
+			if subject
+				.x5t_s256()
+				.is_some_and(|bound| x5t_s256.as_deref() != Some(bound))
+			{
+				return TokenError::wrong_client_certificate().error_response();
+			}
+
+			// check the exchange policy
+			if subject.client_id() != client_id
+				&& !db::client_may_exchange_tokens_from(db, client_id, subject.client_id())
+					.await
+					.unwrap()
+			{
+				return TokenError::exchange_not_allowed().error_response();
+			}
+
+			// the actor token has to belong to the client doing the exchange
+			let actor = if let Some(actor_token) = &actor_token {
+				match jwt::verify_access_token(db, actor_token, &self_id, Some(client_id)).await {
+					Ok(claims) => claims.subject(),
+					Err(err) => {
+						let err = err.unwrap();
+						return TokenError::bad_actor_token(err).error_response();
+					}
+				}
+			} else {
+				client_id
+			};
+
+			// the new token can't have more access than the subject token
+			let scope = if let Some(scope) = scope {
+				if !scopes::is_subset_of(&scope, subject.scopes()) {
+					return TokenError::excessive_scope().error_response();
+				}
+
+				scope
+			} else {
+				subject.scopes().into()
+			};
+
+			let authorization_details = match narrow_authorization_details(
+				authorization_details.as_ref(),
+				subject.authorization_details(),
+			) {
+				Ok(details) => details.cloned(),
+				Err(e) => return e.error_response(),
+			};
+
+			let resource_server =
+				match granted_resource_server(db, resource_server, subject.resource()).await {
+					Ok(resource_server) => resource_server,
+					Err(e) => return e.error_response(),
+				};
+
+			let mut token_audience = Vec::new();
+			if let Some(audience) = &audience {
+				let Some(audience_id) = db::get_client_id_by_alias(db, audience).await.unwrap()
+				else {
+					return TokenError::unknown_audience(audience).error_response();
+				};
+				token_audience.push(audience_id.to_string());
+			}
+			if let Some(resource_server) = &resource_server {
+				token_audience.push(resource_server.uri().to_string());
+			}
+
+			let access_token = jwt::Claims::exchanged_token(
+				db,
+				&subject,
+				actor,
+				client_id,
+				&token_audience,
+				duration,
+				&scope,
+			)
+			.await
+			.unwrap()
+			.with_jkt(jkt.as_deref())
+			.with_x5t_s256(x5t_s256.as_deref())
+			.with_authorization_details(authorization_details.as_ref())
+			.with_resource(resource_server.as_ref().map(ResourceServer::uri));
+
+			let expires_in = access_token.expires_in();
+			let scope = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token: None,
+				scope,
+				id_token: None,
+				issued_token_type: Some(ACCESS_TOKEN_TYPE),
+				authorization_details,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::Ciba {
+			auth_req_id,
+			client_alias,
+		} => {
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			let Ok(auth_req_id) = Uuid::from_str(&auth_req_id) else {
+				return TokenError::bad_auth_req_id().error_response();
+			};
+			let Some(request) = db::get_backchannel_authentication(db, auth_req_id)
+				.await
+				.unwrap()
+			else {
+				return TokenError::bad_auth_req_id().error_response();
+			};
+			if request.client_id != client_id {
+				return TokenError::bad_auth_req_id().error_response();
+			}
+			if request.is_expired() {
+				db::delete_backchannel_authentication(db, request.id)
+					.await
+					.unwrap();
+				return TokenError::expired_auth_req_id().error_response();
+			}
+
+			// the client has to wait at least the interval between each poll
+			let now = Utc::now();
+			if request.polled_too_soon(now) {
+				let interval = request.interval + device_code::SLOW_DOWN_INCREMENT;
+				db::update_backchannel_authentication_poll(db, request.id, now, interval)
+					.await
+					.unwrap();
+				return TokenError::slow_down().error_response();
+			}
+			db::update_backchannel_authentication_poll(db, request.id, now, request.interval)
+				.await
+				.unwrap();
+
+			match request.status {
+				BackchannelAuthenticationStatus::Pending => {
+					return TokenError::authorization_pending().error_response()
+				}
+				BackchannelAuthenticationStatus::Denied => {
+					db::delete_backchannel_authentication(db, request.id)
+						.await
+						.unwrap();
+					return TokenError::access_denied().error_response();
+				}
+				BackchannelAuthenticationStatus::Approved => (),
+			}
+
+			// the request can only be used once
+			if !db::delete_backchannel_authentication(db, request.id)
+				.await
+				.unwrap()
+			{
+				return TokenError::bad_auth_req_id().error_response();
+			}
+
+			let access_token = jwt::Claims::access_token(
+				db,
+				None,
+				self_id.clone(),
+				client_id,
+				request.user_id,
+				duration,
+				&request.scope,
+			)
+			.await
+			.unwrap()
+			.with_jkt(jkt.as_deref())
+			.with_x5t_s256(x5t_s256.as_deref());
+
+			// approving the request is how the user consents
+			let trusted = db::is_client_trusted(db, client_id).await.unwrap().unwrap();
+			if !trusted {
+				consent::grant(db, request.user_id, client_id, &request.scope)
+					.await
+					.unwrap();
+				db::add_consent_token(db, request.user_id, client_id, access_token.grant_id())
+					.await
+					.unwrap();
+			}
+
+			let refresh_token = jwt::Claims::refresh_token(db, &access_token).await.unwrap();
+			let access_token = access_token.restrict_to(resource_server.as_ref());
+
+			let expires_in = access_token.expires_in();
+			let scope: Box<str> = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+			let refresh_token = Some(refresh_token.to_jwt(db).await.unwrap());
+
+			// backchannel authentication always uses the openid scope
+			let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+			let id_token = IdTokenClaims::new(self_id, &client_alias, request.user_id, None, None)
+				.to_jwt(db, Some(&access_token), None)
+				.await
+				.unwrap();
+
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token,
+				scope,
+				id_token: Some(id_token),
+				issued_token_type: None,
+				authorization_details: None,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		GrantType::JwtBearer {
+			assertion,
+			scope,
+			client_alias,
+		} => {
+			// verify client
+			let client_id = match authenticate_client(
+				db,
+				authorization.as_deref(),
+				certificate,
+				client_assertion.as_ref(),
+				client_secret.as_deref(),
+				client_alias.as_deref(),
+				ip,
+			)
+			.await
+			{
+				Ok(id) => id,
+				Err(e) => return e.error_response(),
+			};
+
+			// verify the assertion
+			let subject = match jwt_bearer::verify_assertion_grant(
+				db,
+				&trusted_issuers,
+				&assertion,
+				&self_id,
+			)
+			.await
+			{
+				Ok(subject) => subject,
+				Err(e) => return TokenError::bad_assertion(e.unwrap()).error_response(),
+			};
+			let Some(user) = db::get_user_by_username(db, &subject).await.unwrap() else {
+				return TokenError::unknown_assertion_subject().error_response();
+			};
+
+			// verify scope
+			let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+				.await
+				.unwrap()
+				.unwrap();
+			let scope = if let Some(scope) = &scope {
+				scope.clone()
+			} else {
+				let default_scopes = db::get_client_default_scopes(db, client_id)
+					.await
+					.unwrap()
+					.unwrap();
+				let Some(scope) = default_scopes else {
+					return TokenError::no_scope().error_response();
+				};
+				scope
+			};
+			if !scopes::is_subset_of(&scope, &allowed_scopes) {
+				return TokenError::excessive_scope().error_response();
+			}
+
+			// verify authorization details
+			if let Some(details) = &authorization_details {
+				if !authorization_details::types_are_allowed(db, client_id, details)
+					.await
+					.unwrap()
+				{
+					return TokenError::invalid_authorization_details().error_response();
+				}
+			}
+
+			let access_token =
+				jwt::Claims::access_token(db, None, self_id, client_id, user.id, duration, &scope)
+					.await
+					.unwrap()
+					.with_jkt(jkt.as_deref())
+					.with_x5t_s256(x5t_s256.as_deref())
+					.with_authorization_details(authorization_details.as_ref())
+					.restrict_to(resource_server.as_ref());
+
+			// the user never sees this grant, but the token is still revoked
+			// if the user revokes their consent for the client
+			let trusted = db::is_client_trusted(db, client_id).await.unwrap().unwrap();
+			if !trusted {
+				db::add_consent_token(db, user.id, client_id, access_token.grant_id())
+					.await
+					.unwrap();
+			}
+
+			let expires_in = access_token.expires_in();
+			let scope = access_token.scopes().into();
+			let access_token = access_token.to_jwt(db).await.unwrap();
+
+			// the client can use the assertion again, so no refresh token is needed
+			let response = TokenResponse {
+				access_token,
+				token_type,
+				expires_in,
+				refresh_token: None,
+				scope,
+				id_token: None,
+				issued_token_type: None,
+				authorization_details,
+			};
+			HttpResponse::Ok()
+				.insert_header(cache_control)
+				.insert_header((header::PRAGMA, "no-cache"))
+				.json(response)
+		}
+		_ => TokenError::unsupported_grant_type().error_response(),
+	}
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct DeviceAuthorizationRequest {
+	client_id: Option<Box<str>>,
+	client_secret: Option<Box<str>>,
+	scope: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct DeviceAuthorizationResponse {
+	device_code: Box<str>,
+	user_code: Box<str>,
+	verification_uri: Url,
+	verification_uri_complete: Url,
+	expires_in: i64,
+	interval: u32,
+}
+
+/// Start the device authorization grant, as described in RFC 8628. The device
+/// shows the user code to the user, and then polls the token endpoint with the
+/// device code until the user approves or denies the request.
+#[post("/device_authorization")]
+async fn device_authorization(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+) -> HttpResponse {
+	let db = db.get_ref();
+	let request = serde_urlencoded::from_bytes::<DeviceAuthorizationRequest>(&req);
+	let Ok(request) = request else {
+		return TokenError::invalid_request().error_response();
+	};
+	let config = config::get_config().unwrap();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+	let client_id = match authenticate_client(
+		db,
+		authorization.as_deref(),
+		http_req.conn_data::<ClientCertificate>(),
+		request.client_assertion.as_ref(),
+		request.client_secret.as_deref(),
+		request.client_id.as_deref(),
+		ip,
+	)
+	.await
+	{
+		Ok(id) => id,
+		Err(e) => return e.error_response(),
+	};
+
+	// verify scope
+	let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+		.await
+		.unwrap()
+		.unwrap();
+	let scope = if let Some(scope) = &request.scope {
+		scope.clone()
+	} else {
+		let default_scopes = db::get_client_default_scopes(db, client_id)
+			.await
+			.unwrap()
+			.unwrap();
+		let Some(scope) = default_scopes else {
+			return TokenError::no_scope().error_response();
+		};
+		scope
+	};
+	if !scopes::is_subset_of(&scope, &allowed_scopes) {
+		return TokenError::excessive_scope().error_response();
+	}
+
+	let device_code = DeviceCode::new(db, client_id, &scope).await.unwrap();
+	let user_code = device_code::format_user_code(&device_code.user_code);
+
+	let verification_uri = config.url.join("device").unwrap();
+	let mut verification_uri_complete = verification_uri.clone();
+	verification_uri_complete
+		.query_pairs_mut()
+		.append_pair("user_code", &user_code);
+
+	let response = DeviceAuthorizationResponse {
+		device_code: device_code.id.to_string().into_boxed_str(),
+		user_code: user_code.into_boxed_str(),
+		verification_uri,
+		verification_uri_complete,
+		expires_in: device_code.expires_in(),
+		interval: device_code.interval,
+	};
+	HttpResponse::Ok()
+		.insert_header(cache_control)
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct BackchannelAuthenticationRequest {
+	client_id: Option<Box<str>>,
+	client_secret: Option<Box<str>>,
+	scope: Option<Box<str>>,
+	login_hint: Option<Box<str>>,
+	binding_message: Option<Box<str>>,
+	client_notification_token: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct BackchannelAuthenticationResponse {
+	auth_req_id: Box<str>,
+	expires_in: i64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	interval: Option<u32>,
+}
+
+/// Start a login for a user, as described in OpenID Connect Client-Initiated
+/// Backchannel Authentication. The user approves it on their own device, and
+/// then the client gets its tokens by polling the token endpoint. Clients
+/// using the ping mode are told when to stop waiting.
+#[post("/bc-authorize")]
+async fn backchannel_authentication(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+	notifier: web::Data<dyn BackchannelNotifier>,
+) -> HttpResponse {
+	let db = db.get_ref();
+	let request = serde_urlencoded::from_bytes::<BackchannelAuthenticationRequest>(&req);
+	let Ok(request) = request else {
+		return TokenError::invalid_request().error_response();
+	};
+	let config = config::get_config().unwrap();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+	let client_id = match authenticate_client(
+		db,
+		authorization.as_deref(),
+		http_req.conn_data::<ClientCertificate>(),
+		request.client_assertion.as_ref(),
+		request.client_secret.as_deref(),
+		request.client_id.as_deref(),
+		ip,
+	)
+	.await
+	{
+		Ok(id) => id,
+		Err(e) => return e.error_response(),
+	};
+
+	let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+	let ty = db::get_client_type(db, client_id).await.unwrap().unwrap();
+	if ty != ClientType::Confidential {
+		return TokenError::client_not_confidential(&client_alias).error_response();
+	}
+
+	// verify scope
+	let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+		.await
+		.unwrap()
+		.unwrap();
+	let scope = if let Some(scope) = &request.scope {
+		scope.clone()
+	} else {
+		let default_scopes = db::get_client_default_scopes(db, client_id)
+			.await
+			.unwrap()
+			.unwrap();
+		let Some(scope) = default_scopes else {
+			return TokenError::no_scope().error_response();
+		};
+		scope
+	};
+	if !scopes::is_subset_of(&scope, &allowed_scopes) {
+		return TokenError::excessive_scope().error_response();
+	}
+	if !scopes::is_subset_of(scopes::OPENID, &scope) {
+		return TokenError::no_openid_scope().error_response();
+	}
+
+	// find the user
+	let Some(login_hint) = &request.login_hint else {
+		return TokenError::no_login_hint().error_response();
+	};
+	let Some(user) = db::get_user_by_username(db, login_hint).await.unwrap() else {
+		return TokenError::unknown_user_id().error_response();
+	};
+
+	let binding_message = request.binding_message.as_deref();
+	if binding_message.is_some_and(|message| message.chars().count() > MAX_BINDING_MESSAGE_LENGTH) {
+		return TokenError::invalid_binding_message().error_response();
+	}
+
+	// clients using the ping mode need a token for the notification
+	let endpoint = db::get_client_backchannel_client_notification_endpoint(db, client_id)
+		.await
+		.unwrap()
+		.unwrap();
+	let client_notification_token = match endpoint {
+		Some(_) => match request.client_notification_token.as_deref() {
+			Some(notification_token) => Some(notification_token),
+			None => return TokenError::no_client_notification_token().error_response(),
+		},
+		None => None,
+	};
+
+	let backchannel_authentication = BackchannelAuthentication::new(
+		db,
+		client_id,
+		user.id,
+		&scope,
+		binding_message,
+		client_notification_token,
+	)
+	.await
+	.unwrap();
+
+	let mut approval_uri = config.url.join("backchannel").unwrap();
+	approval_uri
+		.query_pairs_mut()
+		.append_pair("auth_req_id", &backchannel_authentication.id.to_string());
+	let notification = UserNotification {
+		username: &user.username,
+		client_id: &client_alias,
+		binding_message,
+		approval_uri: &approval_uri,
+	};
+	if let Err(e) = notifier.notify_user(&notification).await {
+		log::error!("{e}");
+	}
+
+	let response = BackchannelAuthenticationResponse {
+		auth_req_id: backchannel_authentication.id.to_string().into_boxed_str(),
+		expires_in: backchannel_authentication.expires_in(),
+		interval: client_notification_token
+			.map_or(Some(backchannel_authentication.interval), |_| None),
+	};
+	HttpResponse::Ok()
+		.insert_header(cache_control)
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct PushedAuthorizationRequest {
+	#[serde(flatten)]
+	params: AuthorizationParameters,
+	request_uri: Option<Box<str>>,
+	client_secret: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct PushedAuthorizationResponse {
+	request_uri: Box<str>,
+	expires_in: i64,
+}
+
+/// Store the parameters of an authorization request before the user is sent
+/// to the authorization endpoint, as described in RFC 9126. The client is
+/// authenticated the same way as on the token endpoint.
+#[post("/par")]
+async fn pushed_authorization(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+) -> HttpResponse {
+	let db = db.get_ref();
+	let request = serde_urlencoded::from_bytes::<PushedAuthorizationRequest>(&req);
+	let Ok(request) = request else {
+		return TokenError::invalid_request().error_response();
+	};
+	if request.request_uri.is_some() {
+		return TokenError::nested_request_uri().error_response();
+	}
+	let params = request.params;
+	let client_assertion = request.client_assertion;
+	let client_secret = request.client_secret;
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+	let client_id = match authenticate_client(
+		db,
+		authorization.as_deref(),
+		http_req.conn_data::<ClientCertificate>(),
+		client_assertion.as_ref(),
+		client_secret.as_deref(),
+		Some(&params.client_id),
+		ip,
+	)
+	.await
+	{
+		Ok(id) => id,
+		Err(e) => return e.error_response(),
+	};
+
+	// the rest of the request is checked by the authorization endpoint, where
+	// the errors can be sent to the redirect URI
+	if let Err(e) = get_redirect_uri(&params.redirect_uri, db, client_id).await {
+		if e.expected().is_none() {
+			return HttpResponse::InternalServerError().finish();
+		}
+		return TokenError::unknown_redirect_uri().error_response();
+	}
+	if params.response_type == ResponseType::Unsupported {
+		return TokenError::unsupported_response_type().error_response();
+	}
+	if let Some(details) = &params.authorization_details {
+		if !authorization_details::types_are_allowed(db, client_id, details)
+			.await
+			.unwrap()
+		{
+			return TokenError::invalid_authorization_details().error_response();
+		}
+	}
+	if let Some(resource) = &params.resource {
+		if !db::resource_server_uri_is_used(db, resource).await.unwrap() {
+			return TokenError::unknown_resource(resource).error_response();
+		}
+	}
+
+	let id = id::new_id(db, db::pushed_authorization_exists)
+		.await
+		.unwrap();
+	let exp = Utc::now() + Duration::seconds(PUSHED_AUTHORIZATION_LIFETIME);
+	let parameters = serde_json::to_string(&params).unwrap();
+	db::create_pushed_authorization(db, id, client_id, &parameters, exp)
+		.await
+		.unwrap();
+
+	let response = PushedAuthorizationResponse {
+		request_uri: format!("{REQUEST_URI_PREFIX}{id}").into_boxed_str(),
+		expires_in: PUSHED_AUTHORIZATION_LIFETIME,
+	};
+	HttpResponse::Created()
+		.insert_header(cache_control)
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response)
+}
+
+/// The `token_type_hint` parameter is accepted, but isn't needed, since every
+/// token says what type it is.
+#[derive(Debug, Clone, Deserialize)]
+struct RevocationRequest {
+	token: Box<str>,
+	client_id: Option<Box<str>>,
+	client_secret: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+}
+
+#[post("/revoke")]
+async fn revoke(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+) -> HttpResponse {
+	let db = db.get_ref();
+	let request = serde_urlencoded::from_bytes::<RevocationRequest>(&req);
+	let Ok(request) = request else {
+		return TokenError::invalid_request().error_response();
+	};
+	let config = config::get_config().unwrap();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	let self_id = config.url;
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+	let authorization = authorization.as_deref();
+	let certificate = http_req.conn_data::<ClientCertificate>();
+	let client_id = request.client_id.as_deref();
+	let client_assertion = request.client_assertion.as_ref();
+	let client_secret = request.client_secret.as_deref();
+	let client_id = match authenticate_client(
+		db,
+		authorization,
+		certificate,
+		client_assertion,
+		client_secret,
+		client_id,
+		ip,
+	)
+	.await
+	{
+		Ok(client_id) => client_id,
+		Err(e) => return e.error_response(),
+	};
+
+	// invalid tokens don't need to be revoked, so they're not an error
+	if let Err(e) = jwt::revoke_token(db, &request.token, &self_id, client_id).await {
+		match e.unwrap() {
+			VerifyJwtError::WrongClient => {
+				return TokenError::token_for_other_client().error_response()
+			}
+			VerifyJwtError::UnsupportedTokenType => {
+				return TokenError::unsupported_token_type().error_response()
+			}
+			_ => (),
+		}
+	}
+
+	HttpResponse::Ok()
+		.insert_header(cache_control)
+		.insert_header((header::PRAGMA, "no-cache"))
+		.finish()
+}
+
+/// The `token_type_hint` parameter is accepted, but isn't needed, since every
+/// token says what type it is.
+#[derive(Debug, Clone, Deserialize)]
+struct IntrospectionRequest {
+	token: Box<str>,
+	/// The method that the resource server received the DPoP proof with
+	htm: Option<Box<str>>,
+	/// The URI that the resource server received the DPoP proof on
+	htu: Option<Url>,
+	client_id: Option<Box<str>>,
+	client_secret: Option<Box<str>>,
+	#[serde(flatten)]
+	client_assertion: Option<ClientAssertion>,
+}
+
+/// If the token isn't active, then only the `active` field is included
+#[derive(Debug, Clone, Default, Serialize)]
+struct IntrospectionResponse {
+	active: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	scope: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	client_id: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	username: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	token_type: Option<&'static str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	exp: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	iat: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	nbf: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	sub: Option<Uuid>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	aud: Option<Box<[String]>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	iss: Option<Url>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	jti: Option<Uuid>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	act: Option<jwt::Actor>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	cnf: Option<jwt::Confirmation>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	authorization_details: Option<AuthorizationDetails>,
+}
+
+/// A resource server can forward the DPoP proof that it received along with
+/// the token. The token is only active if the proof is valid, and was made
+/// with the key that the token is bound to.
+#[post("/introspect")]
+async fn introspect(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BasicAuthorization>>,
+	dpop: Option<web::Header<authorization::DpopProof>>,
+) -> HttpResponse {
+	let db = db.get_ref();
+	let request = serde_urlencoded::from_bytes::<IntrospectionRequest>(&req);
+	let Ok(request) = request else {
+		return TokenError::invalid_request().error_response();
+	};
+	let config = config::get_config().unwrap();
+	let ip = http_req.peer_addr().unwrap().ip();
+
+	let self_id = config.url;
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+	// only clients with credentials may introspect tokens
+	let authorization = authorization.as_deref();
+	let certificate = http_req.conn_data::<ClientCertificate>();
+	let client_assertion = request.client_assertion.as_ref();
+	let client_secret = request.client_secret.as_deref();
+	let client_alias = request.client_id.as_deref();
+	let client_id = match authenticate_client(
+		db,
+		authorization,
+		certificate,
+		client_assertion,
+		client_secret,
+		client_alias,
+		ip,
+	)
+	.await
+	{
+		Ok(client_id) => client_id,
+		Err(e) => return e.error_response(),
+	};
+	let ty = db::get_client_type(db, client_id).await.unwrap().unwrap();
+	if ty != ClientType::Confidential {
+		let client_alias = db::get_client_alias(db, client_id).await.unwrap().unwrap();
+		return TokenError::client_not_confidential(&client_alias).error_response();
+	}
+
+	let mut claims = jwt::introspect_token(db, &request.token, &self_id)
+		.await
+		.ok();
+	if let (Some(introspected), Some(proof)) = (&claims, dpop.as_deref()) {
+		let (Some(htm), Some(htu)) = (&request.htm, &request.htu) else {
+			return TokenError::invalid_request().error_response();
+		};
+		let jkt = dpop::verify_proof(db, proof.proof(), htm, htu, Some(&request.token))
+			.await
+			.ok();
+		if jkt.is_none() || jkt.as_deref() != introspected.jkt() {
+			claims = None;
+		}
+	}
+
+	let response = match claims {
+		Some(claims) => {
+			let client_id = db::get_client_alias(db, claims.client_id()).await.unwrap();
+			let username = db::get_username(db, claims.subject()).await.unwrap();
+			let token_type = match (claims.token_type(), claims.jkt()) {
+				(jwt::TokenType::Access, Some(_)) => Some("DPoP"),
+				(jwt::TokenType::Access, None) => Some("bearer"),
+				_ => None,
+			};
+
+			IntrospectionResponse {
+				active: true,
+				scope: Some(claims.scopes().into()),
+				client_id,
+				username,
+				token_type,
+				exp: Some(claims.expiration().timestamp()),
+				iat: Some(claims.issued_at().timestamp()),
+				nbf: claims.not_before().map(|nbf| nbf.timestamp()),
+				sub: Some(claims.subject()),
+				aud: Some(claims.audience().into()),
+				iss: Some(claims.issuer().clone()),
+				jti: Some(claims.id()),
+				act: claims.actor().cloned(),
+				cnf: claims.confirmation().cloned(),
+				authorization_details: claims.authorization_details().cloned(),
+			}
+		}
+		// an error means the token is inactive, since it isn't usable
+		None => IntrospectionResponse::default(),
+	};
+
+	HttpResponse::Ok()
+		.insert_header(cache_control)
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response)
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BearerErrorType {
+	InvalidRequest,
+	InvalidToken,
+	InsufficientScope,
+	InvalidDpopProof,
+}
+
+/// An error from a resource protected by a bearer token, as defined in
+/// RFC 6750. The details are sent in the `WWW-Authenticate` header.
+#[derive(Debug, Clone, Error)]
+#[error("{error_description}")]
+struct BearerError {
+	status_code: StatusCode,
+	error: Option<BearerErrorType>,
+	error_description: Box<str>,
+	scope: Option<&'static str>,
+}
+
+impl BearerError {
+	fn missing_token() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: None,
+			error_description: "No access token was provided".into(),
+			scope: None,
+		}
+	}
+
+	fn multiple_tokens() -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: Some(BearerErrorType::InvalidRequest),
+			error_description: "Only one method may be used to send the access token".into(),
+			scope: None,
+		}
+	}
+
+	fn invalid_token(error: VerifyJwtError) -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidToken),
+			error_description: error.to_string().into_boxed_str(),
+			scope: None,
+		}
+	}
+
+	fn invalid_dpop_proof(error: VerifyProofError) -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidDpopProof),
+			error_description: error.to_string().into_boxed_str(),
+			scope: None,
+		}
+	}
+
+	fn missing_dpop_proof() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidDpopProof),
+			error_description: "The access token is bound to a key, but no DPoP proof was sent"
+				.into(),
+			scope: None,
+		}
+	}
+
+	fn wrong_dpop_key() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidDpopProof),
+			error_description: "The DPoP proof was made with a different key".into(),
+			scope: None,
+		}
+	}
+
+	fn wrong_client_certificate() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidToken),
+			error_description: "The access token is bound to a different client certificate".into(),
+			scope: None,
+		}
+	}
 
-			let trusted = db::is_client_trusted(db, client_id).await.unwrap().unwrap();
-			if !trusted {
-				return TokenError::untrusted_client().error_response();
-			}
+	fn unbound_token() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidToken),
+			error_description: "The access token is not bound to a key".into(),
+			scope: None,
+		}
+	}
 
-			// brute force detection
-			if brute_force_detection::brute_force_detected(db, &client_id.to_string(), ip)
-				.await
-				.unwrap()
-			{
-				return TokenError::too_many_requests().error_response();
-			}
+	fn user_not_found() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: Some(BearerErrorType::InvalidToken),
+			error_description: "The user for this token no longer exists".into(),
+			scope: None,
+		}
+	}
 
-			// verify client
-			let hash = db::get_client_secret(db, client_id).await.unwrap().unwrap();
-			if !hash.check_password(authorization.password()).unwrap() {
-				db::add_failed_login_attempt(db, &client_id.to_string(), ip)
-					.await
-					.unwrap();
-				return TokenError::incorrect_client_secret().error_response();
-			}
+	fn insufficient_scope(scope: &'static str) -> Self {
+		Self {
+			status_code: StatusCode::FORBIDDEN,
+			error: Some(BearerErrorType::InsufficientScope),
+			error_description: format!("The access token must have the {scope} scope")
+				.into_boxed_str(),
+			scope: Some(scope),
+		}
+	}
+}
 
-			// brute force detection
-			if brute_force_detection::brute_force_detected(db, &username, ip)
-				.await
-				.unwrap()
-			{
-				return TokenError::too_many_requests().error_response();
-			}
+impl ResponseError for BearerError {
+	fn status_code(&self) -> StatusCode {
+		self.status_code
+	}
 
-			// authenticate user
-			let Some(user_id) = authenticate_user(db, &username, &password).await.unwrap() else {
-				db::add_failed_login_attempt(db, &username, ip).await.unwrap();
-				return TokenError::incorrect_user_credentials().error_response();
-			};
+	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+		let mut challenge = match self.error {
+			Some(BearerErrorType::InvalidDpopProof) => String::from("DPoP"),
+			_ => String::from("Bearer"),
+		};
+		if let Some(error) = &self.error {
+			let error = serde_variant::to_variant_name(error).unwrap();
+			let description = &self.error_description;
+			challenge.push_str(&format!(
+				" error=\"{error}\", error_description=\"{description}\""
+			));
+		}
+		if let Some(scope) = self.scope {
+			challenge.push_str(&format!(", scope=\"{scope}\""));
+		}
 
-			// verify scope
-			let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
-				.await
-				.unwrap()
-				.unwrap();
-			let scope = if let Some(scope) = &scope {
-				scope.clone()
-			} else {
-				let default_scopes = db::get_client_default_scopes(db, client_id)
-					.await
-					.unwrap()
-					.unwrap();
-				let Some(scope) = default_scopes else {
-					return TokenError::no_scope().error_response();
-				};
-				scope
-			};
-			if !scopes::is_subset_of(&scope, &allowed_scopes) {
-				return TokenError::excessive_scope().error_response();
-			}
+		HttpResponseBuilder::new(self.status_code)
+			.insert_header((header::WWW_AUTHENTICATE, challenge))
+			.finish()
+	}
+}
 
-			let access_token =
-				jwt::Claims::access_token(db, None, self_id, client_id, user_id, duration, &scope)
-					.await
-					.unwrap();
-			let refresh_token = jwt::Claims::refresh_token(db, &access_token).await.unwrap();
+/// The claims about the user that the access token's scopes allow the client
+/// to see
+#[derive(Debug, Clone, Serialize)]
+struct UserInfoResponse {
+	sub: Uuid,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	preferred_username: Option<Box<str>>,
+}
 
-			let expires_in = access_token.expires_in();
-			let scope = access_token.scopes().into();
-			let access_token = access_token.to_jwt().unwrap();
-			let refresh_token = Some(refresh_token.to_jwt().unwrap());
+impl UserInfoResponse {
+	/// Every claim that the UserInfo endpoint might return
+	const CLAIMS: &'static [&'static str] = &["sub", "preferred_username"];
+}
 
-			let response = TokenResponse {
-				access_token,
-				token_type,
-				expires_in,
-				refresh_token,
-				scope,
-			};
-			HttpResponse::Ok()
-				.insert_header(cache_control)
-				.insert_header((header::PRAGMA, "no-cache"))
-				.json(response)
-		}
-		GrantType::ClientCredentials { scope } => {
-			let Some(authorization) = authorization else {
-				return TokenError::no_authorization().error_response();
-			};
-			let client_alias = authorization.username();
-			let Some(client_id) = db::get_client_id_by_alias(db, client_alias).await.unwrap() else {
-				return TokenError::client_not_found(client_alias).error_response();
-			};
+#[derive(Debug, Clone, Deserialize)]
+struct UserInfoRequest {
+	access_token: Option<Box<str>>,
+}
 
-			let ty = db::get_client_type(db, client_id).await.unwrap().unwrap();
-			if ty != ClientType::Confidential {
-				return TokenError::client_not_confidential(client_alias).error_response();
-			}
+/// How the access token was sent to the UserInfo endpoint
+enum UserInfoToken<'a> {
+	Bearer(&'a str),
+	/// The token was sent with the `DPoP` scheme, along with the proof
+	Dpop(&'a str, Option<&'a authorization::DpopProof>),
+}
 
-			// brute force detection
-			if brute_force_detection::brute_force_detected(db, &client_id.to_string(), ip)
-				.await
-				.unwrap()
-			{
-				return TokenError::too_many_requests().error_response();
-			}
+async fn get_user_info(
+	db: &MySqlPool,
+	access_token: Option<UserInfoToken<'_>>,
+	certificate: Option<&ClientCertificate>,
+	method: &str,
+) -> Result<HttpResponse, BearerError> {
+	let (access_token, proof) = match access_token {
+		Some(UserInfoToken::Bearer(access_token)) => (access_token, None),
+		Some(UserInfoToken::Dpop(access_token, proof)) => (access_token, Some(proof)),
+		None => yeet!(BearerError::missing_token()),
+	};
 
-			// verify client
-			let hash = db::get_client_secret(db, client_id).await.unwrap().unwrap();
-			if !hash.check_password(authorization.password()).unwrap() {
-				db::add_failed_login_attempt(db, &client_id.to_string(), ip)
-					.await
-					.unwrap();
-				return TokenError::incorrect_client_secret().error_response();
-			}
+	let config = config::get_config().unwrap();
+	let self_id = config.url;
+	let claims = match jwt::verify_access_token(db, access_token, &self_id, None).await {
+		Ok(claims) => claims,
+		Err(e) => yeet!(BearerError::invalid_token(e.unwrap())),
+	};
 
-			// verify scope
-			let allowed_scopes = db::get_client_allowed_scopes(db, client_id)
+	// tokens which were requested for a resource server can't be used here
+	if !claims.is_intended_for(&self_id) {
+		yeet!(BearerError::invalid_token(VerifyJwtError::BadAudience));
+	}
+
+	// a bound token can only be used with a proof from the same key
+	match (claims.jkt(), proof) {
+		(Some(bound), Some(Some(proof))) => {
+			let uri = self_id.join("oauth/userinfo").unwrap();
+			let jkt = dpop::verify_proof(db, proof.proof(), method, &uri, Some(access_token))
 				.await
-				.unwrap()
-				.unwrap();
-			let scope = if let Some(scope) = &scope {
-				scope.clone()
-			} else {
-				let default_scopes = db::get_client_default_scopes(db, client_id)
-					.await
-					.unwrap()
-					.unwrap();
-				let Some(scope) = default_scopes else {
-					return TokenError::no_scope().error_response();
-				};
-				scope
-			};
-			if !scopes::is_subset_of(&scope, &allowed_scopes) {
-				return TokenError::excessive_scope().error_response();
+				.map_err(|e| BearerError::invalid_dpop_proof(e.unwrap()))?;
+			if &*jkt != bound {
+				yeet!(BearerError::wrong_dpop_key());
 			}
+		}
+		(Some(_), _) => yeet!(BearerError::missing_dpop_proof()),
+		(None, Some(_)) => yeet!(BearerError::unbound_token()),
+		(None, None) => (),
+	}
 
-			let access_token = jwt::Claims::access_token(
-				db, None, self_id, client_id, client_id, duration, &scope,
-			)
-			.await
-			.unwrap();
+	// a certificate-bound token can only be used with the same certificate
+	if let Some(bound) = claims.x5t_s256() {
+		let x5t_s256 = certificate.map(ClientCertificate::thumbprint);
+		if x5t_s256.as_deref() != Some(bound) {
+			yeet!(BearerError::wrong_client_certificate());
+		}
+	}
 
-			let expires_in = access_token.expires_in();
-			let scope = access_token.scopes().into();
-			let access_token = access_token.to_jwt().unwrap();
+	if !scopes::is_subset_of(scopes::OPENID, claims.scopes()) {
+		yeet!(BearerError::insufficient_scope(scopes::OPENID));
+	}
 
-			let response = TokenResponse {
-				access_token,
-				token_type,
-				expires_in,
-				refresh_token: None,
-				scope,
-			};
-			HttpResponse::Ok()
-				.insert_header(cache_control)
-				.insert_header((header::PRAGMA, "no-cache"))
-				.json(response)
-		}
-		GrantType::RefreshToken {
-			refresh_token,
-			scope,
-		} => {
-			let claims = match jwt::verify_refresh_token(db, &refresh_token, &self_id).await {
-				Ok(claims) => claims,
-				Err(e) => {
-					let e = e.unwrap();
-					return TokenError::bad_refresh_token(e).error_response();
-				}
-			};
+	let Some(user) = db::get_user(db, claims.subject()).await.unwrap() else {
+		yeet!(BearerError::user_not_found());
+	};
 
-			let client_id = claims.client_id();
-			if let Some(authorization) = authorization {
-				let client_alias = authorization.username();
-				let Some(id) = db::get_client_id_by_alias(db, client_alias).await.unwrap() else {
-					return TokenError::client_not_found(client_alias).error_response();
-				};
+	let has_profile = scopes::is_subset_of(scopes::PROFILE, claims.scopes());
+	let response = UserInfoResponse {
+		sub: user.id,
+		preferred_username: has_profile.then(|| user.username.clone()),
+	};
 
-				// brute force detection
-				if brute_force_detection::brute_force_detected(db, &client_id.to_string(), ip)
-					.await
-					.unwrap()
-				{
-					return TokenError::too_many_requests().error_response();
-				}
+	let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+	Ok(HttpResponse::Ok()
+		.insert_header(cache_control)
+		.json(response))
+}
 
-				// authenticate client
-				if let Some(hash) = db::get_client_secret(db, id).await.unwrap() {
-					if !hash.check_password(authorization.password()).unwrap() {
-						db::add_failed_login_attempt(db, &client_id.to_string(), ip)
-							.await
-							.unwrap();
-						return TokenError::incorrect_client_secret().error_response();
-					}
-				} else {
-					return TokenError::incorrect_client_secret().error_response();
-				}
-			} else if db::get_client_secret(db, client_id)
-				.await
-				.unwrap()
-				.is_some()
-			{
-				return TokenError::no_authorization().error_response();
-			}
+#[get("/userinfo")]
+async fn userinfo(
+	db: web::Data<MySqlPool>,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+	dpop_authorization: Option<web::Header<authorization::DpopAuthorization>>,
+	dpop: Option<web::Header<authorization::DpopProof>>,
+) -> Result<HttpResponse, BearerError> {
+	let access_token = match (authorization.as_ref(), dpop_authorization.as_ref()) {
+		(Some(header), _) => Some(UserInfoToken::Bearer(header.token())),
+		(None, Some(header)) => Some(UserInfoToken::Dpop(header.token(), dpop.as_deref())),
+		(None, None) => None,
+	};
+	let certificate = http_req.conn_data::<ClientCertificate>();
+	get_user_info(&db, access_token, certificate, "GET").await
+}
 
-			let scope = if let Some(scope) = scope {
-				if !scopes::is_subset_of(&scope, claims.scopes()) {
-					return TokenError::excessive_scope().error_response();
-				}
+/// The access token may be sent in the request body, instead of the
+/// Authorization header
+#[post("/userinfo")]
+async fn userinfo_post(
+	db: web::Data<MySqlPool>,
+	req: web::Bytes,
+	http_req: HttpRequest,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+	dpop_authorization: Option<web::Header<authorization::DpopAuthorization>>,
+	dpop: Option<web::Header<authorization::DpopProof>>,
+) -> Result<HttpResponse, BearerError> {
+	let body_token = serde_urlencoded::from_bytes::<UserInfoRequest>(&req)
+		.ok()
+		.and_then(|request| request.access_token);
+	let header_token = match (authorization.as_ref(), dpop_authorization.as_ref()) {
+		(Some(header), _) => Some(UserInfoToken::Bearer(header.token())),
+		(None, Some(header)) => Some(UserInfoToken::Dpop(header.token(), dpop.as_deref())),
+		(None, None) => None,
+	};
 
-				scope
-			} else {
-				claims.scopes().into()
-			};
+	let access_token = match (header_token, body_token.as_deref()) {
+		(Some(_), Some(_)) => yeet!(BearerError::multiple_tokens()),
+		(Some(access_token), None) => Some(access_token),
+		(None, Some(access_token)) => Some(UserInfoToken::Bearer(access_token)),
+		(None, None) => None,
+	};
 
-			let exp_time = Duration::hours(1);
-			let access_token = jwt::Claims::refreshed_access_token(db, &claims, exp_time)
-				.await
-				.unwrap();
-			let refresh_token = jwt::Claims::refresh_token(db, &claims).await.unwrap();
+	let certificate = http_req.conn_data::<ClientCertificate>();
+	get_user_info(&db, access_token, certificate, "POST").await
+}
 
-			let access_token = access_token.to_jwt().unwrap();
-			let refresh_token = Some(refresh_token.to_jwt().unwrap());
-			let expires_in = exp_time.num_seconds();
+/// Every user has the same subject identifier for every client
+const SUBJECT_TYPES: &[&str] = &["public"];
 
-			let response = TokenResponse {
-				access_token,
-				token_type,
-				expires_in,
-				refresh_token,
-				scope,
-			};
-			HttpResponse::Ok()
-				.insert_header(cache_control)
-				.insert_header((header::PRAGMA, "no-cache"))
-				.json(response)
+/// The authorization server metadata, as defined in RFC 8414. This also
+/// includes the OpenID Provider metadata, so that it can be used for OpenID
+/// Connect discovery.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerMetadata {
+	issuer: Url,
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	userinfo_endpoint: Url,
+	jwks_uri: Url,
+	revocation_endpoint: Url,
+	introspection_endpoint: Url,
+	device_authorization_endpoint: Url,
+	registration_endpoint: Url,
+	pushed_authorization_request_endpoint: Url,
+	backchannel_authentication_endpoint: Url,
+	backchannel_token_delivery_modes_supported: &'static [&'static str],
+	backchannel_user_code_parameter_supported: bool,
+	require_pushed_authorization_requests: bool,
+	request_parameter_supported: bool,
+	request_uri_parameter_supported: bool,
+	request_object_signing_alg_values_supported: &'static [Algorithm],
+	dpop_signing_alg_values_supported: &'static [Algorithm],
+	tls_client_certificate_bound_access_tokens: bool,
+	scopes_supported: &'static [&'static str],
+	response_types_supported: &'static [ResponseType],
+	response_modes_supported: &'static [ResponseMode],
+	authorization_response_iss_parameter_supported: bool,
+	grant_types_supported: &'static [&'static str],
+	token_endpoint_auth_methods_supported: &'static [&'static str],
+	token_endpoint_auth_signing_alg_values_supported: &'static [&'static str],
+	revocation_endpoint_auth_methods_supported: &'static [&'static str],
+	introspection_endpoint_auth_methods_supported: &'static [&'static str],
+	code_challenge_methods_supported: &'static [CodeChallengeMethod],
+	subject_types_supported: &'static [&'static str],
+	id_token_signing_alg_values_supported: &'static [Algorithm],
+	authorization_signing_alg_values_supported: &'static [Algorithm],
+	claims_supported: Box<[&'static str]>,
+	authorization_details_types_supported: Box<[Box<str>]>,
+}
+
+impl ServerMetadata {
+	pub fn new(issuer: Url) -> Result<Self, RawUnexpected> {
+		let config = config::get_config()?;
+		let mut claims_supported = IdTokenClaims::CLAIMS.to_vec();
+		for claim in UserInfoResponse::CLAIMS {
+			if !claims_supported.contains(claim) {
+				claims_supported.push(claim);
+			}
 		}
-		_ => TokenError::unsupported_grant_type().error_response(),
+
+		Ok(Self {
+			authorization_endpoint: issuer.join("oauth/authorize")?,
+			token_endpoint: issuer.join("oauth/token")?,
+			userinfo_endpoint: issuer.join("oauth/userinfo")?,
+			jwks_uri: issuer.join(".well-known/jwks.json")?,
+			revocation_endpoint: issuer.join("oauth/revoke")?,
+			introspection_endpoint: issuer.join("oauth/introspect")?,
+			device_authorization_endpoint: issuer.join("oauth/device_authorization")?,
+			registration_endpoint: issuer.join("oauth/register")?,
+			pushed_authorization_request_endpoint: issuer.join("oauth/par")?,
+			backchannel_authentication_endpoint: issuer.join("oauth/bc-authorize")?,
+			backchannel_token_delivery_modes_supported: &["poll", "ping"],
+			backchannel_user_code_parameter_supported: false,
+			require_pushed_authorization_requests: false,
+			request_parameter_supported: true,
+			// only request URIs from the pushed authorization request endpoint
+			request_uri_parameter_supported: false,
+			request_object_signing_alg_values_supported: Algorithm::SUPPORTED,
+			dpop_signing_alg_values_supported: Algorithm::SUPPORTED,
+			// client certificates are only available if the server terminates TLS
+			tls_client_certificate_bound_access_tokens: config.tls.is_some(),
+			issuer,
+			scopes_supported: scopes::SUPPORTED,
+			response_types_supported: ResponseType::SUPPORTED,
+			response_modes_supported: ResponseMode::SUPPORTED,
+			authorization_response_iss_parameter_supported: true,
+			grant_types_supported: GrantType::SUPPORTED,
+			token_endpoint_auth_methods_supported: TOKEN_ENDPOINT_AUTH_METHODS,
+			token_endpoint_auth_signing_alg_values_supported: TOKEN_ENDPOINT_AUTH_SIGNING_ALGS,
+			revocation_endpoint_auth_methods_supported: TOKEN_ENDPOINT_AUTH_METHODS,
+			introspection_endpoint_auth_methods_supported: INTROSPECTION_ENDPOINT_AUTH_METHODS,
+			code_challenge_methods_supported: CodeChallengeMethod::SUPPORTED,
+			subject_types_supported: SUBJECT_TYPES,
+			id_token_signing_alg_values_supported: Algorithm::SUPPORTED,
+			authorization_signing_alg_values_supported: Algorithm::SUPPORTED,
+			claims_supported: claims_supported.into_boxed_slice(),
+			authorization_details_types_supported: config.authorization_details_types,
+		})
 	}
 }
 
@@ -1018,4 +4172,83 @@ pub fn service() -> Scope {
 		.service(authorize_page)
 		.service(authorize)
 		.service(token)
+		.service(revoke)
+		.service(introspect)
+		.service(userinfo)
+		.service(userinfo_post)
+		.service(device_authorization)
+		.service(backchannel_authentication)
+		.service(pushed_authorization)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parse_params(query: &str) -> AuthorizationParameters {
+		serde_urlencoded::from_str(&format!("client_id=client&{query}")).unwrap()
+	}
+
+	fn redirect_uri() -> Url {
+		Url::parse("https://client.example.com/callback").unwrap()
+	}
+
+	#[test]
+	fn code_defaults_to_query() {
+		let params = parse_params("response_type=code");
+		assert_eq!(params.response_mode(), ResponseMode::Query);
+	}
+
+	#[test]
+	fn tokens_default_to_fragment() {
+		for response_type in ["token", "id_token", "code+id_token", "code+token"] {
+			let params = parse_params(&format!("response_type={response_type}"));
+			assert_eq!(params.response_mode(), ResponseMode::Fragment);
+		}
+	}
+
+	#[test]
+	fn jwt_uses_default_mode() {
+		let params_for_code = parse_params("response_type=code&response_mode=jwt");
+		assert_eq!(params_for_code.response_mode(), ResponseMode::QueryJwt);
+
+		let params_for_token = parse_params("response_type=token&response_mode=jwt");
+		assert_eq!(params_for_token.response_mode(), ResponseMode::FragmentJwt);
+	}
+
+	#[test]
+	fn explicit_mode_is_used() {
+		let params = parse_params("response_type=code&response_mode=form_post");
+		assert_eq!(params.response_mode(), ResponseMode::FormPost);
+
+		let params = parse_params("response_type=code&response_mode=fragment.jwt");
+		assert_eq!(params.response_mode(), ResponseMode::FragmentJwt);
+	}
+
+	#[test]
+	fn tokens_cannot_use_query() {
+		let params = parse_params("response_type=code+token&response_mode=query");
+		let error = response_mode_error(&params, params.response_mode(), &redirect_uri());
+		assert!(error.is_some());
+
+		let params = parse_params("response_type=id_token&response_mode=query.jwt");
+		let error = response_mode_error(&params, params.response_mode(), &redirect_uri());
+		assert!(error.is_some());
+	}
+
+	#[test]
+	fn code_can_use_any_mode() {
+		for response_mode in ["query", "fragment", "form_post", "query.jwt", "jwt"] {
+			let params = parse_params(&format!("response_type=code&response_mode={response_mode}"));
+			let error = response_mode_error(&params, params.response_mode(), &redirect_uri());
+			assert!(error.is_none());
+		}
+	}
+
+	#[test]
+	fn tokens_can_use_form_post() {
+		let params = parse_params("response_type=token&response_mode=form_post");
+		let error = response_mode_error(&params, params.response_mode(), &redirect_uri());
+		assert!(error.is_none());
+	}
 }
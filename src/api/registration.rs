@@ -0,0 +1,608 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{
+	delete, get, post, put, web, HttpResponse, HttpResponseBuilder, ResponseError, Scope,
+};
+use chrono::Utc;
+use raise::yeet;
+use serde::{Deserialize, Serialize};
+use sqlx::{Connection, MySqlPool};
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+use crate::models::client::{AuthMethod, Client, ClientOptions, ClientType, CreateClientError};
+use crate::scopes;
+use crate::services::crypto::{self, PasswordHash};
+use crate::services::keys::JwkSet;
+use crate::services::{authorization, config, db, id};
+
+use super::oauth::{GrantType, ResponseType};
+
+/// The client metadata from RFC 7591. This is stored as-is, so that it can be
+/// returned from the client configuration endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ClientMetadata {
+	#[serde(default)]
+	redirect_uris: Box<[Url]>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	token_endpoint_auth_method: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	grant_types: Option<Box<[Box<str>]>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	response_types: Option<Box<[Box<str>]>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	client_name: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	client_uri: Option<Url>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	logo_uri: Option<Url>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	scope: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	contacts: Option<Box<[Box<str>]>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	tos_uri: Option<Url>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	policy_uri: Option<Url>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	software_id: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	software_version: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	jwks: Option<JwkSet>,
+	/// From RFC 9126
+	#[serde(default)]
+	require_pushed_authorization_requests: bool,
+	/// From RFC 8705
+	#[serde(skip_serializing_if = "Option::is_none")]
+	tls_client_auth_san_dns: Option<Box<str>>,
+	/// From OpenID Connect Client-Initiated Backchannel Authentication
+	#[serde(skip_serializing_if = "Option::is_none")]
+	backchannel_token_delivery_mode: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	backchannel_client_notification_endpoint: Option<Url>,
+	/// From RFC 9396
+	#[serde(default, skip_serializing_if = "<[_]>::is_empty")]
+	authorization_details_types: Box<[Box<str>]>,
+}
+
+/// The parts of the metadata which affect how the client is stored
+struct ClientSettings {
+	client_type: ClientType,
+	auth_method: AuthMethod,
+	allowed_scopes: Box<[Box<str>]>,
+}
+
+/// The client information response from RFC 7591. The client secret is only
+/// included when a new one is issued.
+#[derive(Debug, Clone, Serialize)]
+struct ClientInformation {
+	client_id: Box<str>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	client_secret: Option<Box<str>>,
+	client_id_issued_at: i64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	client_secret_expires_at: Option<i64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	registration_access_token: Option<Box<str>>,
+	registration_client_uri: Url,
+	#[serde(flatten)]
+	metadata: ClientMetadata,
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum RegistrationErrorType {
+	InvalidRedirectUri,
+	InvalidClientMetadata,
+}
+
+/// An error from the registration endpoints. Errors with the registration
+/// access token are described in the `WWW-Authenticate` header instead, as
+/// defined in RFC 6750.
+#[derive(Debug, Clone, Error, Serialize)]
+#[error("{error_description}")]
+struct RegistrationError {
+	#[serde(skip)]
+	status_code: StatusCode,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<RegistrationErrorType>,
+	error_description: Box<str>,
+}
+
+impl RegistrationError {
+	fn invalid_redirect_uri(error: CreateClientError) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: Some(RegistrationErrorType::InvalidRedirectUri),
+			error_description: error.to_string().into_boxed_str(),
+		}
+	}
+
+	fn invalid_client_metadata(description: &str) -> Self {
+		Self {
+			status_code: StatusCode::BAD_REQUEST,
+			error: Some(RegistrationErrorType::InvalidClientMetadata),
+			error_description: description.into(),
+		}
+	}
+
+	fn invalid_token() -> Self {
+		Self {
+			status_code: StatusCode::UNAUTHORIZED,
+			error: None,
+			error_description: "The access token is missing, expired, or invalid".into(),
+		}
+	}
+}
+
+impl ResponseError for RegistrationError {
+	fn error_response(&self) -> HttpResponse<actix_web::body::BoxBody> {
+		let cache_control = header::CacheControl(vec![header::CacheDirective::NoStore]);
+
+		let mut builder = HttpResponseBuilder::new(self.status_code);
+
+		if self.status_code.as_u16() == 401 {
+			builder.insert_header((header::WWW_AUTHENTICATE, "Bearer error=\"invalid_token\""));
+		}
+
+		builder
+			.insert_header(cache_control)
+			.insert_header((header::PRAGMA, "no-cache"))
+			.json(self.clone())
+	}
+}
+
+fn supported_response_type(response_type: &str) -> bool {
+	ResponseType::SUPPORTED
+		.iter()
+		.any(|ty| serde_variant::to_variant_name(ty).is_ok_and(|name| name == response_type))
+}
+
+/// Fill in the defaults from RFC 7591, and check that the metadata describes a
+/// client that this server can support
+fn validate_metadata(metadata: &mut ClientMetadata) -> Result<ClientSettings, RegistrationError> {
+	let auth_method = metadata
+		.token_endpoint_auth_method
+		.get_or_insert_with(|| "client_secret_basic".into());
+	let Ok(auth_method) = auth_method.parse::<AuthMethod>() else {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"The token endpoint authentication method is not supported"
+		));
+	};
+	let client_type = if auth_method == AuthMethod::None {
+		ClientType::Public
+	} else {
+		ClientType::Confidential
+	};
+	if auth_method == AuthMethod::TlsClientAuth && metadata.tls_client_auth_san_dns.is_none() {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"Clients using tls_client_auth must have a tls_client_auth_san_dns"
+		));
+	}
+
+	let grant_types = metadata
+		.grant_types
+		.get_or_insert_with(|| Box::from([Box::from("authorization_code")]));
+	for grant_type in grant_types.iter() {
+		// the password grant is only for trusted clients
+		if !GrantType::SUPPORTED.contains(&&**grant_type) || &**grant_type == "password" {
+			yeet!(RegistrationError::invalid_client_metadata(
+				"A requested grant type is not supported"
+			));
+		}
+		if &**grant_type == "client_credentials" && client_type == ClientType::Public {
+			yeet!(RegistrationError::invalid_client_metadata(
+				"Public clients cannot use the client credentials grant"
+			));
+		}
+		if &**grant_type == "urn:openid:params:grant-type:ciba" && client_type == ClientType::Public
+		{
+			yeet!(RegistrationError::invalid_client_metadata(
+				"Public clients cannot use backchannel authentication"
+			));
+		}
+	}
+
+	// ping clients are told when their tokens are ready, and poll clients aren't
+	match (
+		metadata.backchannel_token_delivery_mode.as_deref(),
+		&metadata.backchannel_client_notification_endpoint,
+	) {
+		(None | Some("poll"), None) => (),
+		(Some("ping"), Some(endpoint)) => {
+			if endpoint.scheme() != "https" {
+				yeet!(RegistrationError::invalid_client_metadata(
+					"The backchannel client notification endpoint must use HTTPS"
+				));
+			}
+		}
+		(Some("ping"), None) => yeet!(RegistrationError::invalid_client_metadata(
+			"Clients using the ping mode must have a backchannel client notification endpoint"
+		)),
+		(None | Some("poll"), Some(_)) => yeet!(RegistrationError::invalid_client_metadata(
+			"Only clients using the ping mode can have a backchannel client notification endpoint"
+		)),
+		(Some(_), _) => yeet!(RegistrationError::invalid_client_metadata(
+			"The backchannel token delivery mode is not supported"
+		)),
+	}
+
+	let response_types = metadata
+		.response_types
+		.get_or_insert_with(|| Box::from([Box::from("code")]));
+	if !response_types.iter().all(|ty| supported_response_type(ty)) {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"A requested response type is not supported"
+		));
+	}
+
+	let allowed_scopes: Box<[Box<str>]> = metadata
+		.scope
+		.as_deref()
+		.unwrap_or_default()
+		.split_whitespace()
+		.map(Box::from)
+		.collect();
+	// only administrators can give a client the admin scope
+	for scope in allowed_scopes.iter() {
+		if !scopes::SUPPORTED.contains(&&**scope) || &**scope == "admin" {
+			yeet!(RegistrationError::invalid_client_metadata(
+				"A requested scope is not supported"
+			));
+		}
+	}
+
+	let supported_types = config::get_config().unwrap().authorization_details_types;
+	if !metadata
+		.authorization_details_types
+		.iter()
+		.all(|ty| supported_types.contains(ty))
+	{
+		yeet!(RegistrationError::invalid_client_metadata(
+			"A requested authorization details type is not supported"
+		));
+	}
+
+	if metadata.jwks.as_ref().is_some_and(|jwks| !jwks.is_valid()) {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"The key set contains a key that isn't a supported public key"
+		));
+	}
+	if auth_method == AuthMethod::SelfSignedTlsClientAuth
+		&& !metadata.jwks.as_ref().is_some_and(JwkSet::has_certificates)
+	{
+		yeet!(RegistrationError::invalid_client_metadata(
+			"Clients using self_signed_tls_client_auth must have a certificate in their key set"
+		));
+	}
+	if auth_method == AuthMethod::PrivateKeyJwt && metadata.jwks.is_none() {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"Clients using private_key_jwt must have a key set"
+		));
+	}
+
+	for redirect_uri in metadata.redirect_uris.iter() {
+		if redirect_uri.scheme() != "https" {
+			yeet!(RegistrationError::invalid_redirect_uri(
+				CreateClientError::NonHttpsUri
+			));
+		}
+
+		if redirect_uri.fragment().is_some() {
+			yeet!(RegistrationError::invalid_redirect_uri(
+				CreateClientError::UriFragment
+			));
+		}
+	}
+
+	Ok(ClientSettings {
+		client_type,
+		auth_method,
+		allowed_scopes,
+	})
+}
+
+fn registration_client_uri(client_alias: &str) -> Url {
+	let config = config::get_config().unwrap();
+	config
+		.url
+		.join(&format!("oauth/register/{client_alias}"))
+		.unwrap()
+}
+
+/// Check the registration access token, and find the client that it's for
+async fn authenticate_registration(
+	db: &MySqlPool,
+	client_alias: &str,
+	authorization: Option<&authorization::BearerAuthorization>,
+) -> Result<(Uuid, db::ClientRegistrationRow), RegistrationError> {
+	let Some(authorization) = authorization else {
+		yeet!(RegistrationError::invalid_token());
+	};
+	let Some(client_id) = db::get_client_id_by_alias(db, client_alias).await.unwrap() else {
+		yeet!(RegistrationError::invalid_token());
+	};
+	let Some(registration) = db::get_client_registration(db, client_id).await.unwrap() else {
+		yeet!(RegistrationError::invalid_token());
+	};
+	if *crypto::token_hash(authorization.token()) != *registration.registration_token_hash {
+		yeet!(RegistrationError::invalid_token());
+	}
+
+	Ok((client_id, registration))
+}
+
+/// Register a new client, as described in RFC 7591. Unless open registration
+/// is enabled, an initial access token is required.
+#[post("")]
+async fn register_client(
+	db: web::Data<MySqlPool>,
+	body: web::Json<ClientMetadata>,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+) -> Result<HttpResponse, RegistrationError> {
+	let db = db.get_ref();
+	let config = config::get_config().unwrap();
+
+	if !config.open_registration {
+		let Some(authorization) = authorization else {
+			yeet!(RegistrationError::invalid_token());
+		};
+		let token_hash = crypto::token_hash(authorization.token());
+		if !db::use_initial_access_token(db, &token_hash).await.unwrap() {
+			yeet!(RegistrationError::invalid_token());
+		}
+	}
+
+	let mut metadata = body.0;
+	let settings = validate_metadata(&mut metadata)?;
+
+	// the client ID that the client knows itself by is a random UUID
+	let id = loop {
+		let id = id::new_id(db, db::client_id_exists).await.unwrap();
+		if !db::client_alias_exists(db, &id.to_string()).await.unwrap() {
+			break id;
+		}
+	};
+	let alias = id.to_string();
+
+	let secret = settings
+		.auth_method
+		.uses_secret()
+		.then(crypto::random_token);
+	let client = Client::new(
+		id,
+		ClientOptions {
+			alias: &alias,
+			ty: settings.client_type,
+			secret: secret.as_deref(),
+			default_scopes: (!settings.allowed_scopes.is_empty())
+				.then(|| settings.allowed_scopes.clone()),
+			allowed_scopes: settings.allowed_scopes,
+			redirect_uris: &metadata.redirect_uris,
+			trusted: false,
+			require_pkce: settings.client_type == ClientType::Public,
+			require_par: metadata.require_pushed_authorization_requests,
+			jwks: metadata.jwks.clone(),
+			auth_method: settings.auth_method,
+			tls_client_auth_san_dns: metadata.tls_client_auth_san_dns.clone(),
+			backchannel_client_notification_endpoint: metadata
+				.backchannel_client_notification_endpoint
+				.clone(),
+		},
+	)
+	.map_err(|e| RegistrationError::invalid_redirect_uri(e.unwrap()))?;
+
+	// the client and its registration are saved together. Each of the nested
+	// transactions only commits to the outer one.
+	let mut transaction = db.begin().await.unwrap();
+	db::create_client(transaction.begin().await.unwrap(), &client)
+		.await
+		.unwrap();
+	db::update_client_authorization_details_types(
+		transaction.begin().await.unwrap(),
+		id,
+		&metadata.authorization_details_types,
+	)
+	.await
+	.unwrap();
+
+	let registration_access_token = crypto::random_token();
+	let issued_at = Utc::now();
+	db::create_client_registration(
+		transaction.as_mut(),
+		id,
+		&crypto::token_hash(&registration_access_token),
+		&serde_json::to_string(&metadata).unwrap(),
+		issued_at,
+	)
+	.await
+	.unwrap();
+	transaction.commit().await.unwrap();
+
+	let registration_client_uri = registration_client_uri(&alias);
+	let response = ClientInformation {
+		client_id: alias.into_boxed_str(),
+		client_secret_expires_at: secret.as_ref().map(|_| 0),
+		client_secret: secret,
+		client_id_issued_at: issued_at.timestamp(),
+		registration_access_token: Some(registration_access_token),
+		registration_client_uri: registration_client_uri.clone(),
+		metadata,
+	};
+	Ok(HttpResponse::Created()
+		.insert_header((header::LOCATION, registration_client_uri.to_string()))
+		.insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response))
+}
+
+/// Read the client's current configuration, as described in RFC 7592
+#[get("/{client_id}")]
+async fn get_client_configuration(
+	db: web::Data<MySqlPool>,
+	client_alias: web::Path<Box<str>>,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+) -> Result<HttpResponse, RegistrationError> {
+	let db = db.get_ref();
+	let (_, registration) =
+		authenticate_registration(db, &client_alias, authorization.as_deref()).await?;
+
+	let metadata = serde_json::from_str(&registration.metadata).unwrap();
+	let response = ClientInformation {
+		client_id: client_alias.clone(),
+		client_secret: None,
+		client_id_issued_at: registration.issued_at.timestamp(),
+		client_secret_expires_at: None,
+		registration_access_token: None,
+		registration_client_uri: registration_client_uri(&client_alias),
+		metadata,
+	};
+	Ok(HttpResponse::Ok()
+		.insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response))
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ClientUpdateRequest {
+	client_id: Box<str>,
+	#[serde(flatten)]
+	metadata: ClientMetadata,
+}
+
+/// Replace the client's metadata, as described in RFC 7592. A new client secret
+/// is only issued if the client switches to a method which uses a secret.
+#[put("/{client_id}")]
+async fn update_client_configuration(
+	db: web::Data<MySqlPool>,
+	client_alias: web::Path<Box<str>>,
+	body: web::Json<ClientUpdateRequest>,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+) -> Result<HttpResponse, RegistrationError> {
+	let db = db.get_ref();
+	let (id, registration) =
+		authenticate_registration(db, &client_alias, authorization.as_deref()).await?;
+
+	if body.client_id != *client_alias {
+		yeet!(RegistrationError::invalid_client_metadata(
+			"The client ID doesn't match the client configuration endpoint"
+		));
+	}
+
+	let mut metadata = body.0.metadata;
+	let settings = validate_metadata(&mut metadata)?;
+
+	let old_auth_method = db::get_client_auth_method(db, id).await.unwrap().unwrap();
+	let secret = if old_auth_method != settings.auth_method {
+		let secret = settings
+			.auth_method
+			.uses_secret()
+			.then(crypto::random_token);
+		let hash = secret
+			.as_deref()
+			.map(PasswordHash::new)
+			.transpose()
+			.unwrap();
+		let ciphertext = secret
+			.as_deref()
+			.filter(|_| settings.auth_method == AuthMethod::ClientSecretJwt)
+			.map(crypto::encrypt_secret)
+			.transpose()
+			.unwrap();
+		db::update_client_type(db, id, settings.client_type)
+			.await
+			.unwrap();
+		db::update_client_auth_method(db, id, settings.auth_method)
+			.await
+			.unwrap();
+		db::update_client_secret(db, id, hash).await.unwrap();
+		db::update_client_secret_ciphertext(db, id, ciphertext.as_deref())
+			.await
+			.unwrap();
+		secret
+	} else {
+		None
+	};
+
+	let scopes = settings.allowed_scopes.join(" ");
+	db::update_client_allowed_scopes(db, id, &scopes)
+		.await
+		.unwrap();
+	let default_scopes = (!scopes.is_empty()).then_some(scopes);
+	db::update_client_default_scopes(db, id, default_scopes)
+		.await
+		.unwrap();
+	db::update_client_require_pkce(db, id, settings.client_type == ClientType::Public)
+		.await
+		.unwrap();
+	db::update_client_require_par(db, id, metadata.require_pushed_authorization_requests)
+		.await
+		.unwrap();
+	db::update_client_jwks(db, id, metadata.jwks.as_ref())
+		.await
+		.unwrap();
+	db::update_client_tls_client_auth_san_dns(db, id, metadata.tls_client_auth_san_dns.as_deref())
+		.await
+		.unwrap();
+	db::update_client_backchannel_client_notification_endpoint(
+		db,
+		id,
+		metadata.backchannel_client_notification_endpoint.as_ref(),
+	)
+	.await
+	.unwrap();
+	let transaction = db.begin().await.unwrap();
+	db::update_client_redirect_uris(transaction, id, &metadata.redirect_uris)
+		.await
+		.unwrap();
+	let transaction = db.begin().await.unwrap();
+	db::update_client_authorization_details_types(
+		transaction,
+		id,
+		&metadata.authorization_details_types,
+	)
+	.await
+	.unwrap();
+	db::update_client_registration_metadata(db, id, &serde_json::to_string(&metadata).unwrap())
+		.await
+		.unwrap();
+
+	let response = ClientInformation {
+		client_id: client_alias.clone(),
+		client_secret_expires_at: secret.as_ref().map(|_| 0),
+		client_secret: secret,
+		client_id_issued_at: registration.issued_at.timestamp(),
+		registration_access_token: None,
+		registration_client_uri: registration_client_uri(&client_alias),
+		metadata,
+	};
+	Ok(HttpResponse::Ok()
+		.insert_header(header::CacheControl(vec![header::CacheDirective::NoStore]))
+		.insert_header((header::PRAGMA, "no-cache"))
+		.json(response))
+}
+
+/// Delete the client, as described in RFC 7592
+#[delete("/{client_id}")]
+async fn delete_client_configuration(
+	db: web::Data<MySqlPool>,
+	client_alias: web::Path<Box<str>>,
+	authorization: Option<web::Header<authorization::BearerAuthorization>>,
+) -> Result<HttpResponse, RegistrationError> {
+	let db = db.get_ref();
+	let (id, _) = authenticate_registration(db, &client_alias, authorization.as_deref()).await?;
+
+	let transaction = db.begin().await.unwrap();
+	db::delete_client(transaction, id).await.unwrap();
+
+	Ok(HttpResponse::NoContent().finish())
+}
+
+pub fn service() -> Scope {
+	web::scope("/oauth/register")
+		.service(register_client)
+		.service(get_client_configuration)
+		.service(update_client_configuration)
+		.service(delete_client_configuration)
+}
@@ -0,0 +1,269 @@
+use actix_web::http::{header, StatusCode};
+use actix_web::{delete, get, post, put, web, HttpResponse, ResponseError, Scope};
+use raise::yeet;
+use serde::{Deserialize, Serialize};
+use sqlx::MySqlPool;
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+use crate::models::resource_server::ResourceServer;
+use crate::services::{db, id};
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceServerResponse {
+	id: Uuid,
+	uri: Url,
+	scopes: Box<[Box<str>]>,
+}
+
+impl From<ResourceServer> for ResourceServerResponse {
+	fn from(resource_server: ResourceServer) -> Self {
+		Self {
+			id: resource_server.id,
+			uri: resource_server.uri,
+			scopes: resource_server
+				.scopes
+				.split_whitespace()
+				.map(Box::from)
+				.collect(),
+		}
+	}
+}
+
+#[get("")]
+async fn get_resource_servers(conn: web::Data<MySqlPool>) -> HttpResponse {
+	let conn = conn.get_ref();
+
+	let results: Box<[ResourceServerResponse]> = db::get_resource_servers(conn)
+		.await
+		.unwrap()
+		.into_vec()
+		.into_iter()
+		.map(|r| r.into())
+		.collect();
+
+	HttpResponse::Ok().json(results)
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("No resource server with the given ID exists")]
+struct ResourceServerNotFoundError {
+	id: Uuid,
+}
+
+impl ResponseError for ResourceServerNotFoundError {
+	fn status_code(&self) -> StatusCode {
+		StatusCode::NOT_FOUND
+	}
+}
+
+#[get("/{id}")]
+async fn get_resource_server(
+	id: web::Path<Uuid>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerNotFoundError> {
+	let conn = conn.get_ref();
+
+	let id = id.to_owned();
+	let Some(resource_server) = db::get_resource_server(conn, id).await.unwrap() else {
+		yeet!(ResourceServerNotFoundError { id });
+	};
+
+	let response: ResourceServerResponse = resource_server.into();
+	let response = HttpResponse::Ok().json(response);
+	Ok(response)
+}
+
+#[get("/{id}/scopes")]
+async fn get_resource_server_scopes(
+	id: web::Path<Uuid>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerNotFoundError> {
+	let conn = conn.get_ref();
+
+	let id = id.to_owned();
+	let Some(resource_server) = db::get_resource_server(conn, id).await.unwrap() else {
+		yeet!(ResourceServerNotFoundError { id });
+	};
+
+	let response: ResourceServerResponse = resource_server.into();
+	let response = HttpResponse::Ok().json(response.scopes);
+	Ok(response)
+}
+
+/// A request to register or update a resource server
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ResourceServerRequest {
+	uri: Url,
+	scopes: Box<[Box<str>]>,
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("A resource server with the given URI already exists.")]
+struct ResourceUriTakenError {
+	uri: Url,
+}
+
+impl ResponseError for ResourceUriTakenError {
+	fn status_code(&self) -> StatusCode {
+		StatusCode::CONFLICT
+	}
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("A resource URI must not have a fragment")]
+struct InvalidResourceUriError {
+	uri: Url,
+}
+
+impl ResponseError for InvalidResourceUriError {
+	fn status_code(&self) -> StatusCode {
+		StatusCode::BAD_REQUEST
+	}
+}
+
+#[derive(Debug, Clone, Error)]
+enum ResourceServerError {
+	#[error(transparent)]
+	UriTaken(#[from] ResourceUriTakenError),
+	#[error(transparent)]
+	InvalidUri(#[from] InvalidResourceUriError),
+	#[error(transparent)]
+	NotFound(#[from] ResourceServerNotFoundError),
+}
+
+impl ResponseError for ResourceServerError {
+	fn status_code(&self) -> StatusCode {
+		match self {
+			Self::UriTaken(e) => e.status_code(),
+			Self::InvalidUri(e) => e.status_code(),
+			Self::NotFound(e) => e.status_code(),
+		}
+	}
+}
+
+#[post("")]
+async fn create_resource_server(
+	body: web::Json<ResourceServerRequest>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerError> {
+	let conn = conn.get_ref();
+
+	let id = id::new_id(conn, db::resource_server_id_exists)
+		.await
+		.unwrap();
+	let uri = body.uri.clone();
+
+	// resource indicators can't have a fragment, as described in RFC 8707
+	if uri.fragment().is_some() {
+		yeet!(InvalidResourceUriError { uri }.into())
+	}
+
+	if db::resource_server_uri_is_used(conn, &uri).await.unwrap() {
+		yeet!(ResourceUriTakenError { uri }.into())
+	}
+
+	let resource_server = ResourceServer {
+		id,
+		uri,
+		scopes: body.scopes.join(" ").into_boxed_str(),
+	};
+
+	db::create_resource_server(conn, &resource_server)
+		.await
+		.unwrap();
+
+	let response = HttpResponse::Created()
+		.insert_header((header::LOCATION, format!("resource-servers/{id}")))
+		.finish();
+	Ok(response)
+}
+
+#[put("/{id}")]
+async fn update_resource_server(
+	id: web::Path<Uuid>,
+	body: web::Json<ResourceServerRequest>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerError> {
+	let conn = conn.get_ref();
+
+	let id = id.to_owned();
+	let uri = body.uri.clone();
+
+	let Some(old_resource_server) = db::get_resource_server(conn, id).await.unwrap() else {
+		yeet!(ResourceServerNotFoundError { id }.into())
+	};
+
+	if uri.fragment().is_some() {
+		yeet!(InvalidResourceUriError { uri }.into())
+	}
+
+	if uri != old_resource_server.uri && db::resource_server_uri_is_used(conn, &uri).await.unwrap()
+	{
+		yeet!(ResourceUriTakenError { uri }.into())
+	}
+
+	let resource_server = ResourceServer {
+		id,
+		uri,
+		scopes: body.scopes.join(" ").into_boxed_str(),
+	};
+
+	db::update_resource_server(conn, &resource_server)
+		.await
+		.unwrap();
+
+	let response = HttpResponse::NoContent().finish();
+	Ok(response)
+}
+
+#[put("/{id}/scopes")]
+async fn update_resource_server_scopes(
+	id: web::Path<Uuid>,
+	body: web::Json<Box<[Box<str>]>>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerNotFoundError> {
+	let conn = conn.get_ref();
+
+	let id = id.to_owned();
+	if !db::resource_server_id_exists(conn, id).await.unwrap() {
+		yeet!(ResourceServerNotFoundError { id })
+	}
+
+	db::update_resource_server_scopes(conn, id, &body.join(" "))
+		.await
+		.unwrap();
+
+	let response = HttpResponse::NoContent().finish();
+	Ok(response)
+}
+
+#[delete("/{id}")]
+async fn delete_resource_server(
+	id: web::Path<Uuid>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ResourceServerNotFoundError> {
+	let conn = conn.get_ref();
+
+	let id = id.to_owned();
+	if !db::delete_resource_server(conn, id).await.unwrap() {
+		yeet!(ResourceServerNotFoundError { id })
+	}
+
+	let response = HttpResponse::NoContent().finish();
+	Ok(response)
+}
+
+pub fn service() -> Scope {
+	web::scope("/resource-servers")
+		.service(get_resource_servers)
+		.service(get_resource_server)
+		.service(get_resource_server_scopes)
+		.service(create_resource_server)
+		.service(update_resource_server)
+		.service(update_resource_server_scopes)
+		.service(delete_resource_server)
+}
@@ -1,5 +1,6 @@
 use actix_web::http::{header, StatusCode};
-use actix_web::{get, post, put, web, HttpResponse, ResponseError, Scope};
+use actix_web::{delete, get, post, put, web, HttpResponse, ResponseError, Scope};
+use chrono::{DateTime, Utc};
 use raise::yeet;
 use serde::{Deserialize, Serialize};
 use sqlx::MySqlPool;
@@ -7,6 +8,7 @@ use thiserror::Error;
 use uuid::Uuid;
 
 use crate::models::user::User;
+use crate::services::consent::{self, Consent};
 use crate::services::crypto::PasswordHash;
 use crate::services::{db, id};
 
@@ -260,6 +262,79 @@ async fn update_password(
 	Ok(response)
 }
 
+/// The scopes that a user has allowed a client to have
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ConsentResponse {
+	client_id: Uuid,
+	scopes: Box<[Box<str>]>,
+	granted_at: DateTime<Utc>,
+}
+
+impl From<Consent> for ConsentResponse {
+	fn from(consent: Consent) -> Self {
+		Self {
+			client_id: consent.client_id,
+			scopes: consent.scopes.split_whitespace().map(Box::from).collect(),
+			granted_at: consent.granted_at,
+		}
+	}
+}
+
+#[get("/{user_id}/consents")]
+async fn get_consents(
+	user_id: web::Path<Uuid>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, UserNotFoundError> {
+	let conn = conn.get_ref();
+
+	let user_id = user_id.to_owned();
+	if !db::user_id_exists(conn, user_id).await.unwrap() {
+		yeet!(UserNotFoundError { user_id })
+	}
+
+	let consents: Box<[ConsentResponse]> = db::get_user_consents(conn, user_id)
+		.await
+		.unwrap()
+		.iter()
+		.cloned()
+		.map(|consent| consent.into())
+		.collect();
+
+	let response = HttpResponse::Ok().json(consents);
+	Ok(response)
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("The user has not given consent to the given client")]
+struct ConsentNotFoundError {
+	user_id: Uuid,
+	client_id: Uuid,
+}
+
+impl ResponseError for ConsentNotFoundError {
+	fn status_code(&self) -> StatusCode {
+		StatusCode::NOT_FOUND
+	}
+}
+
+/// Revoke the consent, and every token that the client was given under it
+#[delete("/{user_id}/consents/{client_id}")]
+async fn revoke_consent(
+	path: web::Path<(Uuid, Uuid)>,
+	conn: web::Data<MySqlPool>,
+) -> Result<HttpResponse, ConsentNotFoundError> {
+	let conn = conn.get_ref();
+
+	let (user_id, client_id) = path.into_inner();
+	if !consent::revoke(conn, user_id, client_id).await.unwrap() {
+		yeet!(ConsentNotFoundError { user_id, client_id })
+	}
+
+	let response = HttpResponse::NoContent().finish();
+	Ok(response)
+}
+
 pub fn service() -> Scope {
 	web::scope("/users")
 		.service(search_users)
@@ -269,4 +344,6 @@ pub fn service() -> Scope {
 		.service(update_user)
 		.service(update_username)
 		.service(update_password)
+		.service(get_consents)
+		.service(revoke_consent)
 }
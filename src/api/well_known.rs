@@ -0,0 +1,37 @@
+use actix_web::{get, web, HttpResponse, Scope};
+use sqlx::MySqlPool;
+
+use crate::api::ServerMetadata;
+use crate::services::{config, key_set};
+
+/// The authorization server metadata, so that clients don't need to hard-code
+/// any of the endpoints
+#[get("/oauth-authorization-server")]
+async fn oauth_authorization_server() -> HttpResponse {
+	let config = config::get_config().unwrap();
+	let metadata = ServerMetadata::new(config.url).unwrap();
+	HttpResponse::Ok().json(metadata)
+}
+
+/// The OpenID Provider metadata. This is the same document as the
+/// authorization server metadata.
+#[get("/openid-configuration")]
+async fn openid_configuration() -> HttpResponse {
+	let config = config::get_config().unwrap();
+	let metadata = ServerMetadata::new(config.url).unwrap();
+	HttpResponse::Ok().json(metadata)
+}
+
+/// The public keys which can be used to verify tokens issued by this server
+#[get("/jwks.json")]
+async fn jwks(db: web::Data<MySqlPool>) -> HttpResponse {
+	let jwks = key_set::published_keys(&db).await.unwrap();
+	HttpResponse::Ok().json(jwks)
+}
+
+pub fn service() -> Scope {
+	web::scope("/.well-known")
+		.service(oauth_authorization_server)
+		.service(openid_configuration)
+		.service(jwks)
+}
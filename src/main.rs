@@ -42,6 +42,33 @@ async fn delete_expired_tokens(db: MySqlPool) {
 		if let Err(e) = db::delete_expired_refresh_tokens(&db).await {
 			log::error!("{e}");
 		}
+		if let Err(e) = db::delete_expired_signing_keys(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_device_codes(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_backchannel_authentications(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_consent_requests(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_initial_access_tokens(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_pushed_authorizations(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_dpop_proofs(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_client_assertions(&db).await {
+			log::error!("{e}");
+		}
+		if let Err(e) = db::delete_expired_jwt_bearer_assertions(&db).await {
+			log::error!("{e}");
+		}
 		if let Err(e) =
 			db::delete_old_login_attempts_before(&db, Utc::now() - chrono::Duration::hours(1)).await
 		{
@@ -62,6 +89,25 @@ struct Opts {
 		display_fallback
 	)]
 	env: config::Environment,
+	#[bpaf(external, optional)]
+	command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Bpaf)]
+enum Command {
+	/// Retire the active signing key, activate the next key, and generate a new
+	/// next key. Tokens signed by the retired key stay valid until they expire.
+	#[bpaf(command("roll-keys"))]
+	RollKeys {
+		/// The algorithm to use for the new next key. Must be one of: RS256,
+		/// ES256, EdDSA. Defaults to the algorithm of the active key.
+		#[bpaf(argument("ALGORITHM"))]
+		algorithm: Option<keys::Algorithm>,
+	},
+	/// Create a single-use token which allows a client to be registered. The
+	/// token expires after one week.
+	#[bpaf(command("create-initial-access-token"))]
+	CreateInitialAccessToken,
 }
 
 #[actix_web::main]
@@ -77,14 +123,36 @@ async fn main() -> Result<(), RawUnexpected> {
 	let db_url = secrets::database_url()?;
 	let sql_pool = db::initialize(&db_url).await?;
 
+	match args.command {
+		Some(Command::RollKeys { algorithm }) => {
+			let kid = key_set::roll_keys(&sql_pool, algorithm).await?;
+			println!("The active signing key is now {kid}");
+			return Ok(());
+		}
+		Some(Command::CreateInitialAccessToken) => {
+			let token = crypto::random_token();
+			let exp = Utc::now() + chrono::Duration::weeks(1);
+			db::create_initial_access_token(&sql_pool, &crypto::token_hash(&token), exp).await?;
+			println!("{token}");
+			return Ok(());
+		}
+		None => (),
+	}
+
+	key_set::initialize(&sql_pool).await?;
+
 	let tera = templates::initialize()?;
 
 	let translations = languages::initialize()?;
 
 	actix_rt::spawn(delete_expired_tokens(sql_pool.clone()));
 
+	let config = config::get_config()?;
+	let notifier = backchannel_notifier::initialize(&config.backchannel);
+	let trusted_issuers = jwt_bearer::initialize(&config.trusted_issuers)?;
+
 	// start the server
-	HttpServer::new(move || {
+	let server = HttpServer::new(move || {
 		App::new()
 			// middleware
 			.wrap(ErrorHandlers::new().default_handler(error_content_language))
@@ -94,6 +162,8 @@ async fn main() -> Result<(), RawUnexpected> {
 			.app_data(Data::new(sql_pool.clone()))
 			.app_data(Data::new(tera.clone()))
 			.app_data(Data::new(translations.clone()))
+			.app_data(Data::from(notifier.clone()))
+			.app_data(Data::new(trusted_issuers.clone()))
 			// frontend services
 			.service(style::get_css)
 			.service(scripts::get_js)
@@ -102,12 +172,21 @@ async fn main() -> Result<(), RawUnexpected> {
 			.service(api::liveops())
 			.service(api::users())
 			.service(api::clients())
+			.service(api::resource_servers())
+			.service(api::registration())
 			.service(api::oauth())
+			.service(api::device())
+			.service(api::backchannel())
+			.service(api::well_known())
 	})
-	.shutdown_timeout(1)
-	.bind(("127.0.0.1", 8080))?
-	.run()
-	.await?;
+	.on_connect(tls::on_connect)
+	.shutdown_timeout(1);
+
+	let server = match &config.tls {
+		Some(tls) => server.bind_rustls(("127.0.0.1", 8080), tls::server_config(tls)?)?,
+		None => server.bind(("127.0.0.1", 8080))?,
+	};
+	server.run().await?;
 
 	Ok(())
 }
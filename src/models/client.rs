@@ -9,7 +9,8 @@ use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 
-use crate::services::crypto::PasswordHash;
+use crate::services::crypto::{self, PasswordHash};
+use crate::services::keys::JwkSet;
 
 /// There are two types of clients, based on their ability to maintain the
 /// security of their client credentials.
@@ -78,16 +79,84 @@ impl From<String> for ClientType {
 	}
 }
 
+/// How a client authenticates to the token endpoint, using the names from the
+/// OAuth Token Endpoint Authentication Methods registry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(rename_all = "snake_case")]
+pub enum AuthMethod {
+	/// The client secret is sent in the HTTP Authorization header
+	ClientSecretBasic,
+	/// The client secret is sent in the request body
+	ClientSecretPost,
+	/// The client presents a certificate issued by a trusted certificate
+	/// authority, as described in RFC 8705
+	TlsClientAuth,
+	/// The client presents a certificate which it registered in its key set,
+	/// as described in RFC 8705
+	SelfSignedTlsClientAuth,
+	/// The client sends a JWT which is MAC'd with its client secret, as
+	/// described in RFC 7523
+	ClientSecretJwt,
+	/// The client sends a JWT which is signed by a key in its key set, as
+	/// described in RFC 7523
+	PrivateKeyJwt,
+	/// Public clients don't authenticate
+	None,
+}
+
+impl AuthMethod {
+	/// The default method for each type of client
+	pub fn default_for(ty: ClientType) -> Self {
+		match ty {
+			ClientType::Confidential => Self::ClientSecretBasic,
+			ClientType::Public => Self::None,
+		}
+	}
+
+	/// Returns `true` if the client needs a client secret to use this method
+	pub fn uses_secret(self) -> bool {
+		matches!(
+			self,
+			Self::ClientSecretBasic | Self::ClientSecretPost | Self::ClientSecretJwt
+		)
+	}
+}
+
+impl FromStr for AuthMethod {
+	type Err = ();
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"client_secret_basic" => Ok(Self::ClientSecretBasic),
+			"client_secret_post" => Ok(Self::ClientSecretPost),
+			"tls_client_auth" => Ok(Self::TlsClientAuth),
+			"self_signed_tls_client_auth" => Ok(Self::SelfSignedTlsClientAuth),
+			"client_secret_jwt" => Ok(Self::ClientSecretJwt),
+			"private_key_jwt" => Ok(Self::PrivateKeyJwt),
+			"none" => Ok(Self::None),
+			_ => Err(()),
+		}
+	}
+}
+
 #[derive(Debug, Clone)]
 pub struct Client {
 	id: Uuid,
 	ty: ClientType,
 	alias: Box<str>,
 	secret: Option<PasswordHash>,
+	secret_ciphertext: Option<Box<[u8]>>,
 	allowed_scopes: Box<[Box<str>]>,
 	default_scopes: Option<Box<[Box<str>]>>,
 	redirect_uris: Box<[Url]>,
 	trusted: bool,
+	require_pkce: bool,
+	require_par: bool,
+	jwks: Option<JwkSet>,
+	auth_method: AuthMethod,
+	tls_client_auth_san_dns: Option<Box<str>>,
+	backchannel_client_notification_endpoint: Option<Url>,
 }
 
 impl PartialEq for Client {
@@ -117,6 +186,22 @@ pub enum CreateClientError {
 	NonHttpsUri,
 	#[error("The default scope is not a subset of the allowed scopes for this client")]
 	ImpermissibleDefaultScopes,
+	#[error("The key set contains a key that isn't a supported public key")]
+	UnsupportedKey,
+	#[error(
+		"Public clients must use the none authentication method, and confidential clients must not"
+	)]
+	AuthMethodMismatch,
+	#[error("Clients using tls_client_auth must have a DNS name for their certificate")]
+	NoTlsSubject,
+	#[error("Clients using self_signed_tls_client_auth must have a certificate in their key set")]
+	NoCertificate,
+	#[error("Clients using private_key_jwt must have a key set")]
+	NoClientKeys,
+	#[error("The client secret must be set again when switching to client_secret_jwt")]
+	NoSecretCiphertext,
+	#[error("The backchannel client notification endpoint must use HTTPS")]
+	NonHttpsNotificationEndpoint,
 }
 
 impl ResponseError for CreateClientError {
@@ -125,27 +210,77 @@ impl ResponseError for CreateClientError {
 	}
 }
 
+/// Everything needed to create a client, other than its ID
+#[derive(Debug, Clone)]
+pub struct ClientOptions<'a> {
+	pub alias: &'a str,
+	pub ty: ClientType,
+	pub secret: Option<&'a str>,
+	pub allowed_scopes: Box<[Box<str>]>,
+	pub default_scopes: Option<Box<[Box<str>]>>,
+	pub redirect_uris: &'a [Url],
+	pub trusted: bool,
+	pub require_pkce: bool,
+	pub require_par: bool,
+	pub jwks: Option<JwkSet>,
+	pub auth_method: AuthMethod,
+	pub tls_client_auth_san_dns: Option<Box<str>>,
+	pub backchannel_client_notification_endpoint: Option<Url>,
+}
+
 impl Client {
-	pub fn new(
-		id: Uuid,
-		alias: &str,
-		ty: ClientType,
-		secret: Option<&str>,
-		allowed_scopes: Box<[Box<str>]>,
-		default_scopes: Option<Box<[Box<str>]>>,
-		redirect_uris: &[Url],
-		trusted: bool,
-	) -> Result<Self, Expect<CreateClientError>> {
+	pub fn new(id: Uuid, options: ClientOptions<'_>) -> Result<Self, Expect<CreateClientError>> {
+		let ClientOptions {
+			alias,
+			ty,
+			secret,
+			allowed_scopes,
+			default_scopes,
+			redirect_uris,
+			trusted,
+			require_pkce,
+			require_par,
+			jwks,
+			auth_method,
+			tls_client_auth_san_dns,
+			backchannel_client_notification_endpoint,
+		} = options;
+
+		// only client_secret_jwt needs the secret itself, rather than a hash
+		let secret_ciphertext = match secret {
+			Some(secret) if auth_method == AuthMethod::ClientSecretJwt => {
+				Some(crypto::encrypt_secret(secret)?)
+			}
+			_ => None,
+		};
 		let secret = if let Some(secret) = secret {
 			Some(PasswordHash::new(secret)?)
 		} else {
 			None
 		};
 
-		if ty == ClientType::Confidential && secret.is_none() {
+		if (ty == ClientType::Public) != (auth_method == AuthMethod::None) {
+			yeet!(CreateClientError::AuthMethodMismatch.into());
+		}
+
+		if auth_method.uses_secret() && secret.is_none() {
 			yeet!(CreateClientError::NoSecret.into());
 		}
 
+		if auth_method == AuthMethod::TlsClientAuth && tls_client_auth_san_dns.is_none() {
+			yeet!(CreateClientError::NoTlsSubject.into());
+		}
+
+		if auth_method == AuthMethod::SelfSignedTlsClientAuth
+			&& !jwks.as_ref().is_some_and(JwkSet::has_certificates)
+		{
+			yeet!(CreateClientError::NoCertificate.into());
+		}
+
+		if auth_method == AuthMethod::PrivateKeyJwt && jwks.is_none() {
+			yeet!(CreateClientError::NoClientKeys.into());
+		}
+
 		if ty == ClientType::Public && trusted {
 			yeet!(CreateClientError::TrustedError.into());
 		}
@@ -158,6 +293,17 @@ impl Client {
 			}
 		}
 
+		if jwks.as_ref().is_some_and(|jwks| !jwks.is_valid()) {
+			yeet!(CreateClientError::UnsupportedKey.into());
+		}
+
+		if backchannel_client_notification_endpoint
+			.as_ref()
+			.is_some_and(|endpoint| endpoint.scheme() != "https")
+		{
+			yeet!(CreateClientError::NonHttpsNotificationEndpoint.into())
+		}
+
 		for redirect_uri in redirect_uris {
 			if redirect_uri.scheme() != "https" {
 				yeet!(CreateClientError::NonHttpsUri.into())
@@ -173,10 +319,17 @@ impl Client {
 			alias: Box::from(alias),
 			ty,
 			secret,
+			secret_ciphertext,
 			allowed_scopes,
 			default_scopes,
 			redirect_uris: redirect_uris.into_iter().cloned().collect(),
 			trusted,
+			require_pkce,
+			require_par,
+			jwks,
+			auth_method,
+			tls_client_auth_san_dns,
+			backchannel_client_notification_endpoint,
 		})
 	}
 
@@ -208,6 +361,12 @@ impl Client {
 		self.secret.as_ref().map(|s| s.version())
 	}
 
+	/// The encrypted client secret, which is needed to check assertions from
+	/// clients using `client_secret_jwt`
+	pub fn secret_ciphertext(&self) -> Option<&[u8]> {
+		self.secret_ciphertext.as_deref()
+	}
+
 	pub fn allowed_scopes(&self) -> String {
 		self.allowed_scopes.join(" ")
 	}
@@ -220,6 +379,35 @@ impl Client {
 		self.trusted
 	}
 
+	pub fn requires_pkce(&self) -> bool {
+		self.require_pkce
+	}
+
+	pub fn requires_par(&self) -> bool {
+		self.require_par
+	}
+
+	/// The public keys which the client signs its requests with
+	pub fn jwks(&self) -> Option<&JwkSet> {
+		self.jwks.as_ref()
+	}
+
+	pub fn auth_method(&self) -> AuthMethod {
+		self.auth_method
+	}
+
+	/// The DNS name that the client's certificate must be issued for, if the
+	/// client uses `tls_client_auth`
+	pub fn tls_client_auth_san_dns(&self) -> Option<&str> {
+		self.tls_client_auth_san_dns.as_deref()
+	}
+
+	/// The endpoint which is pinged when a backchannel authentication request
+	/// is finished. If this isn't set, then the client polls for its tokens.
+	pub fn backchannel_client_notification_endpoint(&self) -> Option<&Url> {
+		self.backchannel_client_notification_endpoint.as_ref()
+	}
+
 	pub fn check_secret(&self, secret: &str) -> Option<Result<bool, RawUnexpected>> {
 		self.secret.as_ref().map(|s| s.check_password(secret))
 	}
@@ -1,2 +1,3 @@
 pub mod client;
+pub mod resource_server;
 pub mod user;
@@ -0,0 +1,38 @@
+use std::hash::Hash;
+
+use url::Url;
+use uuid::Uuid;
+
+/// An API which accepts access tokens from this server, as described in
+/// RFC 8707. Tokens which are requested for a resource server can only be used
+/// there, and only with the scopes which it owns.
+#[derive(Debug, Clone)]
+pub struct ResourceServer {
+	pub id: Uuid,
+	pub uri: Url,
+	pub scopes: Box<str>,
+}
+
+impl PartialEq for ResourceServer {
+	fn eq(&self, other: &Self) -> bool {
+		self.id == other.id
+	}
+}
+
+impl Eq for ResourceServer {}
+
+impl Hash for ResourceServer {
+	fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+		state.write_u128(self.id.as_u128())
+	}
+}
+
+impl ResourceServer {
+	pub fn uri(&self) -> &Url {
+		&self.uri
+	}
+
+	pub fn scopes(&self) -> &str {
+		&self.scopes
+	}
+}
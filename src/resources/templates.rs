@@ -1,12 +1,14 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 
 use exun::{RawUnexpected, ResultErrorExt};
 use raise::yeet;
 use serde::Serialize;
 use tera::{Function, Tera, Value};
 use unic_langid::subtags::Language;
+use url::Url;
 
-use crate::api::AuthorizationParameters;
+use crate::api::AuthorizationRequest;
+use crate::services::authorization_details::AuthorizationDetails;
 
 use super::languages;
 
@@ -45,6 +47,11 @@ pub enum ErrorPage {
 	ClientNotFound,
 	MissingRedirectUri,
 	InvalidRedirectUri,
+	PushedAuthorizationRequired,
+	InvalidRequestUri,
+	InvalidRequestObject,
+	ConflictingRequestObject,
+	InvalidConsentRequest,
 	InternalServerError,
 }
 
@@ -72,7 +79,8 @@ pub fn error_page(
 
 pub fn login_page(
 	tera: &Tera,
-	params: &AuthorizationParameters,
+	params: &AuthorizationRequest,
+	authorization_details: Option<&AuthorizationDetails>,
 	language: Language,
 	mut translations: languages::Translations,
 ) -> Result<String, RawUnexpected> {
@@ -82,12 +90,16 @@ pub fn login_page(
 	let mut context = tera::Context::new();
 	context.insert("lang", language.as_str());
 	context.insert("params", &serde_urlencoded::to_string(params)?);
+	if let Some(authorization_details) = authorization_details {
+		context.insert("authorizationDetails", authorization_details);
+	}
 	tera.render("login.html", &context).unexpect()
 }
 
 pub fn login_error_page(
 	tera: &Tera,
-	params: &AuthorizationParameters,
+	params: &AuthorizationRequest,
+	authorization_details: Option<&AuthorizationDetails>,
 	language: Language,
 	mut translations: languages::Translations,
 ) -> Result<String, RawUnexpected> {
@@ -97,6 +109,156 @@ pub fn login_error_page(
 	let mut context = tera::Context::new();
 	context.insert("lang", language.as_str());
 	context.insert("params", &serde_urlencoded::to_string(params)?);
+	if let Some(authorization_details) = authorization_details {
+		context.insert("authorizationDetails", authorization_details);
+	}
 	context.insert("errorMessage", "loginErrorMessage");
 	tera.render("login.html", &context).unexpect()
 }
+
+pub fn device_page(
+	tera: &Tera,
+	user_code: Option<&str>,
+	error_message: Option<&str>,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	context.insert("userCode", user_code.unwrap_or_default());
+	if let Some(error_message) = error_message {
+		context.insert("errorMessage", error_message);
+	}
+	tera.render("device.html", &context).unexpect()
+}
+
+pub fn device_complete_page(
+	tera: &Tera,
+	approved: bool,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let message = if approved {
+		"deviceApprovedMessage"
+	} else {
+		"deviceDeniedMessage"
+	};
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	context.insert("message", message);
+	tera.render("device_complete.html", &context).unexpect()
+}
+
+pub fn backchannel_page(
+	tera: &Tera,
+	auth_req_id: Option<&str>,
+	client_alias: Option<&str>,
+	binding_message: Option<&str>,
+	error_message: Option<&str>,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	if let Some(auth_req_id) = auth_req_id {
+		context.insert("authReqId", auth_req_id);
+	}
+	if let Some(client_alias) = client_alias {
+		context.insert("clientAlias", client_alias);
+	}
+	if let Some(binding_message) = binding_message {
+		context.insert("bindingMessage", binding_message);
+	}
+	if let Some(error_message) = error_message {
+		context.insert("errorMessage", error_message);
+	}
+	tera.render("backchannel.html", &context).unexpect()
+}
+
+pub fn backchannel_complete_page(
+	tera: &Tera,
+	approved: bool,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let message = if approved {
+		"backchannelApprovedMessage"
+	} else {
+		"backchannelDeniedMessage"
+	};
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	context.insert("message", message);
+	tera.render("backchannel_complete.html", &context)
+		.unexpect()
+}
+
+/// A page which posts the authorization response to the client, as described
+/// in OAuth 2.0 Form Post Response Mode
+pub fn form_post_page(
+	tera: &Tera,
+	redirect_uri: &Url,
+	params: &BTreeMap<String, String>,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	context.insert("redirectUri", redirect_uri.as_str());
+	context.insert("params", params);
+	tera.render("form_post.html", &context).unexpect()
+}
+
+#[derive(Serialize)]
+struct ScopeDescription<'a> {
+	name: &'a str,
+	description: Option<String>,
+}
+
+/// The page where a user who has logged in decides whether to give the client
+/// the scopes and authorization details that it asked for
+pub fn consent_page(
+	tera: &Tera,
+	params: &AuthorizationRequest,
+	consent_request: &str,
+	scope: &str,
+	authorization_details: Option<&AuthorizationDetails>,
+	language: Language,
+	mut translations: languages::Translations,
+) -> Result<String, RawUnexpected> {
+	translations.refresh()?;
+	let scopes = scope
+		.split_whitespace()
+		.map(|name| ScopeDescription {
+			name,
+			description: translations.get_message(language, &format!("scopeDescription_{name}")),
+		})
+		.collect::<Vec<_>>();
+	let mut tera = extend_tera(tera, language, translations)?;
+	tera.full_reload()?;
+	let mut context = tera::Context::new();
+	context.insert("lang", language.as_str());
+	context.insert("params", &serde_urlencoded::to_string(params)?);
+	context.insert("consentRequest", consent_request);
+	context.insert("clientAlias", params.client_id());
+	context.insert("scopes", &scopes);
+	if let Some(authorization_details) = authorization_details {
+		context.insert("authorizationDetails", authorization_details);
+	}
+	tera.render("consent.html", &context).unexpect()
+}
@@ -5,6 +5,15 @@ use crate::models::{client::Client, user::User};
 
 mod admin;
 
+/// Every scope that the server understands
+pub const SUPPORTED: &[&str] = &["admin", OPENID, PROFILE];
+
+/// The scope which asks for an OpenID Connect ID token
+pub const OPENID: &str = "openid";
+
+/// The scope which allows the client to read the user's profile
+pub const PROFILE: &str = "profile";
+
 /// The action which was attempted on a resource
 pub enum Action<T> {
 	Create(T),
@@ -95,6 +104,34 @@ pub fn is_subset_of(left_scopes: &str, right_scopes: &str) -> bool {
 	true
 }
 
+/// Only the scopes in `left_scopes` which are also in `right_scopes`, in the
+/// order that they appear in `left_scopes`
+pub fn intersection(left_scopes: &str, right_scopes: &str) -> String {
+	let right_scopes: HashSet<&str> = right_scopes.split_whitespace().collect();
+
+	left_scopes
+		.split_whitespace()
+		.filter(|scope| right_scopes.contains(scope))
+		.collect::<Vec<_>>()
+		.join(" ")
+}
+
+/// Every scope in `left_scopes`, followed by the scopes in `right_scopes` which
+/// aren't already in `left_scopes`
+pub fn union(left_scopes: &str, right_scopes: &str) -> String {
+	let mut scopes: Vec<&str> = Vec::new();
+	for scope in left_scopes
+		.split_whitespace()
+		.chain(right_scopes.split_whitespace())
+	{
+		if !scopes.contains(&scope) {
+			scopes.push(scope);
+		}
+	}
+
+	scopes.join(" ")
+}
+
 pub fn has_user_permission(
 	user: User,
 	action: Action<User>,
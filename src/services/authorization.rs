@@ -80,3 +80,148 @@ impl BasicAuthorization {
 		&self.password
 	}
 }
+
+/// An access token, sent with the `Bearer` scheme from RFC 6750
+#[derive(Clone)]
+pub struct BearerAuthorization {
+	token: Box<str>,
+}
+
+impl TryIntoHeaderValue for BearerAuthorization {
+	type Error = InvalidHeaderValue;
+
+	fn try_into_value(self) -> Result<HeaderValue, Self::Error> {
+		let token = self.token;
+		let value = format!("Bearer {token}");
+		HeaderValue::from_str(&value)
+	}
+}
+
+impl Header for BearerAuthorization {
+	fn name() -> HeaderName {
+		header::AUTHORIZATION
+	}
+
+	fn parse<M: actix_web::HttpMessage>(msg: &M) -> Result<Self, actix_web::error::ParseError> {
+		let Some(value) = msg.headers().get(Self::name()) else {
+			yeet!(ParseError::Header)
+		};
+
+		let Ok(value) = value.to_str() else {
+			yeet!(ParseError::Header)
+		};
+
+		let Some(token) = value.strip_prefix("Bearer") else {
+			yeet!(ParseError::Header)
+		};
+
+		let token = token.trim_start();
+		if token.is_empty() {
+			yeet!(ParseError::Header);
+		}
+
+		Ok(Self {
+			token: Box::from(token),
+		})
+	}
+}
+
+impl BearerAuthorization {
+	pub fn token(&self) -> &str {
+		&self.token
+	}
+}
+
+/// An access token which is bound to a key, sent with the `DPoP` scheme from
+/// RFC 9449
+#[derive(Clone)]
+pub struct DpopAuthorization {
+	token: Box<str>,
+}
+
+impl TryIntoHeaderValue for DpopAuthorization {
+	type Error = InvalidHeaderValue;
+
+	fn try_into_value(self) -> Result<HeaderValue, Self::Error> {
+		let token = self.token;
+		let value = format!("DPoP {token}");
+		HeaderValue::from_str(&value)
+	}
+}
+
+impl Header for DpopAuthorization {
+	fn name() -> HeaderName {
+		header::AUTHORIZATION
+	}
+
+	fn parse<M: actix_web::HttpMessage>(msg: &M) -> Result<Self, actix_web::error::ParseError> {
+		let Some(value) = msg.headers().get(Self::name()) else {
+			yeet!(ParseError::Header)
+		};
+
+		let Ok(value) = value.to_str() else {
+			yeet!(ParseError::Header)
+		};
+
+		let Some(token) = value.strip_prefix("DPoP") else {
+			yeet!(ParseError::Header)
+		};
+
+		let token = token.trim_start();
+		if token.is_empty() {
+			yeet!(ParseError::Header);
+		}
+
+		Ok(Self {
+			token: Box::from(token),
+		})
+	}
+}
+
+impl DpopAuthorization {
+	pub fn token(&self) -> &str {
+		&self.token
+	}
+}
+
+/// The `DPoP` header, which holds a proof that the client has the private key
+/// that its tokens are bound to. Only one proof may be sent.
+#[derive(Clone)]
+pub struct DpopProof {
+	proof: Box<str>,
+}
+
+impl TryIntoHeaderValue for DpopProof {
+	type Error = InvalidHeaderValue;
+
+	fn try_into_value(self) -> Result<HeaderValue, Self::Error> {
+		HeaderValue::from_str(&self.proof)
+	}
+}
+
+impl Header for DpopProof {
+	fn name() -> HeaderName {
+		HeaderName::from_static("dpop")
+	}
+
+	fn parse<M: actix_web::HttpMessage>(msg: &M) -> Result<Self, actix_web::error::ParseError> {
+		let mut values = msg.headers().get_all(Self::name());
+		let (Some(value), None) = (values.next(), values.next()) else {
+			yeet!(ParseError::Header)
+		};
+
+		let Ok(value) = value.to_str() else {
+			yeet!(ParseError::Header)
+		};
+
+		Ok(Self {
+			proof: Box::from(value.trim()),
+		})
+	}
+}
+
+impl DpopProof {
+	pub fn proof(&self) -> &str {
+		&self.proof
+	}
+}
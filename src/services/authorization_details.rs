@@ -0,0 +1,109 @@
+use exun::RawUnexpected;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use super::db;
+
+/// A single permission that a client asks for, as described in RFC 9396. Only
+/// the type is understood by this server. The other fields are defined by the
+/// resource server, and are passed along as-is.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthorizationDetail {
+	#[serde(rename = "type")]
+	ty: Box<str>,
+	#[serde(flatten)]
+	fields: Map<String, Value>,
+}
+
+impl AuthorizationDetail {
+	pub fn ty(&self) -> &str {
+		&self.ty
+	}
+}
+
+/// Permissions which are too fine-grained to be expressed as scopes, such as
+/// "pay up to 50 EUR to account X"
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<AuthorizationDetail>")]
+pub struct AuthorizationDetails(Box<[AuthorizationDetail]>);
+
+impl TryFrom<Vec<AuthorizationDetail>> for AuthorizationDetails {
+	type Error = &'static str;
+
+	fn try_from(value: Vec<AuthorizationDetail>) -> Result<Self, Self::Error> {
+		if value.is_empty() {
+			return Err("The authorization details must not be empty");
+		}
+
+		Ok(Self(value.into_boxed_slice()))
+	}
+}
+
+impl AuthorizationDetails {
+	pub fn iter(&self) -> impl Iterator<Item = &AuthorizationDetail> {
+		self.0.iter()
+	}
+
+	/// Returns `true` if every detail here was also in the other details. This
+	/// is used to narrow down a grant when a token is requested.
+	pub fn is_subset_of(&self, other: &Self) -> bool {
+		self.iter().all(|detail| other.0.contains(detail))
+	}
+}
+
+/// Returns `true` if every detail has a type which the client is allowed to
+/// request
+pub async fn types_are_allowed(
+	db: &MySqlPool,
+	client_id: Uuid,
+	details: &AuthorizationDetails,
+) -> Result<bool, RawUnexpected> {
+	let allowed_types = db::get_client_authorization_details_types(db, client_id).await?;
+	Ok(details
+		.iter()
+		.all(|detail| allowed_types.iter().any(|ty| **ty == *detail.ty())))
+}
+
+/// Form-encoded requests send the authorization details as a JSON string, but
+/// request objects send them as an array
+pub mod form {
+	use serde::de::Error as _;
+	use serde::ser::Error as _;
+	use serde::{Deserialize, Deserializer, Serializer};
+
+	use super::AuthorizationDetails;
+
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum EncodedDetails {
+		Json(Box<str>),
+		Array(AuthorizationDetails),
+	}
+
+	pub fn serialize<S: Serializer>(
+		details: &Option<AuthorizationDetails>,
+		serializer: S,
+	) -> Result<S::Ok, S::Error> {
+		match details {
+			Some(details) => {
+				let json = serde_json::to_string(details).map_err(S::Error::custom)?;
+				serializer.serialize_some(&json)
+			}
+			None => serializer.serialize_none(),
+		}
+	}
+
+	pub fn deserialize<'de, D: Deserializer<'de>>(
+		deserializer: D,
+	) -> Result<Option<AuthorizationDetails>, D::Error> {
+		match Option::<EncodedDetails>::deserialize(deserializer)? {
+			Some(EncodedDetails::Json(json)) => serde_json::from_str(&json)
+				.map(Some)
+				.map_err(D::Error::custom),
+			Some(EncodedDetails::Array(details)) => Ok(Some(details)),
+			None => Ok(None),
+		}
+	}
+}
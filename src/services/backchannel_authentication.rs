@@ -0,0 +1,82 @@
+use chrono::{DateTime, Duration, Utc};
+use exun::RawUnexpected;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use super::{db, id::new_id};
+
+/// The number of seconds that the client should wait between polls
+const DEFAULT_INTERVAL: u32 = 5;
+
+/// The longest binding message that can be shown to the user
+pub const MAX_BINDING_MESSAGE_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type)]
+#[sqlx(rename_all = "kebab-case")]
+pub enum BackchannelAuthenticationStatus {
+	Pending,
+	Approved,
+	Denied,
+}
+
+/// A pending backchannel authentication request, as described in OpenID
+/// Connect Client-Initiated Backchannel Authentication. The ID is the
+/// `auth_req_id`, which the client uses to get its tokens. Unlike a device
+/// code, the user is known when the request is made.
+#[derive(Debug, Clone)]
+pub struct BackchannelAuthentication {
+	pub id: Uuid,
+	pub client_id: Uuid,
+	pub user_id: Uuid,
+	pub scope: Box<str>,
+	pub binding_message: Option<Box<str>>,
+	pub client_notification_token: Option<Box<str>>,
+	pub exp: DateTime<Utc>,
+	pub interval: u32,
+	pub last_polled: Option<DateTime<Utc>>,
+	pub status: BackchannelAuthenticationStatus,
+}
+
+impl BackchannelAuthentication {
+	pub async fn new(
+		db: &MySqlPool,
+		client_id: Uuid,
+		user_id: Uuid,
+		scope: &str,
+		binding_message: Option<&str>,
+		client_notification_token: Option<&str>,
+	) -> Result<Self, RawUnexpected> {
+		let ten_minutes = Duration::minutes(10);
+
+		let id = new_id(db, db::backchannel_authentication_exists).await?;
+		let request = Self {
+			id,
+			client_id,
+			user_id,
+			scope: scope.into(),
+			binding_message: binding_message.map(Box::from),
+			client_notification_token: client_notification_token.map(Box::from),
+			exp: Utc::now() + ten_minutes,
+			interval: DEFAULT_INTERVAL,
+			last_polled: None,
+			status: BackchannelAuthenticationStatus::Pending,
+		};
+		db::create_backchannel_authentication(db, &request).await?;
+
+		Ok(request)
+	}
+
+	pub fn is_expired(&self) -> bool {
+		Utc::now() > self.exp
+	}
+
+	/// Returns `true` if the client polled before the interval was up
+	pub fn polled_too_soon(&self, now: DateTime<Utc>) -> bool {
+		self.last_polled
+			.is_some_and(|last_polled| now < last_polled + Duration::seconds(self.interval.into()))
+	}
+
+	pub fn expires_in(&self) -> i64 {
+		(self.exp - Utc::now()).num_seconds()
+	}
+}
@@ -0,0 +1,120 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use exun::RawUnexpected;
+use serde::Serialize;
+use url::Url;
+use uuid::Uuid;
+
+use super::config::BackchannelConfig;
+
+/// The message which asks a user to approve a backchannel authentication
+/// request
+#[derive(Debug, Clone, Serialize)]
+pub struct UserNotification<'a> {
+	pub username: &'a str,
+	pub client_id: &'a str,
+	pub binding_message: Option<&'a str>,
+	pub approval_uri: &'a Url,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct ClientNotification {
+	auth_req_id: Uuid,
+}
+
+/// Delivers the notifications for backchannel authentication
+#[async_trait]
+pub trait BackchannelNotifier: Send + Sync {
+	/// Ask the user to approve a request on their own device
+	async fn notify_user(&self, notification: &UserNotification<'_>) -> Result<(), RawUnexpected>;
+
+	/// Tell a client using the ping mode that the user has made a decision, so
+	/// that it can get its tokens
+	async fn ping_client(
+		&self,
+		endpoint: &Url,
+		client_notification_token: &str,
+		auth_req_id: Uuid,
+	) -> Result<(), RawUnexpected>;
+}
+
+/// Sends notifications over HTTP
+struct HttpNotifier {
+	client: reqwest::Client,
+	user_notification_endpoint: Option<Url>,
+}
+
+#[async_trait]
+impl BackchannelNotifier for HttpNotifier {
+	async fn notify_user(&self, notification: &UserNotification<'_>) -> Result<(), RawUnexpected> {
+		let Some(endpoint) = &self.user_notification_endpoint else {
+			log::warn!(
+				"{} must approve a backchannel authentication request, but no user notification endpoint is configured",
+				notification.username
+			);
+			return Ok(());
+		};
+
+		self.client
+			.post(endpoint.clone())
+			.json(notification)
+			.send()
+			.await?
+			.error_for_status()?;
+		Ok(())
+	}
+
+	async fn ping_client(
+		&self,
+		endpoint: &Url,
+		client_notification_token: &str,
+		auth_req_id: Uuid,
+	) -> Result<(), RawUnexpected> {
+		self.client
+			.post(endpoint.clone())
+			.bearer_auth(client_notification_token)
+			.json(&ClientNotification { auth_req_id })
+			.send()
+			.await?
+			.error_for_status()?;
+		Ok(())
+	}
+}
+
+/// Logs notifications instead of sending them, for running the server locally
+struct LogNotifier;
+
+#[async_trait]
+impl BackchannelNotifier for LogNotifier {
+	async fn notify_user(&self, notification: &UserNotification<'_>) -> Result<(), RawUnexpected> {
+		log::info!(
+			"{} is asked to approve a request from {} at {}",
+			notification.username,
+			notification.client_id,
+			notification.approval_uri
+		);
+		Ok(())
+	}
+
+	async fn ping_client(
+		&self,
+		endpoint: &Url,
+		_client_notification_token: &str,
+		auth_req_id: Uuid,
+	) -> Result<(), RawUnexpected> {
+		log::info!("{endpoint} is told that {auth_req_id} is ready");
+		Ok(())
+	}
+}
+
+pub fn initialize(config: &BackchannelConfig) -> Arc<dyn BackchannelNotifier> {
+	if config.log_notifications {
+		Arc::new(LogNotifier)
+	} else {
+		Arc::new(HttpNotifier {
+			client: reqwest::Client::new(),
+			user_notification_endpoint: config.user_notification_endpoint.clone(),
+		})
+	}
+}
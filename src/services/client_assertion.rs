@@ -0,0 +1,252 @@
+use chrono::serde::{ts_seconds, ts_seconds_option};
+use chrono::{DateTime, Utc};
+use exun::Expect;
+use raise::yeet;
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+use super::jws::{self, JwsError};
+use super::keys::Jwk;
+use super::request_object::Audience;
+use super::{crypto, db};
+use crate::models::client::AuthMethod;
+
+/// The only supported value of the `client_assertion_type` parameter
+pub const CLIENT_ASSERTION_TYPE: &str = "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+#[derive(Debug, Clone, Deserialize)]
+struct AssertionClaims {
+	iss: Box<str>,
+	sub: Box<str>,
+	aud: Audience,
+	#[serde(with = "ts_seconds")]
+	exp: DateTime<Utc>,
+	#[serde(default, with = "ts_seconds_option")]
+	nbf: Option<DateTime<Utc>>,
+	jti: Box<str>,
+}
+
+/// Only the subject is needed to find out which client sent the assertion
+#[derive(Debug, Clone, Deserialize)]
+struct AssertionSubject {
+	sub: Box<str>,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyClientAssertionError {
+	#[error("{0}")]
+	ParseJwtError(#[from] JwsError),
+	#[error("The client has not registered any keys")]
+	NoClientKeys,
+	#[error("The client secret must be reset before it can be used to make assertions")]
+	NoClientSecret,
+	#[error("The assertion must be issued by the client, about the client")]
+	IncorrectIssuer,
+	#[error("The assertion was intended for a different server")]
+	BadAudience,
+	#[error("The assertion is expired")]
+	ExpiredToken,
+	#[error("The assertion cannot be used yet")]
+	NotYet,
+	#[error("The assertion has already been used")]
+	ReusedAssertion,
+}
+
+/// The client ID in an assertion, which is needed before the assertion can be
+/// verified. Returns `None` if the assertion is malformed.
+pub fn assertion_subject(assertion: &str) -> Option<Box<str>> {
+	let token = jws::decode(assertion).ok()?;
+	let claims: AssertionSubject = token.unverified_claims().ok()?;
+	Some(claims.sub)
+}
+
+/// Verify a client assertion, as described in RFC 7523. Clients using
+/// `private_key_jwt` sign the assertion with one of their registered keys, and
+/// clients using `client_secret_jwt` MAC it with their client secret.
+pub async fn verify_client_assertion(
+	db: &MySqlPool,
+	assertion: &str,
+	self_id: &Url,
+	client_id: Uuid,
+	client_alias: &str,
+	auth_method: AuthMethod,
+) -> Result<(), Expect<VerifyClientAssertionError>> {
+	let token = jws::decode(assertion).map_err(VerifyClientAssertionError::from)?;
+	let claims: AssertionClaims = if auth_method == AuthMethod::ClientSecretJwt {
+		let Some(ciphertext) = db::get_client_secret_ciphertext(db, client_id).await? else {
+			yeet!(VerifyClientAssertionError::NoClientSecret.into())
+		};
+		let secret = crypto::decrypt_secret(&ciphertext)?;
+		token
+			.verify_mac(&secret)
+			.map_err(VerifyClientAssertionError::from)?
+	} else {
+		let Some(jwks) = db::get_client_jwks(db, client_id).await?.flatten() else {
+			yeet!(VerifyClientAssertionError::NoClientKeys.into())
+		};
+		let Some(key) = jwks.get(token.header().kid()).and_then(Jwk::verifying_key) else {
+			yeet!(VerifyClientAssertionError::from(JwsError::UnknownKey).into())
+		};
+		token
+			.verify(&key)
+			.map_err(VerifyClientAssertionError::from)?
+	};
+
+	let jti_hash = check_claims(&claims, self_id, client_alias, Utc::now())?;
+	if !db::use_client_assertion(db, &jti_hash, claims.exp).await? {
+		yeet!(VerifyClientAssertionError::ReusedAssertion.into())
+	}
+
+	Ok(())
+}
+
+/// Check the claims of an assertion whose signature was already verified.
+/// Returns the hash which identifies the assertion, so that it can't be used
+/// again.
+fn check_claims(
+	claims: &AssertionClaims,
+	self_id: &Url,
+	client_alias: &str,
+	now: DateTime<Utc>,
+) -> Result<Box<[u8]>, VerifyClientAssertionError> {
+	if &*claims.iss != client_alias || &*claims.sub != client_alias {
+		yeet!(VerifyClientAssertionError::IncorrectIssuer)
+	}
+
+	// the issuer and the token endpoint both identify this server
+	let token_uri = self_id.join("oauth/token").unwrap();
+	if !claims.aud.contains(self_id.as_str()) && !claims.aud.contains(token_uri.as_str()) {
+		yeet!(VerifyClientAssertionError::BadAudience)
+	}
+
+	if now > claims.exp {
+		yeet!(VerifyClientAssertionError::ExpiredToken)
+	}
+
+	if claims.nbf.is_some_and(|nbf| now < nbf) {
+		yeet!(VerifyClientAssertionError::NotYet)
+	}
+
+	let jti_hash = crypto::token_hash(&format!("{client_alias}:{}", claims.jti));
+	Ok(jti_hash)
+}
+
+#[cfg(test)]
+mod tests {
+	use chrono::Duration;
+	use serde_json::{json, Value};
+
+	use super::*;
+
+	const CLIENT: &str = "client";
+
+	fn self_id() -> Url {
+		Url::parse("https://auth.example.com/").unwrap()
+	}
+
+	fn claims(now: DateTime<Utc>) -> Value {
+		json!({
+			"iss": CLIENT,
+			"sub": CLIENT,
+			"aud": "https://auth.example.com/oauth/token",
+			"exp": (now + Duration::minutes(5)).timestamp(),
+			"jti": "assertion",
+		})
+	}
+
+	fn check(claims: Value, now: DateTime<Utc>) -> Result<Box<[u8]>, VerifyClientAssertionError> {
+		let claims: AssertionClaims = serde_json::from_value(claims).unwrap();
+		check_claims(&claims, &self_id(), CLIENT, now)
+	}
+
+	#[test]
+	fn accepts_valid_claims() {
+		let now = Utc::now();
+		assert!(check(claims(now), now).is_ok());
+	}
+
+	#[test]
+	fn accepts_issuer_as_audience() {
+		let now = Utc::now();
+		let mut claims = claims(now);
+		claims["aud"] = json!(["https://resource.example.com", "https://auth.example.com/"]);
+		assert!(check(claims, now).is_ok());
+	}
+
+	#[test]
+	fn rejects_other_audience() {
+		let now = Utc::now();
+		let mut claims = claims(now);
+		claims["aud"] = json!("https://other.example.com/oauth/token");
+		assert!(matches!(
+			check(claims, now),
+			Err(VerifyClientAssertionError::BadAudience)
+		));
+	}
+
+	#[test]
+	fn rejects_other_issuer() {
+		let now = Utc::now();
+		let mut claims = claims(now);
+		claims["iss"] = json!("other");
+		assert!(matches!(
+			check(claims, now),
+			Err(VerifyClientAssertionError::IncorrectIssuer)
+		));
+	}
+
+	#[test]
+	fn rejects_other_subject() {
+		let now = Utc::now();
+		let mut claims = claims(now);
+		claims["sub"] = json!("other");
+		assert!(matches!(
+			check(claims, now),
+			Err(VerifyClientAssertionError::IncorrectIssuer)
+		));
+	}
+
+	#[test]
+	fn rejects_expired_assertion() {
+		let now = Utc::now();
+		assert!(matches!(
+			check(claims(now), now + Duration::minutes(6)),
+			Err(VerifyClientAssertionError::ExpiredToken)
+		));
+	}
+
+	#[test]
+	fn rejects_assertion_before_nbf() {
+		let now = Utc::now();
+		let mut claims = claims(now);
+		claims["nbf"] = json!((now + Duration::minutes(1)).timestamp());
+		assert!(matches!(
+			check(claims, now),
+			Err(VerifyClientAssertionError::NotYet)
+		));
+	}
+
+	#[test]
+	fn requires_jti() {
+		let mut claims = claims(Utc::now());
+		claims.as_object_mut().unwrap().remove("jti");
+		assert!(serde_json::from_value::<AssertionClaims>(claims).is_err());
+	}
+
+	#[test]
+	fn jti_is_scoped_to_client() {
+		let now = Utc::now();
+		let mut other: AssertionClaims = serde_json::from_value(claims(now)).unwrap();
+		other.iss = "other".into();
+		other.sub = "other".into();
+
+		let first = check(claims(now), now).unwrap();
+		let again = check(claims(now), now).unwrap();
+		let other = check_claims(&other, &self_id(), "other", now).unwrap();
+		assert_eq!(first, again);
+		assert_ne!(first, other);
+	}
+}
@@ -1,5 +1,6 @@
 use std::{
 	fmt::{self, Display},
+	path::PathBuf,
 	str::FromStr,
 };
 
@@ -14,6 +15,57 @@ static ENVIRONMENT: RwLock<Environment> = RwLock::new(Environment::Local);
 #[derive(Debug, Clone, Deserialize)]
 pub struct Config {
 	pub url: Url,
+	/// If this is `true`, then anyone can register a client. Otherwise, an
+	/// initial access token is needed.
+	#[serde(default)]
+	pub open_registration: bool,
+	/// If this is set, then the server terminates TLS itself, and clients can
+	/// authenticate with a certificate
+	#[serde(default)]
+	pub tls: Option<TlsConfig>,
+	/// The issuers whose assertions can be exchanged for an access token, using
+	/// the JWT bearer grant
+	#[serde(default)]
+	pub trusted_issuers: Box<[TrustedIssuer]>,
+	/// The types of authorization details that resource servers understand.
+	/// Clients can only register for these types.
+	#[serde(default)]
+	pub authorization_details_types: Box<[Box<str>]>,
+	/// How users and clients are told about backchannel authentication requests
+	#[serde(default)]
+	pub backchannel: BackchannelConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TlsConfig {
+	/// The path to the server's PEM-encoded certificate chain
+	pub certificate: PathBuf,
+	/// The path to the server's PEM-encoded PKCS#8 private key
+	pub private_key: PathBuf,
+	/// The path to the PEM-encoded certificate authorities which issue the
+	/// certificates for clients using `tls_client_auth`
+	#[serde(default)]
+	pub client_ca: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TrustedIssuer {
+	/// The `iss` claim in the issuer's assertions
+	pub issuer: Box<str>,
+	/// The path to the JSON Web Key Set which the issuer signs assertions with
+	pub jwks: PathBuf,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct BackchannelConfig {
+	/// The endpoint which is sent a JSON message whenever a user is asked to
+	/// approve a backchannel authentication request. It's expected to forward
+	/// the message to the user's device.
+	#[serde(default)]
+	pub user_notification_endpoint: Option<Url>,
+	/// If this is `true`, then notifications are logged instead of being sent
+	#[serde(default)]
+	pub log_notifications: bool,
 }
 
 pub fn get_config() -> Result<Config, RawUnexpected> {
@@ -0,0 +1,131 @@
+use chrono::{DateTime, Duration, Utc};
+use exun::RawUnexpected;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use super::authorization_details::AuthorizationDetails;
+use super::jwt::RevokedRefreshTokenReason;
+use super::{db, id::new_id};
+use crate::scopes;
+
+/// The scopes that a user has allowed a client to have. The consent page isn't
+/// shown again unless the client asks for more than this.
+#[derive(Debug, Clone)]
+pub struct Consent {
+	pub user_id: Uuid,
+	pub client_id: Uuid,
+	pub scopes: Box<str>,
+	pub granted_at: DateTime<Utc>,
+}
+
+/// A user who has logged in, but hasn't decided whether to allow the client's
+/// request yet. The ID is sent back with the user's decision, so that the user
+/// doesn't need to log in again.
+#[derive(Debug, Clone)]
+pub struct ConsentRequest {
+	pub id: Uuid,
+	pub client_id: Uuid,
+	pub user_id: Uuid,
+	/// The authorization parameters that the user was shown, as JSON. Only
+	/// these are used once the user decides.
+	pub parameters: Box<str>,
+	pub exp: DateTime<Utc>,
+}
+
+impl ConsentRequest {
+	pub async fn new(
+		db: &MySqlPool,
+		client_id: Uuid,
+		user_id: Uuid,
+		parameters: &str,
+	) -> Result<Self, RawUnexpected> {
+		let ten_minutes = Duration::minutes(10);
+
+		let id = new_id(db, db::consent_request_exists).await?;
+		let exp = Utc::now() + ten_minutes;
+
+		let request = Self {
+			id,
+			client_id,
+			user_id,
+			parameters: Box::from(parameters),
+			exp,
+		};
+		db::create_consent_request(db, &request).await?;
+
+		Ok(request)
+	}
+
+	pub fn is_expired(&self) -> bool {
+		Utc::now() > self.exp
+	}
+}
+
+/// Returns `true` if the user has already allowed the client to have every
+/// scope that it's asking for. Authorization details aren't remembered, so the
+/// user is always asked about them.
+pub async fn is_granted(
+	db: &MySqlPool,
+	user_id: Uuid,
+	client_id: Uuid,
+	scope: &str,
+	authorization_details: Option<&AuthorizationDetails>,
+) -> Result<bool, RawUnexpected> {
+	if authorization_details.is_some() {
+		return Ok(false);
+	}
+
+	let Some(granted) = db::get_consent_scopes(db, user_id, client_id).await? else {
+		return Ok(false);
+	};
+
+	Ok(scopes::is_subset_of(scope, &granted))
+}
+
+/// Remember that the user allowed the client to have these scopes, along with
+/// any that were allowed before
+pub async fn grant(
+	db: &MySqlPool,
+	user_id: Uuid,
+	client_id: Uuid,
+	scope: &str,
+) -> Result<(), RawUnexpected> {
+	let granted = db::get_consent_scopes(db, user_id, client_id)
+		.await?
+		.unwrap_or_default();
+	let scopes = scopes::union(&granted, scope);
+
+	let consent = Consent {
+		user_id,
+		client_id,
+		scopes: scopes.into_boxed_str(),
+		granted_at: Utc::now(),
+	};
+	db::save_consent(db, &consent).await?;
+
+	Ok(())
+}
+
+/// Revoke the user's consent, along with every token that the client was given
+/// under it. This covers tokens from the authorization endpoint, and from the
+/// device, backchannel, and JWT bearer grants, along with any tokens that were
+/// refreshed or exchanged from them. Trusted clients don't need consent, and
+/// only trusted clients can use the password grant, so those tokens aren't
+/// covered. Returns `false` if the user never consented.
+pub async fn revoke(db: &MySqlPool, user_id: Uuid, client_id: Uuid) -> Result<bool, RawUnexpected> {
+	let reason = RevokedRefreshTokenReason::ConsentRevoked;
+	let mut transaction = db.begin().await?;
+
+	// the tokens are recorded by the grant that they came from
+	let grants = db::get_consent_tokens(transaction.as_mut(), user_id, client_id).await?;
+	for grant_id in grants.iter() {
+		db::delete_access_tokens_with_grant(transaction.as_mut(), *grant_id).await?;
+		db::revoke_refresh_tokens_with_grant(transaction.as_mut(), *grant_id, reason).await?;
+	}
+
+	db::delete_consent_tokens(transaction.as_mut(), user_id, client_id).await?;
+	let existed = db::delete_consent(transaction.as_mut(), user_id, client_id).await?;
+	transaction.commit().await?;
+
+	Ok(existed)
+}
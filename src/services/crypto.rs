@@ -1,7 +1,12 @@
 use std::hash::Hash;
 
 use argon2::{hash_raw, verify_raw};
+use base64::Engine;
 use exun::RawUnexpected;
+use rand::RngCore;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::hmac;
+use sha2::{Digest, Sha256};
 
 use crate::services::secrets::pepper;
 
@@ -95,3 +100,82 @@ impl PasswordHash {
 		)?)
 	}
 }
+
+/// A random token with 256 bits of entropy, for credentials which are generated
+/// by the server rather than chosen by a person
+pub fn random_token() -> Box<str> {
+	let mut bytes = [0; 32];
+	rand::thread_rng().fill_bytes(&mut bytes);
+	base64::engine::general_purpose::URL_SAFE_NO_PAD
+		.encode(bytes)
+		.into_boxed_str()
+}
+
+/// Hash a token from [`random_token`]. These tokens are random enough that
+/// they don't need a salt or a slow hash, so they can be looked up by hash.
+pub fn token_hash(token: &str) -> Box<[u8]> {
+	Sha256::digest(token.as_bytes()).to_vec().into_boxed_slice()
+}
+
+/// A key for encrypting data at rest. It's derived from the pepper, so that no
+/// other key needs to be configured. Each kind of data gets its own key.
+fn encryption_key(purpose: &[u8]) -> Result<LessSafeKey, RawUnexpected> {
+	let pepper = pepper()?;
+	let key = hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, &pepper), purpose);
+	let key = UnboundKey::new(&AES_256_GCM, key.as_ref())
+		.map_err(|_| RawUnexpected::msg("Failed to create the encryption key"))?;
+	Ok(LessSafeKey::new(key))
+}
+
+/// The nonce is stored in front of the ciphertext
+fn encrypt(purpose: &[u8], plaintext: &[u8]) -> Result<Box<[u8]>, RawUnexpected> {
+	let key = encryption_key(purpose)?;
+	let nonce: [u8; NONCE_LEN] = rand::random();
+	let mut ciphertext = plaintext.to_vec();
+	key.seal_in_place_append_tag(
+		Nonce::assume_unique_for_key(nonce),
+		Aad::empty(),
+		&mut ciphertext,
+	)
+	.map_err(|_| RawUnexpected::msg("Failed to encrypt the data"))?;
+
+	Ok([&nonce[..], &ciphertext].concat().into_boxed_slice())
+}
+
+fn decrypt(purpose: &[u8], ciphertext: &[u8]) -> Result<Box<[u8]>, RawUnexpected> {
+	let key = encryption_key(purpose)?;
+	if ciphertext.len() < NONCE_LEN {
+		return Err(RawUnexpected::msg("The encrypted data is too short"));
+	}
+	let (nonce, ciphertext) = ciphertext.split_at(NONCE_LEN);
+	let nonce = Nonce::try_assume_unique_for_key(nonce)
+		.map_err(|_| RawUnexpected::msg("The encrypted data has an invalid nonce"))?;
+
+	let mut ciphertext = ciphertext.to_vec();
+	let plaintext = key
+		.open_in_place(nonce, Aad::empty(), &mut ciphertext)
+		.map_err(|_| RawUnexpected::msg("Failed to decrypt the data"))?;
+	Ok(Box::from(&*plaintext))
+}
+
+/// Encrypt a client secret, so that it can be used to check MACs made with
+/// the secret
+pub fn encrypt_secret(secret: &str) -> Result<Box<[u8]>, RawUnexpected> {
+	encrypt(b"client secret encryption", secret.as_bytes())
+}
+
+/// Decrypt a client secret from [`encrypt_secret`]
+pub fn decrypt_secret(ciphertext: &[u8]) -> Result<Box<[u8]>, RawUnexpected> {
+	decrypt(b"client secret encryption", ciphertext)
+}
+
+/// Encrypt a PEM-encoded signing key, so that it isn't stored in plaintext
+pub fn encrypt_signing_key(pem: &str) -> Result<Box<[u8]>, RawUnexpected> {
+	encrypt(b"signing key encryption", pem.as_bytes())
+}
+
+/// Decrypt a signing key from [`encrypt_signing_key`]
+pub fn decrypt_signing_key(ciphertext: &[u8]) -> Result<String, RawUnexpected> {
+	let pem = decrypt(b"signing key encryption", ciphertext)?;
+	Ok(String::from_utf8(pem.into_vec())?)
+}
@@ -1,17 +1,39 @@
-use exun::{RawUnexpected, ResultErrorExt};
+use exun::RawUnexpected;
 use sqlx::MySqlPool;
 
+mod backchannel_authentication;
 mod client;
+mod client_assertion;
+mod consent;
+mod device_code;
+mod dpop;
 mod jwt;
+mod jwt_bearer;
 mod login_attempt;
+mod pushed_authorization;
+mod registration;
+mod resource_server;
+mod signing_key;
 mod user;
 
 pub use self::jwt::*;
+pub use backchannel_authentication::*;
 pub use client::*;
+pub use client_assertion::*;
+pub use consent::*;
+pub use device_code::*;
+pub use dpop::*;
+pub use jwt_bearer::*;
 pub use login_attempt::*;
+pub use pushed_authorization::*;
+pub use registration::*;
+pub use resource_server::*;
+pub use signing_key::*;
 pub use user::*;
 
-/// Intialize the connection pool
+/// Intialize the connection pool, and bring the schema up to date
 pub async fn initialize(db_url: &str) -> Result<MySqlPool, RawUnexpected> {
-	MySqlPool::connect(db_url).await.unexpect()
+	let pool = MySqlPool::connect(db_url).await?;
+	sqlx::migrate!().run(&pool).await?;
+	Ok(pool)
 }
@@ -0,0 +1,180 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_as, query_scalar, Executor, MySql};
+use uuid::Uuid;
+
+use crate::services::backchannel_authentication::{
+	BackchannelAuthentication, BackchannelAuthenticationStatus,
+};
+
+struct BackchannelAuthenticationRow {
+	id: Uuid,
+	client_id: Uuid,
+	user_id: Uuid,
+	scope: String,
+	binding_message: Option<String>,
+	client_notification_token: Option<String>,
+	exp: DateTime<Utc>,
+	poll_interval: u32,
+	last_polled: Option<DateTime<Utc>>,
+	status: BackchannelAuthenticationStatus,
+}
+
+impl From<BackchannelAuthenticationRow> for BackchannelAuthentication {
+	fn from(row: BackchannelAuthenticationRow) -> Self {
+		Self {
+			id: row.id,
+			client_id: row.client_id,
+			user_id: row.user_id,
+			scope: row.scope.into_boxed_str(),
+			binding_message: row.binding_message.map(String::into_boxed_str),
+			client_notification_token: row.client_notification_token.map(String::into_boxed_str),
+			exp: row.exp,
+			interval: row.poll_interval,
+			last_polled: row.last_polled,
+			status: row.status,
+		}
+	}
+}
+
+pub async fn backchannel_authentication_exists<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		"SELECT EXISTS(SELECT id FROM backchannel_authentications WHERE id = ?) as `e: bool`",
+		id
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn create_backchannel_authentication<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	request: &BackchannelAuthentication,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO backchannel_authentications (id, client_id, user_id, scope, binding_message, client_notification_token, exp, poll_interval, status)
+		                                   VALUES ( ?,         ?,       ?,     ?,               ?,                         ?,   ?,             ?,      ?)",
+		request.id,
+		request.client_id,
+		request.user_id,
+		request.scope.as_ref(),
+		request.binding_message.as_deref(),
+		request.client_notification_token.as_deref(),
+		request.exp,
+		request.interval,
+		request.status
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn get_backchannel_authentication<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<BackchannelAuthentication>, RawUnexpected> {
+	let record = query_as!(
+		BackchannelAuthenticationRow,
+		r"SELECT id as `id: Uuid`, client_id as `client_id: Uuid`, user_id as `user_id: Uuid`,
+		         scope, binding_message, client_notification_token,
+		         exp as `exp: DateTime<Utc>`, poll_interval,
+		         last_polled as `last_polled: DateTime<Utc>`,
+		         status as `status: BackchannelAuthenticationStatus`
+		  FROM backchannel_authentications WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(record.map(BackchannelAuthentication::from))
+}
+
+/// Get a request which is still waiting for the user to approve it
+pub async fn get_pending_backchannel_authentication<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<BackchannelAuthentication>, RawUnexpected> {
+	let record = query_as!(
+		BackchannelAuthenticationRow,
+		r"SELECT id as `id: Uuid`, client_id as `client_id: Uuid`, user_id as `user_id: Uuid`,
+		         scope, binding_message, client_notification_token,
+		         exp as `exp: DateTime<Utc>`, poll_interval,
+		         last_polled as `last_polled: DateTime<Utc>`,
+		         status as `status: BackchannelAuthenticationStatus`
+		  FROM backchannel_authentications WHERE id = ? AND status = 'pending' AND exp > ?",
+		id,
+		Utc::now()
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(record.map(BackchannelAuthentication::from))
+}
+
+pub async fn update_backchannel_authentication_poll<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	last_polled: DateTime<Utc>,
+	interval: u32,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"UPDATE backchannel_authentications SET last_polled = ?, poll_interval = ? WHERE id = ?",
+		last_polled,
+		interval,
+		id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+/// Approve or deny a pending request. Returns `false` if the request was no
+/// longer pending, or was made for a different user.
+pub async fn update_backchannel_authentication_status<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	user_id: Uuid,
+	status: BackchannelAuthenticationStatus,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		r"UPDATE backchannel_authentications SET status = ?
+		  WHERE id = ? AND user_id = ? AND status = 'pending'",
+		status,
+		id,
+		user_id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+/// Returns `true` if the request was deleted by this call
+pub async fn delete_backchannel_authentication<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!("DELETE FROM backchannel_authentications WHERE id = ?", id)
+		.execute(executor)
+		.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_backchannel_authentications<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"DELETE FROM backchannel_authentications WHERE exp < ?",
+		Utc::now()
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
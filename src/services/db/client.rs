@@ -8,8 +8,8 @@ use url::Url;
 use uuid::Uuid;
 
 use crate::{
-	models::client::{Client, ClientType},
-	services::crypto::PasswordHash,
+	models::client::{AuthMethod, Client, ClientType},
+	services::{crypto::PasswordHash, jwt::RevokedRefreshTokenReason, keys::JwkSet},
 };
 
 #[derive(Debug, Clone, FromRow)]
@@ -20,6 +20,11 @@ pub struct ClientRow {
 	pub allowed_scopes: String,
 	pub default_scopes: Option<String>,
 	pub is_trusted: bool,
+	pub requires_pkce: bool,
+	pub requires_par: bool,
+	pub auth_method: AuthMethod,
+	pub tls_client_auth_san_dns: Option<String>,
+	pub backchannel_client_notification_endpoint: Option<String>,
 }
 
 #[derive(Clone, FromRow)]
@@ -79,7 +84,12 @@ pub async fn get_client_response<'c>(
 				 type as `client_type`,
 				 allowed_scopes,
 				 default_scopes,
-				 trusted as `is_trusted: bool`
+				 trusted as `is_trusted: bool`,
+				 require_pkce as `requires_pkce: bool`,
+				 require_par as `requires_par: bool`,
+				 auth_method as `auth_method: AuthMethod`,
+				 tls_client_auth_san_dns,
+				 backchannel_client_notification_endpoint
 		  FROM clients WHERE id = ?",
 		id
 	)
@@ -138,6 +148,64 @@ pub async fn get_client_default_scopes<'c>(
 	Ok(scopes.map(|s| s.map(Box::from)))
 }
 
+pub async fn get_client_jwks<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<Option<JwkSet>>, RawUnexpected> {
+	query_scalar!(
+		"SELECT jwks as `jwks: JwkSet` FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn get_client_auth_method<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<AuthMethod>, RawUnexpected> {
+	query_scalar!(
+		"SELECT auth_method as `auth_method: AuthMethod` FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn get_client_tls_client_auth_san_dns<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<Option<Box<str>>>, RawUnexpected> {
+	let san_dns = query_scalar!(
+		"SELECT tls_client_auth_san_dns FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(san_dns.map(|s| s.map(Box::from)))
+}
+
+pub async fn get_client_backchannel_client_notification_endpoint<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<Option<Url>>, RawUnexpected> {
+	let endpoint = query_scalar!(
+		"SELECT backchannel_client_notification_endpoint FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	let Some(endpoint) = endpoint else {
+		return Ok(None);
+	};
+	let endpoint = endpoint.map(|e| Url::from_str(&e)).transpose()?;
+	Ok(Some(endpoint))
+}
+
 pub async fn get_client_secret<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	id: Uuid,
@@ -160,6 +228,17 @@ pub async fn get_client_secret<'c>(
 	Ok(Some(hash))
 }
 
+pub async fn get_client_secret_ciphertext<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<Box<[u8]>>, RawUnexpected> {
+	let ciphertext = query_scalar!("SELECT secret_ciphertext FROM clients WHERE id = ?", id)
+		.fetch_optional(executor)
+		.await?;
+
+	Ok(ciphertext.flatten().map(Box::from))
+}
+
 pub async fn is_client_trusted<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	id: Uuid,
@@ -170,6 +249,32 @@ pub async fn is_client_trusted<'c>(
 		.unexpect()
 }
 
+pub async fn client_requires_par<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<bool>, RawUnexpected> {
+	query_scalar!(
+		"SELECT require_par as `r: bool` FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn client_requires_pkce<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<bool>, RawUnexpected> {
+	query_scalar!(
+		"SELECT require_pkce as `r: bool` FROM clients WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
 pub async fn get_client_redirect_uris<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	id: Uuid,
@@ -237,22 +342,138 @@ async fn create_client_redirect_uris<'c>(
 	Ok(())
 }
 
+/// The clients whose tokens this client may exchange for new tokens
+pub async fn get_client_token_exchange_sources<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Box<[Uuid]>, RawUnexpected> {
+	let sources = query_scalar!(
+		r"SELECT source_client_id as `source_client_id: Uuid`
+		  FROM client_token_exchange_sources WHERE client_id = ?",
+		id
+	)
+	.fetch_all(executor)
+	.await?;
+
+	Ok(sources.into_boxed_slice())
+}
+
+pub async fn client_may_exchange_tokens_from<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	source_client_id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		r"SELECT EXISTS(
+			  SELECT source_client_id
+			  FROM client_token_exchange_sources
+			  WHERE client_id = ? AND source_client_id = ?
+		  ) as `e: bool`",
+		id,
+		source_client_id
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn update_client_token_exchange_sources<'c>(
+	mut transaction: Transaction<'c, MySql>,
+	id: Uuid,
+	sources: &[Uuid],
+) -> Result<(), sqlx::Error> {
+	query!(
+		"DELETE FROM client_token_exchange_sources WHERE client_id = ?",
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+
+	for source in sources {
+		query!(
+			r"INSERT INTO client_token_exchange_sources (client_id, source_client_id)
+			                                     VALUES (        ?,                ?)",
+			id,
+			source
+		)
+		.execute(transaction.as_mut())
+		.await?;
+	}
+
+	transaction.commit().await?;
+
+	Ok(())
+}
+
+/// The types of authorization details which this client may request
+pub async fn get_client_authorization_details_types<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Box<[Box<str>]>, RawUnexpected> {
+	let types = query_scalar!(
+		r"SELECT authorization_details_type
+		  FROM client_authorization_details_types WHERE client_id = ?",
+		id
+	)
+	.fetch_all(executor)
+	.await?;
+
+	Ok(types.into_iter().map(String::into_boxed_str).collect())
+}
+
+pub async fn update_client_authorization_details_types<'c>(
+	mut transaction: Transaction<'c, MySql>,
+	id: Uuid,
+	types: &[Box<str>],
+) -> Result<(), sqlx::Error> {
+	query!(
+		"DELETE FROM client_authorization_details_types WHERE client_id = ?",
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+
+	for ty in types {
+		query!(
+			r"INSERT INTO client_authorization_details_types (client_id, authorization_details_type)
+			                                          VALUES (        ?,                          ?)",
+			id,
+			ty.as_ref()
+		)
+		.execute(transaction.as_mut())
+		.await?;
+	}
+
+	transaction.commit().await?;
+
+	Ok(())
+}
+
 pub async fn create_client<'c>(
 	mut transaction: Transaction<'c, MySql>,
 	client: &Client,
 ) -> Result<(), sqlx::Error> {
 	query!(
-		r"INSERT INTO clients (id, alias, type, secret_hash, secret_salt, secret_version, allowed_scopes, default_scopes, trusted)
-					   VALUES ( ?,     ?,    ?,           ?,           ?,              ?,              ?,              ?,       ?)",
+		r"INSERT INTO clients (id, alias, type, secret_hash, secret_salt, secret_version, secret_ciphertext, allowed_scopes, default_scopes, trusted, require_pkce, require_par, jwks, auth_method, tls_client_auth_san_dns, backchannel_client_notification_endpoint)
+					   VALUES ( ?,     ?,    ?,           ?,           ?,              ?,                 ?,              ?,              ?,       ?,            ?,           ?,    ?,           ?,                       ?,                                        ?)",
 		client.id(),
 		client.alias(),
 		client.client_type(),
 		client.secret_hash(),
 		client.secret_salt(),
 		client.secret_version(),
+		client.secret_ciphertext(),
 		client.allowed_scopes(),
 		client.default_scopes(),
-		client.is_trusted()
+		client.is_trusted(),
+		client.requires_pkce(),
+		client.requires_par(),
+		client.jwks(),
+		client.auth_method(),
+		client.tls_client_auth_san_dns(),
+		client
+			.backchannel_client_notification_endpoint()
+			.map(Url::as_str)
 	)
 	.execute(transaction.as_mut())
 	.await?;
@@ -273,16 +494,32 @@ pub async fn update_client<'c>(
 		secret_hash = ?,
 		secret_salt = ?,
 		secret_version = ?,
+		secret_ciphertext = ?,
 		allowed_scopes = ?,
-		default_scopes = ?
+		default_scopes = ?,
+		require_pkce = ?,
+		require_par = ?,
+		jwks = ?,
+		auth_method = ?,
+		tls_client_auth_san_dns = ?,
+		backchannel_client_notification_endpoint = ?
 		WHERE id = ?",
 		client.alias(),
 		client.client_type(),
 		client.secret_hash(),
 		client.secret_salt(),
 		client.secret_version(),
+		client.secret_ciphertext(),
 		client.allowed_scopes(),
 		client.default_scopes(),
+		client.requires_pkce(),
+		client.requires_par(),
+		client.jwks(),
+		client.auth_method(),
+		client.tls_client_auth_san_dns(),
+		client
+			.backchannel_client_notification_endpoint()
+			.map(Url::as_str),
 		client.id()
 	)
 	.execute(transaction.as_mut())
@@ -355,6 +592,86 @@ pub async fn update_client_trusted<'c>(
 	.await
 }
 
+pub async fn update_client_require_pkce<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	require_pkce: bool,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET require_pkce = ? WHERE id = ?",
+		require_pkce,
+		id
+	)
+	.execute(executor)
+	.await
+}
+
+pub async fn update_client_require_par<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	require_par: bool,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET require_par = ? WHERE id = ?",
+		require_par,
+		id
+	)
+	.execute(executor)
+	.await
+}
+
+pub async fn update_client_jwks<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	jwks: Option<&JwkSet>,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!("UPDATE clients SET jwks = ? WHERE id = ?", jwks, id)
+		.execute(executor)
+		.await
+}
+
+pub async fn update_client_auth_method<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	auth_method: AuthMethod,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET auth_method = ? WHERE id = ?",
+		auth_method,
+		id
+	)
+	.execute(executor)
+	.await
+}
+
+pub async fn update_client_tls_client_auth_san_dns<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	san_dns: Option<&str>,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET tls_client_auth_san_dns = ? WHERE id = ?",
+		san_dns,
+		id
+	)
+	.execute(executor)
+	.await
+}
+
+pub async fn update_client_backchannel_client_notification_endpoint<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	endpoint: Option<&Url>,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET backchannel_client_notification_endpoint = ? WHERE id = ?",
+		endpoint.map(Url::as_str),
+		id
+	)
+	.execute(executor)
+	.await
+}
+
 pub async fn update_client_redirect_uris<'c>(
 	mut transaction: Transaction<'c, MySql>,
 	id: Uuid,
@@ -365,6 +682,20 @@ pub async fn update_client_redirect_uris<'c>(
 	Ok(())
 }
 
+pub async fn update_client_secret_ciphertext<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	ciphertext: Option<&[u8]>,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		"UPDATE clients SET secret_ciphertext = ? WHERE id = ?",
+		ciphertext,
+		id
+	)
+	.execute(executor)
+	.await
+}
+
 pub async fn update_client_secret<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	id: Uuid,
@@ -391,3 +722,66 @@ pub async fn update_client_secret<'c>(
 		.await
 	}
 }
+
+/// Delete a client, along with its redirect URIs and registration. Every token
+/// and pending request that the client has is revoked, along with the consents
+/// that users gave it. Pushed authorization requests are deleted by the
+/// database.
+pub async fn delete_client<'c>(
+	mut transaction: Transaction<'c, MySql>,
+	id: Uuid,
+) -> Result<(), sqlx::Error> {
+	query!("DELETE FROM access_tokens WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+	query!(
+		"UPDATE refresh_tokens SET revoked_reason = ? WHERE client_id = ?",
+		RevokedRefreshTokenReason::Revoked,
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+	query!("DELETE FROM device_codes WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+	query!(
+		"DELETE FROM backchannel_authentications WHERE client_id = ?",
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+	query!("DELETE FROM consent_requests WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+	query!("DELETE FROM consent_tokens WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+	query!("DELETE FROM consents WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+
+	delete_client_redirect_uris(transaction.as_mut(), id).await?;
+	query!(
+		"DELETE FROM client_token_exchange_sources WHERE client_id = ? OR source_client_id = ?",
+		id,
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+	query!(
+		"DELETE FROM client_authorization_details_types WHERE client_id = ?",
+		id
+	)
+	.execute(transaction.as_mut())
+	.await?;
+	query!("DELETE FROM client_registrations WHERE client_id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+	query!("DELETE FROM clients WHERE id = ?", id)
+		.execute(transaction.as_mut())
+		.await?;
+
+	transaction.commit().await?;
+
+	Ok(())
+}
@@ -0,0 +1,241 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_as, query_scalar, Executor, MySql};
+use uuid::Uuid;
+
+use crate::services::consent::{Consent, ConsentRequest};
+
+struct ConsentRow {
+	user_id: Uuid,
+	client_id: Uuid,
+	scopes: String,
+	granted_at: DateTime<Utc>,
+}
+
+impl From<ConsentRow> for Consent {
+	fn from(row: ConsentRow) -> Self {
+		Self {
+			user_id: row.user_id,
+			client_id: row.client_id,
+			scopes: row.scopes.into_boxed_str(),
+			granted_at: row.granted_at,
+		}
+	}
+}
+
+struct ConsentRequestRow {
+	id: Uuid,
+	client_id: Uuid,
+	user_id: Uuid,
+	parameters: String,
+	exp: DateTime<Utc>,
+}
+
+impl From<ConsentRequestRow> for ConsentRequest {
+	fn from(row: ConsentRequestRow) -> Self {
+		Self {
+			id: row.id,
+			client_id: row.client_id,
+			user_id: row.user_id,
+			parameters: row.parameters.into_boxed_str(),
+			exp: row.exp,
+		}
+	}
+}
+
+/// The scopes that the user has allowed the client to have, if any
+pub async fn get_consent_scopes<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+	client_id: Uuid,
+) -> Result<Option<Box<str>>, RawUnexpected> {
+	let scopes = query_scalar!(
+		"SELECT scopes FROM consents WHERE user_id = ? AND client_id = ?",
+		user_id,
+		client_id
+	)
+	.fetch_optional(executor)
+	.await?
+	.map(String::into_boxed_str);
+
+	Ok(scopes)
+}
+
+/// Every client that the user has given consent to
+pub async fn get_user_consents<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+) -> Result<Box<[Consent]>, RawUnexpected> {
+	let records = query_as!(
+		ConsentRow,
+		r"SELECT user_id as `user_id: Uuid`, client_id as `client_id: Uuid`, scopes,
+		         granted_at as `granted_at: DateTime<Utc>`
+		  FROM consents WHERE user_id = ?",
+		user_id
+	)
+	.fetch_all(executor)
+	.await?;
+
+	Ok(records.into_iter().map(Consent::from).collect())
+}
+
+/// Create the consent, or replace the one that the user already gave
+pub async fn save_consent<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	consent: &Consent,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO consents (user_id, client_id, scopes, granted_at)
+		               VALUES (      ?,         ?,      ?,          ?)
+		  ON DUPLICATE KEY UPDATE scopes = VALUES(scopes), granted_at = VALUES(granted_at)",
+		consent.user_id,
+		consent.client_id,
+		consent.scopes.as_ref(),
+		consent.granted_at
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+/// Returns `false` if the user never gave consent to the client
+pub async fn delete_consent<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+	client_id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		"DELETE FROM consents WHERE user_id = ? AND client_id = ?",
+		user_id,
+		client_id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+/// Remember that a grant was made under the user's consent, so that its tokens
+/// can be revoked along with the consent
+pub async fn add_consent_token<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+	client_id: Uuid,
+	grant_id: Uuid,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO consent_tokens (user_id, client_id, grant_id)
+		                      VALUES (      ?,         ?,        ?)",
+		user_id,
+		client_id,
+		grant_id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn get_consent_tokens<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+	client_id: Uuid,
+) -> Result<Box<[Uuid]>, RawUnexpected> {
+	let tokens = query_scalar!(
+		r"SELECT grant_id as `grant_id: Uuid` FROM consent_tokens WHERE user_id = ? AND client_id = ?",
+		user_id,
+		client_id
+	)
+	.fetch_all(executor)
+	.await?;
+
+	Ok(tokens.into_boxed_slice())
+}
+
+pub async fn delete_consent_tokens<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_id: Uuid,
+	client_id: Uuid,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"DELETE FROM consent_tokens WHERE user_id = ? AND client_id = ?",
+		user_id,
+		client_id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn consent_request_exists<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		"SELECT EXISTS(SELECT id FROM consent_requests WHERE id = ?) as `e: bool`",
+		id
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn create_consent_request<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	request: &ConsentRequest,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO consent_requests (id, client_id, user_id, parameters, exp)
+		                        VALUES ( ?,         ?,       ?,          ?,   ?)",
+		request.id,
+		request.client_id,
+		request.user_id,
+		request.parameters.as_ref(),
+		request.exp
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn get_consent_request<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<ConsentRequest>, RawUnexpected> {
+	let record = query_as!(
+		ConsentRequestRow,
+		r"SELECT id as `id: Uuid`, client_id as `client_id: Uuid`, user_id as `user_id: Uuid`,
+		         parameters, exp as `exp: DateTime<Utc>`
+		  FROM consent_requests WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(record.map(ConsentRequest::from))
+}
+
+/// Returns `true` if the request was deleted by this call
+pub async fn delete_consent_request<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!("DELETE FROM consent_requests WHERE id = ?", id)
+		.execute(executor)
+		.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_consent_requests<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!("DELETE FROM consent_requests WHERE exp < ?", Utc::now())
+		.execute(executor)
+		.await?;
+
+	Ok(())
+}
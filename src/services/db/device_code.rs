@@ -0,0 +1,181 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_as, query_scalar, Executor, MySql};
+use uuid::Uuid;
+
+use crate::services::device_code::{DeviceCode, DeviceCodeStatus};
+
+struct DeviceCodeRow {
+	id: Uuid,
+	user_code: String,
+	client_id: Uuid,
+	scope: String,
+	exp: DateTime<Utc>,
+	poll_interval: u32,
+	last_polled: Option<DateTime<Utc>>,
+	status: DeviceCodeStatus,
+	user_id: Option<Uuid>,
+}
+
+impl From<DeviceCodeRow> for DeviceCode {
+	fn from(row: DeviceCodeRow) -> Self {
+		Self {
+			id: row.id,
+			user_code: row.user_code.into_boxed_str(),
+			client_id: row.client_id,
+			scope: row.scope.into_boxed_str(),
+			exp: row.exp,
+			interval: row.poll_interval,
+			last_polled: row.last_polled,
+			status: row.status,
+			user_id: row.user_id,
+		}
+	}
+}
+
+pub async fn device_code_exists<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		"SELECT EXISTS(SELECT id FROM device_codes WHERE id = ?) as `e: bool`",
+		id
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn user_code_exists<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_code: &str,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		"SELECT EXISTS(SELECT id FROM device_codes WHERE user_code = ?) as `e: bool`",
+		user_code
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn create_device_code<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	device_code: &DeviceCode,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO device_codes (id, user_code, client_id, scope, exp, poll_interval, status)
+		                    VALUES ( ?,         ?,         ?,     ?,   ?,             ?,      ?)",
+		device_code.id,
+		device_code.user_code.as_ref(),
+		device_code.client_id,
+		device_code.scope.as_ref(),
+		device_code.exp,
+		device_code.interval,
+		device_code.status
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn get_device_code<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<DeviceCode>, RawUnexpected> {
+	let record = query_as!(
+		DeviceCodeRow,
+		r"SELECT id as `id: Uuid`, user_code, client_id as `client_id: Uuid`, scope,
+		         exp as `exp: DateTime<Utc>`, poll_interval,
+		         last_polled as `last_polled: DateTime<Utc>`, status as `status: DeviceCodeStatus`,
+		         user_id as `user_id: Uuid`
+		  FROM device_codes WHERE id = ?",
+		id
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(record.map(DeviceCode::from))
+}
+
+/// Get a device code which is still waiting for the user to approve it
+pub async fn get_pending_device_code_by_user_code<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	user_code: &str,
+) -> Result<Option<DeviceCode>, RawUnexpected> {
+	let record = query_as!(
+		DeviceCodeRow,
+		r"SELECT id as `id: Uuid`, user_code, client_id as `client_id: Uuid`, scope,
+		         exp as `exp: DateTime<Utc>`, poll_interval,
+		         last_polled as `last_polled: DateTime<Utc>`, status as `status: DeviceCodeStatus`,
+		         user_id as `user_id: Uuid`
+		  FROM device_codes WHERE user_code = ? AND status = 'pending' AND exp > ?",
+		user_code,
+		Utc::now()
+	)
+	.fetch_optional(executor)
+	.await?;
+
+	Ok(record.map(DeviceCode::from))
+}
+
+pub async fn update_device_code_poll<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	last_polled: DateTime<Utc>,
+	interval: u32,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"UPDATE device_codes SET last_polled = ?, poll_interval = ? WHERE id = ?",
+		last_polled,
+		interval,
+		id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+/// Approve or deny a pending device code. Returns `false` if the code was no
+/// longer pending.
+pub async fn update_device_code_status<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	status: DeviceCodeStatus,
+	user_id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		"UPDATE device_codes SET status = ?, user_id = ? WHERE id = ? AND status = 'pending'",
+		status,
+		user_id,
+		id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+/// Returns `true` if the device code was deleted by this call
+pub async fn delete_device_code<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!("DELETE FROM device_codes WHERE id = ?", id)
+		.execute(executor)
+		.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_device_codes<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!("DELETE FROM device_codes WHERE exp < ?", Utc::now())
+		.execute(executor)
+		.await?;
+
+	Ok(())
+}
@@ -0,0 +1,31 @@
+use chrono::{DateTime, Utc};
+use exun::RawUnexpected;
+use sqlx::{query, Executor, MySql};
+
+/// Remember that a DPoP proof was used, until it expires. Returns `false` if
+/// the proof was already used.
+pub async fn use_dpop_proof<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	jti_hash: &[u8],
+	exp: DateTime<Utc>,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		"INSERT IGNORE INTO dpop_proofs (jti_hash, exp) VALUES (?, ?)",
+		jti_hash,
+		exp
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_dpop_proofs<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!("DELETE FROM dpop_proofs WHERE exp < ?", Utc::now())
+		.execute(executor)
+		.await?;
+
+	Ok(())
+}
@@ -3,6 +3,8 @@ use exun::{RawUnexpected, ResultErrorExt};
 use sqlx::{query, query_scalar, Executor, MySql};
 use uuid::Uuid;
 
+use crate::services::jwt::RevokedRefreshTokenReason;
+
 pub async fn auth_code_exists<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	jti: Uuid,
@@ -79,14 +81,16 @@ pub async fn create_auth_code<'c>(
 pub async fn create_access_token<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	jti: Uuid,
-	auth_code: Option<Uuid>,
+	grant_id: Uuid,
+	client_id: Uuid,
 	exp: DateTime<Utc>,
 ) -> Result<(), sqlx::Error> {
 	query!(
-		r"INSERT INTO access_tokens (jti, auth_code, exp)
-	                         VALUES (  ?,         ?,   ?)",
+		r"INSERT INTO access_tokens (jti, grant_id, client_id, exp)
+	                         VALUES (  ?,        ?,         ?,   ?)",
 		jti,
-		auth_code,
+		grant_id,
+		client_id,
 		exp
 	)
 	.execute(executor)
@@ -98,14 +102,16 @@ pub async fn create_access_token<'c>(
 pub async fn create_refresh_token<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	jti: Uuid,
-	auth_code: Option<Uuid>,
+	grant_id: Uuid,
+	client_id: Uuid,
 	exp: DateTime<Utc>,
 ) -> Result<(), sqlx::Error> {
 	query!(
-		r"INSERT INTO access_tokens (jti, auth_code, exp)
-	                         VALUES (  ?,         ?,   ?)",
+		r"INSERT INTO refresh_tokens (jti, grant_id, client_id, exp)
+	                          VALUES (  ?,        ?,         ?,   ?)",
 		jti,
-		auth_code,
+		grant_id,
+		client_id,
 		exp
 	)
 	.execute(executor)
@@ -135,11 +141,22 @@ pub async fn delete_expired_auth_codes<'c>(
 	Ok(())
 }
 
-pub async fn delete_access_tokens_with_auth_code<'c>(
+pub async fn delete_access_tokens_with_grant<'c>(
 	executor: impl Executor<'c, Database = MySql>,
-	auth_code: Uuid,
+	grant_id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!("DELETE FROM access_tokens WHERE grant_id = ?", grant_id)
+		.execute(executor)
+		.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_access_token<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	jti: Uuid,
 ) -> Result<bool, RawUnexpected> {
-	let result = query!("DELETE FROM access_tokens WHERE auth_code = ?", auth_code)
+	let result = query!("DELETE FROM access_tokens WHERE jti = ?", jti)
 		.execute(executor)
 		.await?;
 
@@ -156,13 +173,16 @@ pub async fn delete_expired_access_tokens<'c>(
 	Ok(())
 }
 
-pub async fn revoke_refresh_token<'c>(
+/// Revoke a refresh token, unless it was already revoked. Returns `false` if it
+/// was, which means that another request used it first.
+pub async fn consume_refresh_token<'c>(
 	executor: impl Executor<'c, Database = MySql>,
 	jti: Uuid,
+	reason: RevokedRefreshTokenReason,
 ) -> Result<bool, RawUnexpected> {
 	let result = query!(
-		"UPDATE refresh_tokens SET revoked_reason = ? WHERE jti = ?",
-		"new-refresh-token",
+		"UPDATE refresh_tokens SET revoked_reason = ? WHERE jti = ? AND revoked_reason IS NULL",
+		reason,
 		jti
 	)
 	.execute(executor)
@@ -171,14 +191,15 @@ pub async fn revoke_refresh_token<'c>(
 	Ok(result.rows_affected() != 0)
 }
 
-pub async fn revoke_refresh_tokens_with_auth_code<'c>(
+pub async fn revoke_refresh_tokens_with_grant<'c>(
 	executor: impl Executor<'c, Database = MySql>,
-	auth_code: Uuid,
+	grant_id: Uuid,
+	reason: RevokedRefreshTokenReason,
 ) -> Result<bool, RawUnexpected> {
 	let result = query!(
-		"UPDATE refresh_tokens SET revoked_reason = ? WHERE auth_code = ?",
-		"reused-authorization-code",
-		auth_code
+		"UPDATE refresh_tokens SET revoked_reason = ? WHERE grant_id = ?",
+		reason,
+		grant_id
 	)
 	.execute(executor)
 	.await?;
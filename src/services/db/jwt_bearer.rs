@@ -0,0 +1,34 @@
+use chrono::{DateTime, Utc};
+use exun::RawUnexpected;
+use sqlx::{query, Executor, MySql};
+
+/// Remember that an assertion grant was used, until it expires. Returns
+/// `false` if the assertion was already used.
+pub async fn use_jwt_bearer_assertion<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	jti_hash: &[u8],
+	exp: DateTime<Utc>,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		"INSERT IGNORE INTO jwt_bearer_assertions (jti_hash, exp) VALUES (?, ?)",
+		jti_hash,
+		exp
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_jwt_bearer_assertions<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"DELETE FROM jwt_bearer_assertions WHERE exp < ?",
+		Utc::now()
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
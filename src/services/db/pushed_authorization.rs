@@ -0,0 +1,84 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_as, query_scalar, Executor, MySql};
+use uuid::Uuid;
+
+pub struct PushedAuthorizationRow {
+	pub client_id: Uuid,
+	pub parameters: String,
+}
+
+pub async fn pushed_authorization_exists<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	query_scalar!(
+		"SELECT EXISTS(SELECT id FROM pushed_authorization_requests WHERE id = ?) as `e: bool`",
+		id
+	)
+	.fetch_one(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn create_pushed_authorization<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	client_id: Uuid,
+	parameters: &str,
+	exp: DateTime<Utc>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO pushed_authorization_requests (id, client_id, parameters, exp)
+		                                     VALUES ( ?,         ?,          ?,   ?)",
+		id,
+		client_id,
+		parameters,
+		exp
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+/// Expired requests are treated as if they don't exist
+pub async fn get_pushed_authorization<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<PushedAuthorizationRow>, RawUnexpected> {
+	query_as!(
+		PushedAuthorizationRow,
+		r"SELECT client_id as `client_id: Uuid`, parameters
+		  FROM pushed_authorization_requests WHERE id = ? AND exp > ?",
+		id,
+		Utc::now()
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn delete_pushed_authorization<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<(), RawUnexpected> {
+	query!("DELETE FROM pushed_authorization_requests WHERE id = ?", id)
+		.execute(executor)
+		.await?;
+
+	Ok(())
+}
+
+pub async fn delete_expired_pushed_authorizations<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"DELETE FROM pushed_authorization_requests WHERE exp < ?",
+		Utc::now()
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
@@ -0,0 +1,109 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_as, Executor, MySql};
+use uuid::Uuid;
+
+pub struct ClientRegistrationRow {
+	pub registration_token_hash: Vec<u8>,
+	pub metadata: String,
+	pub issued_at: DateTime<Utc>,
+}
+
+pub async fn create_initial_access_token<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	token_hash: &[u8],
+	exp: DateTime<Utc>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO initial_access_tokens (token_hash, exp)
+		                             VALUES (         ?,   ?)",
+		token_hash,
+		exp
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+/// Initial access tokens can only be used once. Returns `true` if the token
+/// was valid, in which case it's now deleted.
+pub async fn use_initial_access_token<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	token_hash: &[u8],
+) -> Result<bool, RawUnexpected> {
+	let result = query!(
+		"DELETE FROM initial_access_tokens WHERE token_hash = ? AND exp > ?",
+		token_hash,
+		Utc::now()
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(result.rows_affected() != 0)
+}
+
+pub async fn delete_expired_initial_access_tokens<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"DELETE FROM initial_access_tokens WHERE exp < ?",
+		Utc::now()
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn create_client_registration<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	client_id: Uuid,
+	registration_token_hash: &[u8],
+	metadata: &str,
+	issued_at: DateTime<Utc>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO client_registrations (client_id, registration_token_hash, metadata, issued_at)
+		                            VALUES (        ?,                       ?,        ?,         ?)",
+		client_id,
+		registration_token_hash,
+		metadata,
+		issued_at
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn get_client_registration<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	client_id: Uuid,
+) -> Result<Option<ClientRegistrationRow>, RawUnexpected> {
+	query_as!(
+		ClientRegistrationRow,
+		r"SELECT registration_token_hash, metadata, issued_at as `issued_at: DateTime<Utc>`
+		  FROM client_registrations WHERE client_id = ?",
+		client_id
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn update_client_registration_metadata<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	client_id: Uuid,
+	metadata: &str,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"UPDATE client_registrations SET metadata = ? WHERE client_id = ?",
+		metadata,
+		client_id
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
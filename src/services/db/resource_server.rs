@@ -0,0 +1,173 @@
+use exun::RawUnexpected;
+use sqlx::{mysql::MySqlQueryResult, query, query_as, query_scalar, Executor, MySql};
+use url::Url;
+use uuid::Uuid;
+
+use crate::models::resource_server::ResourceServer;
+
+struct ResourceServerRow {
+	id: Uuid,
+	uri: String,
+	scopes: String,
+}
+
+impl TryFrom<ResourceServerRow> for ResourceServer {
+	type Error = RawUnexpected;
+
+	fn try_from(row: ResourceServerRow) -> Result<Self, Self::Error> {
+		let resource_server = ResourceServer {
+			id: row.id,
+			uri: Url::parse(&row.uri)?,
+			scopes: row.scopes.into_boxed_str(),
+		};
+		Ok(resource_server)
+	}
+}
+
+/// Check if a resource server with a given ID exists
+pub async fn resource_server_id_exists<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let exists = query_scalar!(
+		r#"SELECT EXISTS(SELECT id FROM resource_servers WHERE id = ?) as `e: bool`"#,
+		id
+	)
+	.fetch_one(conn)
+	.await?;
+
+	Ok(exists)
+}
+
+/// Check if a given resource URI is taken
+pub async fn resource_server_uri_is_used<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	uri: &Url,
+) -> Result<bool, RawUnexpected> {
+	let exists = query_scalar!(
+		r#"SELECT EXISTS(SELECT id FROM resource_servers WHERE uri = ?) as "e: bool""#,
+		uri.to_string()
+	)
+	.fetch_one(conn)
+	.await?;
+
+	Ok(exists)
+}
+
+/// Get every registered resource server
+pub async fn get_resource_servers<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+) -> Result<Box<[ResourceServer]>, RawUnexpected> {
+	let records = query_as!(
+		ResourceServerRow,
+		r"SELECT id as `id: Uuid`, uri, scopes FROM resource_servers"
+	)
+	.fetch_all(conn)
+	.await?;
+
+	records
+		.into_iter()
+		.map(|r| r.try_into())
+		.collect::<Result<Box<[ResourceServer]>, RawUnexpected>>()
+}
+
+/// Get a resource server from its ID
+pub async fn get_resource_server<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<Option<ResourceServer>, RawUnexpected> {
+	let record = query_as!(
+		ResourceServerRow,
+		r"SELECT id as `id: Uuid`, uri, scopes FROM resource_servers WHERE id = ?",
+		id
+	)
+	.fetch_optional(conn)
+	.await?;
+
+	let Some(record) = record else {
+		return Ok(None);
+	};
+
+	Ok(Some(record.try_into()?))
+}
+
+/// Get a resource server from the URI which identifies it
+pub async fn get_resource_server_by_uri<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	uri: &Url,
+) -> Result<Option<ResourceServer>, RawUnexpected> {
+	let record = query_as!(
+		ResourceServerRow,
+		r"SELECT id as `id: Uuid`, uri, scopes FROM resource_servers WHERE uri = ?",
+		uri.to_string()
+	)
+	.fetch_optional(conn)
+	.await?;
+
+	let Some(record) = record else {
+		return Ok(None);
+	};
+
+	Ok(Some(record.try_into()?))
+}
+
+/// Register a new resource server
+pub async fn create_resource_server<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	resource_server: &ResourceServer,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		r"INSERT INTO resource_servers (id, uri, scopes)
+		                        VALUES ( ?,   ?,      ?)",
+		resource_server.id,
+		resource_server.uri.to_string(),
+		resource_server.scopes()
+	)
+	.execute(conn)
+	.await
+}
+
+/// Update a resource server
+pub async fn update_resource_server<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	resource_server: &ResourceServer,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		r"UPDATE resource_servers SET
+		  uri = ?,
+		  scopes = ?
+		  WHERE id = ?",
+		resource_server.uri.to_string(),
+		resource_server.scopes(),
+		resource_server.id
+	)
+	.execute(conn)
+	.await
+}
+
+/// Update the scopes owned by the resource server with the given ID
+pub async fn update_resource_server_scopes<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+	scopes: &str,
+) -> Result<MySqlQueryResult, sqlx::Error> {
+	query!(
+		r"UPDATE resource_servers SET scopes = ? WHERE id = ?",
+		scopes,
+		id
+	)
+	.execute(conn)
+	.await
+}
+
+/// Remove a resource server. Returns `false` if it didn't exist.
+pub async fn delete_resource_server<'c>(
+	conn: impl Executor<'c, Database = MySql>,
+	id: Uuid,
+) -> Result<bool, RawUnexpected> {
+	let result = query!(r"DELETE FROM resource_servers WHERE id = ?", id)
+		.execute(conn)
+		.await?;
+
+	Ok(result.rows_affected() != 0)
+}
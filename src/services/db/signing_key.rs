@@ -0,0 +1,92 @@
+use chrono::{DateTime, Utc};
+use exun::{RawUnexpected, ResultErrorExt};
+use sqlx::{query, query_scalar, Executor, MySql};
+
+use crate::services::key_set::KeyState;
+
+pub async fn get_signing_key_with_state<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	state: KeyState,
+) -> Result<Option<Vec<u8>>, RawUnexpected> {
+	query_scalar!(
+		"SELECT private_key FROM signing_keys WHERE state = ?",
+		state
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+/// Get a key which can still be used to verify tokens
+pub async fn get_unexpired_signing_key<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	kid: &str,
+) -> Result<Option<Vec<u8>>, RawUnexpected> {
+	query_scalar!(
+		"SELECT private_key FROM signing_keys WHERE kid = ? AND (expires IS NULL OR expires > ?)",
+		kid,
+		Utc::now()
+	)
+	.fetch_optional(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn get_unexpired_signing_keys<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<Vec<Vec<u8>>, RawUnexpected> {
+	query_scalar!(
+		"SELECT private_key FROM signing_keys WHERE expires IS NULL OR expires > ?",
+		Utc::now()
+	)
+	.fetch_all(executor)
+	.await
+	.unexpect()
+}
+
+pub async fn create_signing_key<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	kid: &str,
+	private_key: &[u8],
+	state: KeyState,
+) -> Result<(), RawUnexpected> {
+	query!(
+		r"INSERT INTO signing_keys (kid, private_key, state)
+		                   VALUES (  ?,           ?,     ?)",
+		kid,
+		private_key,
+		state
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn update_signing_key_state<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+	kid: &str,
+	state: KeyState,
+	expires: Option<DateTime<Utc>>,
+) -> Result<(), RawUnexpected> {
+	query!(
+		"UPDATE signing_keys SET state = ?, expires = ? WHERE kid = ?",
+		state,
+		expires,
+		kid
+	)
+	.execute(executor)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn delete_expired_signing_keys<'c>(
+	executor: impl Executor<'c, Database = MySql>,
+) -> Result<(), RawUnexpected> {
+	query!("DELETE FROM signing_keys WHERE expires < ?", Utc::now())
+		.execute(executor)
+		.await?;
+
+	Ok(())
+}
@@ -0,0 +1,110 @@
+use chrono::{DateTime, Duration, Utc};
+use exun::RawUnexpected;
+use rand::Rng;
+use sqlx::MySqlPool;
+use uuid::Uuid;
+
+use super::{db, id::new_id};
+
+/// The characters used in user codes. There are no vowels, so that the codes
+/// can't spell words, and no digits, so that they're easy to type on a TV.
+const USER_CODE_CHARACTERS: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// The number of characters in a user code, not including the hyphen
+const USER_CODE_LENGTH: usize = 8;
+
+/// The number of seconds that the client should wait between polls
+const DEFAULT_INTERVAL: u32 = 5;
+
+/// Added to the interval each time the client polls too quickly
+pub const SLOW_DOWN_INCREMENT: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type)]
+#[sqlx(rename_all = "kebab-case")]
+pub enum DeviceCodeStatus {
+	Pending,
+	Approved,
+	Denied,
+}
+
+/// A pending device authorization, as described in RFC 8628. The ID is the
+/// device code, which is only known by the client. The user code is shown to
+/// the user, so that they can find the request on another device.
+#[derive(Debug, Clone)]
+pub struct DeviceCode {
+	pub id: Uuid,
+	pub user_code: Box<str>,
+	pub client_id: Uuid,
+	pub scope: Box<str>,
+	pub exp: DateTime<Utc>,
+	pub interval: u32,
+	pub last_polled: Option<DateTime<Utc>>,
+	pub status: DeviceCodeStatus,
+	pub user_id: Option<Uuid>,
+}
+
+fn random_user_code() -> Box<str> {
+	let mut rng = rand::thread_rng();
+	(0..USER_CODE_LENGTH)
+		.map(|_| USER_CODE_CHARACTERS[rng.gen_range(0..USER_CODE_CHARACTERS.len())] as char)
+		.collect()
+}
+
+/// Remove anything that the user might have typed which isn't part of the
+/// code, like hyphens, spaces, and lowercase letters
+pub fn normalize_user_code(user_code: &str) -> Box<str> {
+	user_code
+		.chars()
+		.map(|c| c.to_ascii_uppercase())
+		.filter(|c| c.is_ascii() && USER_CODE_CHARACTERS.contains(&(*c as u8)))
+		.collect()
+}
+
+/// Split the user code in half with a hyphen, to make it easier to read
+pub fn format_user_code(user_code: &str) -> String {
+	let (left, right) = user_code.split_at(user_code.len() / 2);
+	format!("{left}-{right}")
+}
+
+impl DeviceCode {
+	pub async fn new(db: &MySqlPool, client_id: Uuid, scope: &str) -> Result<Self, RawUnexpected> {
+		let fifteen_minutes = Duration::minutes(15);
+
+		let id = new_id(db, db::device_code_exists).await?;
+		let user_code = loop {
+			let user_code = random_user_code();
+			if !db::user_code_exists(db, &user_code).await? {
+				break user_code;
+			}
+		};
+
+		let device_code = Self {
+			id,
+			user_code,
+			client_id,
+			scope: scope.into(),
+			exp: Utc::now() + fifteen_minutes,
+			interval: DEFAULT_INTERVAL,
+			last_polled: None,
+			status: DeviceCodeStatus::Pending,
+			user_id: None,
+		};
+		db::create_device_code(db, &device_code).await?;
+
+		Ok(device_code)
+	}
+
+	pub fn is_expired(&self) -> bool {
+		Utc::now() > self.exp
+	}
+
+	/// Returns `true` if the client polled before the interval was up
+	pub fn polled_too_soon(&self, now: DateTime<Utc>) -> bool {
+		self.last_polled
+			.is_some_and(|last_polled| now < last_polled + Duration::seconds(self.interval.into()))
+	}
+
+	pub fn expires_in(&self) -> i64 {
+		(self.exp - Utc::now()).num_seconds()
+	}
+}
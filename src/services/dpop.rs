@@ -0,0 +1,331 @@
+use base64::Engine;
+use chrono::serde::ts_seconds;
+use chrono::{DateTime, Duration, Utc};
+use exun::Expect;
+use raise::yeet;
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use thiserror::Error;
+use url::Url;
+
+use super::jws::{self, JwsError};
+use super::{crypto, db};
+
+/// The `typ` header of every DPoP proof
+const PROOF_TYPE: &str = "dpop+jwt";
+
+/// How far the issued at time of a proof can be from the current time. Proofs
+/// are remembered for this long, so that they can't be replayed.
+fn max_proof_age() -> Duration {
+	Duration::minutes(5)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct ProofClaims {
+	jti: Box<str>,
+	htm: Box<str>,
+	htu: Url,
+	#[serde(with = "ts_seconds")]
+	iat: DateTime<Utc>,
+	ath: Option<Box<str>>,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyProofError {
+	#[error("{0}")]
+	ParseJwtError(#[from] JwsError),
+	#[error("The token is not a DPoP proof")]
+	WrongType,
+	#[error("The proof does not include a supported public key")]
+	MissingKey,
+	#[error("The proof was made for a different HTTP method")]
+	WrongMethod,
+	#[error("The proof was made for a different URI")]
+	WrongUri,
+	#[error("The proof is too old, or was issued in the future")]
+	StaleProof,
+	#[error("The proof was made for a different access token")]
+	WrongAccessToken,
+	#[error("The proof has already been used")]
+	ReusedProof,
+}
+
+/// The URI without its query and fragment, which aren't covered by proofs
+fn target_uri(uri: &Url) -> Url {
+	let mut uri = uri.clone();
+	uri.set_query(None);
+	uri.set_fragment(None);
+	uri
+}
+
+/// The `ath` claim for the given access token
+fn access_token_hash(access_token: &str) -> Box<str> {
+	let hash = crypto::token_hash(access_token);
+	base64::engine::general_purpose::URL_SAFE_NO_PAD
+		.encode(hash)
+		.into_boxed_str()
+}
+
+/// A proof which passed every check that doesn't need the database
+struct CheckedProof {
+	/// The JWK thumbprint of the key that signed the proof
+	jkt: Box<str>,
+	/// Identifies the proof, so that it can't be used again
+	jti_hash: Box<[u8]>,
+	/// When the proof no longer needs to be remembered
+	exp: DateTime<Utc>,
+}
+
+fn check_proof(
+	proof: &str,
+	method: &str,
+	uri: &Url,
+	access_token: Option<&str>,
+	now: DateTime<Utc>,
+) -> Result<CheckedProof, VerifyProofError> {
+	let token = jws::decode(proof)?;
+	if token.header().typ() != Some(PROOF_TYPE) {
+		yeet!(VerifyProofError::WrongType)
+	}
+	let Some(jwk) = token.header().jwk().cloned() else {
+		yeet!(VerifyProofError::MissingKey)
+	};
+	let Some(key) = jwk.verifying_key() else {
+		yeet!(VerifyProofError::MissingKey)
+	};
+
+	let claims: ProofClaims = token.verify(&key)?;
+
+	if &*claims.htm != method {
+		yeet!(VerifyProofError::WrongMethod)
+	}
+
+	if target_uri(&claims.htu) != target_uri(uri) {
+		yeet!(VerifyProofError::WrongUri)
+	}
+
+	if claims.iat < now - max_proof_age() || claims.iat > now + max_proof_age() {
+		yeet!(VerifyProofError::StaleProof)
+	}
+
+	if let Some(access_token) = access_token {
+		if claims.ath.as_deref() != Some(&*access_token_hash(access_token)) {
+			yeet!(VerifyProofError::WrongAccessToken)
+		}
+	}
+
+	let jkt = jwk.thumbprint();
+	let jti_hash = crypto::token_hash(&format!("{jkt}:{}", claims.jti));
+	let exp = claims.iat + max_proof_age();
+
+	Ok(CheckedProof { jkt, jti_hash, exp })
+}
+
+/// Verify a DPoP proof, as described in RFC 9449. If the proof is sent with an
+/// access token, then it must be made for that token. Returns the JWK
+/// thumbprint of the key that signed the proof.
+pub async fn verify_proof(
+	db: &MySqlPool,
+	proof: &str,
+	method: &str,
+	uri: &Url,
+	access_token: Option<&str>,
+) -> Result<Box<str>, Expect<VerifyProofError>> {
+	let proof = check_proof(proof, method, uri, access_token, Utc::now())?;
+
+	if !db::use_dpop_proof(db, &proof.jti_hash, proof.exp).await? {
+		yeet!(VerifyProofError::ReusedProof.into())
+	}
+
+	Ok(proof.jkt)
+}
+
+#[cfg(test)]
+mod tests {
+	use base64::Engine;
+	use serde_json::{json, Value};
+
+	use super::*;
+	use crate::services::keys::{Algorithm, SigningKey};
+
+	fn base64_encode(bytes: &[u8]) -> String {
+		base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+	}
+
+	fn sign(key: &SigningKey, header: &Value, claims: &Value) -> String {
+		let header = base64_encode(&serde_json::to_vec(header).unwrap());
+		let payload = base64_encode(&serde_json::to_vec(claims).unwrap());
+		let signing_input = format!("{header}.{payload}");
+		let signature = key.sign(signing_input.as_bytes()).unwrap();
+		format!("{signing_input}.{}", base64_encode(&signature))
+	}
+
+	fn proof(key: &SigningKey, claims: Value) -> String {
+		let header = json!({
+			"typ": PROOF_TYPE,
+			"alg": key.algorithm(),
+			"jwk": key.verifying_key().to_jwk(),
+		});
+		sign(key, &header, &claims)
+	}
+
+	fn claims(now: DateTime<Utc>) -> Value {
+		json!({
+			"jti": "proof",
+			"htm": "POST",
+			"htu": "https://example.com/oauth/token",
+			"iat": now.timestamp(),
+		})
+	}
+
+	fn token_uri() -> Url {
+		Url::parse("https://example.com/oauth/token").unwrap()
+	}
+
+	fn check(proof: &str, access_token: Option<&str>) -> Result<CheckedProof, VerifyProofError> {
+		check_proof(proof, "POST", &token_uri(), access_token, Utc::now())
+	}
+
+	#[test]
+	fn accepts_valid_proof() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now()));
+		let checked = check(&proof, None).unwrap();
+		assert_eq!(checked.jkt, key.verifying_key().to_jwk().thumbprint());
+	}
+
+	#[test]
+	fn rejects_wrong_type() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let header = json!({
+			"typ": "JWT",
+			"alg": key.algorithm(),
+			"jwk": key.verifying_key().to_jwk(),
+		});
+		let proof = sign(&key, &header, &claims(Utc::now()));
+		assert!(matches!(
+			check(&proof, None),
+			Err(VerifyProofError::WrongType)
+		));
+	}
+
+	#[test]
+	fn rejects_missing_key() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let header = json!({ "typ": PROOF_TYPE, "alg": key.algorithm() });
+		let proof = sign(&key, &header, &claims(Utc::now()));
+		assert!(matches!(
+			check(&proof, None),
+			Err(VerifyProofError::MissingKey)
+		));
+	}
+
+	#[test]
+	fn rejects_proof_signed_by_other_key() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let other = SigningKey::generate(Algorithm::Es256).unwrap();
+		let header = json!({
+			"typ": PROOF_TYPE,
+			"alg": key.algorithm(),
+			"jwk": other.verifying_key().to_jwk(),
+		});
+		let proof = sign(&key, &header, &claims(Utc::now()));
+		assert!(matches!(
+			check(&proof, None),
+			Err(VerifyProofError::ParseJwtError(JwsError::InvalidSignature))
+		));
+	}
+
+	#[test]
+	fn rejects_wrong_method() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now()));
+		let result = check_proof(&proof, "GET", &token_uri(), None, Utc::now());
+		assert!(matches!(result, Err(VerifyProofError::WrongMethod)));
+	}
+
+	#[test]
+	fn rejects_wrong_uri() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now()));
+		let uri = Url::parse("https://example.com/oauth/revoke").unwrap();
+		let result = check_proof(&proof, "POST", &uri, None, Utc::now());
+		assert!(matches!(result, Err(VerifyProofError::WrongUri)));
+	}
+
+	#[test]
+	fn ignores_query_and_fragment_in_uri() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let mut claims = claims(Utc::now());
+		claims["htu"] = json!("https://example.com/oauth/token?query#fragment");
+		let proof = proof(&key, claims);
+		assert!(check(&proof, None).is_ok());
+	}
+
+	#[test]
+	fn rejects_old_proof() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now() - Duration::minutes(6)));
+		assert!(matches!(
+			check(&proof, None),
+			Err(VerifyProofError::StaleProof)
+		));
+	}
+
+	#[test]
+	fn rejects_proof_from_future() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now() + Duration::minutes(6)));
+		assert!(matches!(
+			check(&proof, None),
+			Err(VerifyProofError::StaleProof)
+		));
+	}
+
+	#[test]
+	fn checks_access_token_hash() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let mut claims = claims(Utc::now());
+		claims["ath"] = json!(access_token_hash("token"));
+		let proof = proof(&key, claims);
+
+		assert!(check(&proof, Some("token")).is_ok());
+		assert!(matches!(
+			check(&proof, Some("other token")),
+			Err(VerifyProofError::WrongAccessToken)
+		));
+	}
+
+	#[test]
+	fn requires_access_token_hash_with_access_token() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let proof = proof(&key, claims(Utc::now()));
+		assert!(matches!(
+			check(&proof, Some("token")),
+			Err(VerifyProofError::WrongAccessToken)
+		));
+	}
+
+	#[test]
+	fn replays_are_recognized() {
+		// the same proof is remembered under the same hash, until it's too old
+		// to be accepted anyway
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let now = Utc::now();
+		let proof = proof(&key, claims(now));
+		let first = check(&proof, None).unwrap();
+		let second = check(&proof, None).unwrap();
+		assert_eq!(first.jti_hash, second.jti_hash);
+		assert_eq!(first.exp.timestamp(), now.timestamp() + 300);
+	}
+
+	#[test]
+	fn jti_is_scoped_to_key() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let other = SigningKey::generate(Algorithm::Es256).unwrap();
+		let now = Utc::now();
+		let first = check(&proof(&key, claims(now)), None).unwrap();
+		let second = check(&proof(&other, claims(now)), None).unwrap();
+		assert_ne!(first.jti_hash, second.jti_hash);
+	}
+}
@@ -0,0 +1,104 @@
+use base64::Engine;
+use chrono::{serde::ts_seconds, serde::ts_seconds_option, DateTime, Duration, Utc};
+use exun::RawUnexpected;
+use serde::Serialize;
+use sha2::{Digest, Sha256, Sha512};
+use sqlx::MySqlPool;
+use url::Url;
+use uuid::Uuid;
+
+use super::jws;
+use super::key_set;
+use super::keys::Algorithm;
+
+/// The claims of an OpenID Connect ID token. Unlike the other tokens, these
+/// timestamps are in seconds, because that's what relying parties expect.
+#[derive(Debug, Clone, Serialize)]
+pub struct IdTokenClaims {
+	iss: Url,
+	sub: Uuid,
+	aud: Box<str>,
+	#[serde(with = "ts_seconds")]
+	exp: DateTime<Utc>,
+	#[serde(with = "ts_seconds")]
+	iat: DateTime<Utc>,
+	#[serde(with = "ts_seconds_option", skip_serializing_if = "Option::is_none")]
+	auth_time: Option<DateTime<Utc>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	nonce: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	at_hash: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	c_hash: Option<Box<str>>,
+}
+
+/// The left-most half of the hash of the value, using the hash function of
+/// the algorithm that the ID token is signed with. This is used for the
+/// `at_hash` and `c_hash` claims.
+fn half_hash(algorithm: Algorithm, value: &str) -> Box<str> {
+	let hash = match algorithm {
+		Algorithm::Rs256 | Algorithm::Es256 => Sha256::digest(value.as_bytes()).to_vec(),
+		// Ed25519 uses SHA-512 internally
+		Algorithm::EdDsa => Sha512::digest(value.as_bytes()).to_vec(),
+	};
+
+	base64::engine::general_purpose::URL_SAFE_NO_PAD
+		.encode(&hash[..hash.len() / 2])
+		.into_boxed_str()
+}
+
+impl IdTokenClaims {
+	/// Every claim that an ID token might have
+	pub const CLAIMS: &'static [&'static str] = &[
+		"iss",
+		"sub",
+		"aud",
+		"exp",
+		"iat",
+		"auth_time",
+		"nonce",
+		"at_hash",
+		"c_hash",
+	];
+
+	/// The audience is the client's alias, since that's the `client_id` that
+	/// the client knows itself by.
+	pub fn new(
+		self_id: Url,
+		client_alias: &str,
+		sub: Uuid,
+		auth_time: Option<DateTime<Utc>>,
+		nonce: Option<&str>,
+	) -> Self {
+		let iat = Utc::now();
+		let exp = iat + Duration::hours(1);
+
+		Self {
+			iss: self_id,
+			sub,
+			aud: client_alias.into(),
+			exp,
+			iat,
+			auth_time,
+			nonce: nonce.map(Box::from),
+			at_hash: None,
+			c_hash: None,
+		}
+	}
+
+	/// Sign the ID token with the active key. If the token is issued alongside
+	/// an access token or authorization code, then their hashes are included.
+	pub async fn to_jwt(
+		&self,
+		db: &MySqlPool,
+		access_token: Option<&str>,
+		code: Option<&str>,
+	) -> Result<Box<str>, RawUnexpected> {
+		let key = key_set::active_signing_key(db).await?;
+		let mut claims = self.clone();
+		claims.at_hash = access_token.map(|token| half_hash(key.algorithm(), token));
+		claims.c_hash = code.map(|code| half_hash(key.algorithm(), code));
+
+		jws::encode(&jws::Header::jwt(&key), &claims, &key)
+	}
+}
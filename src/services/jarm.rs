@@ -0,0 +1,40 @@
+use chrono::{serde::ts_seconds, DateTime, Duration, Utc};
+use exun::RawUnexpected;
+use serde::Serialize;
+use sqlx::MySqlPool;
+use url::Url;
+
+use super::jws;
+use super::key_set;
+
+/// The claims of a JWT-secured authorization response. The parameters that
+/// would've been in the redirect URI are included as claims.
+#[derive(Debug, Clone, Serialize)]
+struct ResponseClaims<'a, T: Serialize> {
+	iss: Url,
+	aud: &'a str,
+	#[serde(with = "ts_seconds")]
+	exp: DateTime<Utc>,
+	#[serde(flatten)]
+	response: &'a T,
+}
+
+/// Sign an authorization response, or an authorization error, with the active
+/// key. The audience is the client's alias, like it is in ID tokens. The
+/// response is only meant to be read once, so it expires quickly.
+pub async fn encode_response<T: Serialize>(
+	db: &MySqlPool,
+	self_id: Url,
+	client_alias: &str,
+	response: &T,
+) -> Result<Box<str>, RawUnexpected> {
+	let claims = ResponseClaims {
+		iss: self_id,
+		aud: client_alias,
+		exp: Utc::now() + Duration::minutes(10),
+		response,
+	};
+
+	let key = key_set::active_signing_key(db).await?;
+	jws::encode(&jws::Header::jwt(&key), &claims, &key)
+}
@@ -0,0 +1,270 @@
+use base64::Engine;
+use exun::RawUnexpected;
+use ring::hmac;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use super::keys::{Algorithm, Jwk, SigningKey, VerifyingKey};
+
+/// The algorithms which can be used to MAC a token. These are only used by
+/// clients, which use their client secret as the key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum MacAlgorithm {
+	#[serde(rename = "HS256")]
+	Hs256,
+}
+
+/// The `alg` header, which is either a signature or a MAC algorithm
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+enum HeaderAlgorithm {
+	Signature(Algorithm),
+	Mac(MacAlgorithm),
+}
+
+/// The protected header of a JSON Web Signature
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Header {
+	alg: HeaderAlgorithm,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	kid: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	typ: Option<Box<str>>,
+	/// The public key is sent with some tokens, such as DPoP proofs
+	#[serde(skip_serializing_if = "Option::is_none")]
+	jwk: Option<Jwk>,
+}
+
+/// A token whose signature hasn't been checked yet. The header can be read in
+/// order to find the key that the token should be verified with.
+pub struct UnverifiedToken<'a> {
+	header: Header,
+	signing_input: &'a str,
+	payload: Vec<u8>,
+	signature: Vec<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum JwsError {
+	#[error("The token is not a valid JWS")]
+	Malformed,
+	#[error("The key that signed the token is unknown")]
+	UnknownKey,
+	#[error("The token's algorithm doesn't match its signing key")]
+	WrongAlgorithm,
+	#[error("The token's signature is invalid")]
+	InvalidSignature,
+}
+
+fn base64_encode(bytes: &[u8]) -> String {
+	base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn base64_decode(value: &str) -> Result<Vec<u8>, JwsError> {
+	base64::engine::general_purpose::URL_SAFE_NO_PAD
+		.decode(value)
+		.map_err(|_| JwsError::Malformed)
+}
+
+impl Header {
+	/// The header for a JWT signed by the given key
+	pub fn jwt(key: &SigningKey) -> Self {
+		Self {
+			alg: HeaderAlgorithm::Signature(key.algorithm()),
+			kid: Some(key.kid().into()),
+			typ: Some("JWT".into()),
+			jwk: None,
+		}
+	}
+
+	pub fn kid(&self) -> Option<&str> {
+		self.kid.as_deref()
+	}
+
+	pub fn typ(&self) -> Option<&str> {
+		self.typ.as_deref()
+	}
+
+	pub fn jwk(&self) -> Option<&Jwk> {
+		self.jwk.as_ref()
+	}
+}
+
+/// Serialize and sign the claims, using the JWS compact serialization
+pub fn encode<T: Serialize>(
+	header: &Header,
+	claims: &T,
+	key: &SigningKey,
+) -> Result<Box<str>, RawUnexpected> {
+	let header = base64_encode(&serde_json::to_vec(header)?);
+	let payload = base64_encode(&serde_json::to_vec(claims)?);
+	let signing_input = format!("{header}.{payload}");
+	let signature = base64_encode(&key.sign(signing_input.as_bytes())?);
+
+	Ok(format!("{signing_input}.{signature}").into_boxed_str())
+}
+
+/// Split a compact JWS into its parts, without checking the signature
+pub fn decode(token: &str) -> Result<UnverifiedToken<'_>, JwsError> {
+	let (signing_input, signature) = token.rsplit_once('.').ok_or(JwsError::Malformed)?;
+	let (header, payload) = signing_input.split_once('.').ok_or(JwsError::Malformed)?;
+
+	let header =
+		serde_json::from_slice(&base64_decode(header)?).map_err(|_| JwsError::Malformed)?;
+
+	Ok(UnverifiedToken {
+		header,
+		signing_input,
+		payload: base64_decode(payload)?,
+		signature: base64_decode(signature)?,
+	})
+}
+
+impl<'a> UnverifiedToken<'a> {
+	pub fn header(&self) -> &Header {
+		&self.header
+	}
+
+	/// Deserialize the claims without checking the signature. This is only for
+	/// finding out who made the token, so that the right key can be used.
+	pub fn unverified_claims<T: DeserializeOwned>(&self) -> Result<T, JwsError> {
+		serde_json::from_slice(&self.payload).map_err(|_| JwsError::Malformed)
+	}
+
+	/// Check the signature, and then deserialize the claims
+	pub fn verify<T: DeserializeOwned>(self, key: &VerifyingKey) -> Result<T, JwsError> {
+		if self.header.alg != HeaderAlgorithm::Signature(key.algorithm()) {
+			return Err(JwsError::WrongAlgorithm);
+		}
+
+		if !key.verify(self.signing_input.as_bytes(), &self.signature) {
+			return Err(JwsError::InvalidSignature);
+		}
+
+		self.unverified_claims()
+	}
+
+	/// Check the MAC using the shared secret, and then deserialize the claims
+	pub fn verify_mac<T: DeserializeOwned>(self, secret: &[u8]) -> Result<T, JwsError> {
+		if self.header.alg != HeaderAlgorithm::Mac(MacAlgorithm::Hs256) {
+			return Err(JwsError::WrongAlgorithm);
+		}
+
+		let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+		if hmac::verify(&key, self.signing_input.as_bytes(), &self.signature).is_err() {
+			return Err(JwsError::InvalidSignature);
+		}
+
+		self.unverified_claims()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use serde_json::{json, Value};
+
+	use super::*;
+
+	fn token(key: &SigningKey) -> Box<str> {
+		encode(&Header::jwt(key), &json!({ "sub": "user" }), key).unwrap()
+	}
+
+	fn mac_token(secret: &[u8]) -> String {
+		let header = Header {
+			alg: HeaderAlgorithm::Mac(MacAlgorithm::Hs256),
+			kid: None,
+			typ: Some("JWT".into()),
+			jwk: None,
+		};
+		let header = base64_encode(&serde_json::to_vec(&header).unwrap());
+		let payload = base64_encode(br#"{"sub":"client"}"#);
+		let signing_input = format!("{header}.{payload}");
+		let key = hmac::Key::new(hmac::HMAC_SHA256, secret);
+		let tag = hmac::sign(&key, signing_input.as_bytes());
+		format!("{signing_input}.{}", base64_encode(tag.as_ref()))
+	}
+
+	#[test]
+	fn signed_token_verifies() {
+		for algorithm in [Algorithm::Es256, Algorithm::EdDsa] {
+			let key = SigningKey::generate(algorithm).unwrap();
+			let token = token(&key);
+			let decoded = decode(&token).unwrap();
+			assert_eq!(decoded.header().kid(), Some(key.kid()));
+			assert_eq!(decoded.header().typ(), Some("JWT"));
+			let claims: Value = decoded.verify(&key.verifying_key()).unwrap();
+			assert_eq!(claims, json!({ "sub": "user" }));
+		}
+	}
+
+	#[test]
+	fn rejects_other_key() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let other = SigningKey::generate(Algorithm::Es256).unwrap();
+		let token = token(&key);
+		let result = decode(&token)
+			.unwrap()
+			.verify::<Value>(&other.verifying_key());
+		assert_eq!(result, Err(JwsError::InvalidSignature));
+	}
+
+	#[test]
+	fn rejects_algorithm_that_does_not_match_key() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let other = SigningKey::generate(Algorithm::EdDsa).unwrap();
+		let token = token(&key);
+		let result = decode(&token)
+			.unwrap()
+			.verify::<Value>(&other.verifying_key());
+		assert_eq!(result, Err(JwsError::WrongAlgorithm));
+	}
+
+	#[test]
+	fn rejects_modified_payload() {
+		let key = SigningKey::generate(Algorithm::EdDsa).unwrap();
+		let token = token(&key);
+		let (header, rest) = token.split_once('.').unwrap();
+		let (_, signature) = rest.split_once('.').unwrap();
+		let payload = base64_encode(br#"{"sub":"admin"}"#);
+		let forged = format!("{header}.{payload}.{signature}");
+		let result = decode(&forged)
+			.unwrap()
+			.verify::<Value>(&key.verifying_key());
+		assert_eq!(result, Err(JwsError::InvalidSignature));
+	}
+
+	#[test]
+	fn mac_token_verifies() {
+		let token = mac_token(b"secret");
+		let claims: Value = decode(&token).unwrap().verify_mac(b"secret").unwrap();
+		assert_eq!(claims, json!({ "sub": "client" }));
+	}
+
+	#[test]
+	fn rejects_mac_with_wrong_secret() {
+		let token = mac_token(b"secret");
+		let result = decode(&token).unwrap().verify_mac::<Value>(b"other");
+		assert_eq!(result, Err(JwsError::InvalidSignature));
+	}
+
+	#[test]
+	fn mac_and_signature_algorithms_are_not_interchangeable() {
+		// a MAC token can't be verified with a public key
+		let key = SigningKey::generate(Algorithm::EdDsa).unwrap();
+		let result = decode(&mac_token(b"secret"))
+			.unwrap()
+			.verify::<Value>(&key.verifying_key());
+		assert_eq!(result, Err(JwsError::WrongAlgorithm));
+
+		// and a signed token can't be verified with a secret
+		let result = decode(&token(&key)).unwrap().verify_mac::<Value>(b"secret");
+		assert_eq!(result, Err(JwsError::WrongAlgorithm));
+	}
+
+	#[test]
+	fn rejects_malformed_tokens() {
+		assert!(matches!(decode("not a token"), Err(JwsError::Malformed)));
+		assert!(matches!(decode("a.b"), Err(JwsError::Malformed)));
+		assert!(matches!(decode("!!.e30.e30"), Err(JwsError::Malformed)));
+	}
+}
@@ -1,14 +1,17 @@
 use chrono::{serde::ts_milliseconds, serde::ts_milliseconds_option, DateTime, Duration, Utc};
 use exun::{Expect, RawUnexpected, ResultErrorExt};
-use jwt::{SignWithKey, VerifyWithKey};
 use raise::yeet;
 use serde::{Deserialize, Serialize};
-use sqlx::{Executor, MySql, MySqlPool};
+use sqlx::MySqlPool;
 use thiserror::Error;
 use url::Url;
 use uuid::Uuid;
 
-use super::{db, id::new_id, secrets};
+use super::authorization_details::AuthorizationDetails;
+use super::jws::{self, JwsError};
+use super::{db, id::new_id, key_set, pkce::CodeChallenge};
+use crate::models::resource_server::ResourceServer;
+use crate::scopes;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum TokenType {
@@ -32,8 +35,43 @@ pub struct Claims {
 	scope: Box<str>,
 	client_id: Uuid,
 	token_type: TokenType,
-	auth_code_id: Option<Uuid>,
+	/// Every token which came from the same authorization is part of the same
+	/// grant, so that they can be revoked together
+	grant_id: Uuid,
 	redirect_uri: Option<Url>,
+	code_challenge: Option<CodeChallenge>,
+	#[serde(default, with = "ts_milliseconds_option")]
+	auth_time: Option<DateTime<Utc>>,
+	nonce: Option<Box<str>>,
+	#[serde(default)]
+	act: Option<Actor>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	cnf: Option<Confirmation>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	authorization_details: Option<AuthorizationDetails>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	resource: Option<Url>,
+}
+
+/// The party that a delegated token was issued to, as described in RFC 8693.
+/// If the token was exchanged more than once, then the previous actors are
+/// nested inside.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Actor {
+	sub: Uuid,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	act: Option<Box<Actor>>,
+}
+
+/// The key that a sender-constrained token is bound to, as described in
+/// RFC 7800. DPoP tokens use the key's JWK thumbprint, and certificate-bound
+/// tokens use the SHA-256 hash of the client's certificate.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Confirmation {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	jkt: Option<Box<str>>,
+	#[serde(rename = "x5t#S256", skip_serializing_if = "Option::is_none")]
+	x5t_s256: Option<Box<str>>,
 }
 
 #[derive(Debug, Clone, Copy, sqlx::Type)]
@@ -41,6 +79,27 @@ pub struct Claims {
 pub enum RevokedRefreshTokenReason {
 	ReusedAuthorizationCode,
 	NewRefreshToken,
+	/// The client asked for the token to be revoked, or deleted itself
+	Revoked,
+	/// The user revoked their consent for the client
+	ConsentRevoked,
+}
+
+/// The longest that an access token can last. Longer durations are cut short.
+fn max_access_token_lifetime() -> Duration {
+	Duration::hours(1)
+}
+
+/// How much longer a refresh token lasts than the access token it came with
+fn refresh_token_extension() -> Duration {
+	Duration::days(1)
+}
+
+/// The longest that any token can last, which is a refresh token issued with
+/// the longest possible access token. Signing keys need to be kept for at least
+/// this long after they're retired.
+pub fn max_token_lifetime() -> Duration {
+	max_access_token_lifetime() + refresh_token_extension()
 }
 
 impl Claims {
@@ -51,6 +110,7 @@ impl Claims {
 		sub: Uuid,
 		scopes: &str,
 		redirect_uri: &Url,
+		code_challenge: Option<CodeChallenge>,
 	) -> Result<Self, RawUnexpected> {
 		let five_minutes = Duration::minutes(5);
 
@@ -72,15 +132,71 @@ impl Claims {
 			jti: id,
 			scope: scopes.into(),
 			client_id,
-			auth_code_id: Some(id),
+			grant_id: id,
 			token_type: TokenType::Authorization,
 			redirect_uri: Some(redirect_uri.clone()),
+			code_challenge,
+			auth_time: Some(iat),
+			nonce: None,
+			act: None,
+			cnf: None,
+			authorization_details: None,
+			resource: None,
 		})
 	}
 
+	/// The nonce is passed along to the ID token, if the client requests one
+	pub fn with_nonce(mut self, nonce: Option<&str>) -> Self {
+		self.nonce = nonce.map(Box::from);
+		self
+	}
+
+	/// The fine-grained permissions that the token grants, as described in
+	/// RFC 9396. These replace any details that the token already had.
+	pub fn with_authorization_details(mut self, details: Option<&AuthorizationDetails>) -> Self {
+		self.authorization_details = details.cloned();
+		self
+	}
+
+	/// The resource server that the tokens from this grant were requested for,
+	/// as described in RFC 8707
+	pub fn with_resource(mut self, resource: Option<&Url>) -> Self {
+		self.resource = resource.cloned();
+		self
+	}
+
+	/// Restrict the token to a single resource server. The token can only be
+	/// used there, and only keeps the scopes which the resource server owns.
+	pub fn restrict_to(mut self, resource_server: Option<&ResourceServer>) -> Self {
+		if let Some(resource_server) = resource_server {
+			self.aud = [resource_server.uri().to_string()].into();
+			self.scope = scopes::intersection(&self.scope, resource_server.scopes()).into();
+			self.resource = Some(resource_server.uri().clone());
+		}
+		self
+	}
+
+	/// Bind the token to the key with the given JWK thumbprint, so that it can
+	/// only be used with a DPoP proof from that key
+	pub fn with_jkt(mut self, jkt: Option<&str>) -> Self {
+		if let Some(jkt) = jkt {
+			self.cnf.get_or_insert_with(Confirmation::default).jkt = Some(jkt.into());
+		}
+		self
+	}
+
+	/// Bind the token to the client certificate with the given thumbprint, so
+	/// that it can only be used over a connection with that certificate
+	pub fn with_x5t_s256(mut self, x5t_s256: Option<&str>) -> Self {
+		if let Some(x5t_s256) = x5t_s256 {
+			self.cnf.get_or_insert_with(Confirmation::default).x5t_s256 = Some(x5t_s256.into());
+		}
+		self
+	}
+
 	pub async fn access_token<'c>(
 		db: &MySqlPool,
-		auth_code_id: Option<Uuid>,
+		auth_code: Option<&Claims>,
 		self_id: Url,
 		client_id: Uuid,
 		sub: Uuid,
@@ -89,9 +205,11 @@ impl Claims {
 	) -> Result<Self, RawUnexpected> {
 		let id = new_id(db, db::access_token_exists).await?;
 		let iat = Utc::now();
-		let exp = iat + duration;
+		let exp = iat + duration.min(max_access_token_lifetime());
+		// a grant without an authorization code starts with this token
+		let grant_id = auth_code.map_or(id, |code| code.grant_id);
 
-		db::create_access_token(db, id, auth_code_id, exp)
+		db::create_access_token(db, id, grant_id, client_id, exp)
 			.await
 			.unexpect()?;
 
@@ -107,9 +225,72 @@ impl Claims {
 			jti: id,
 			scope: scopes.into(),
 			client_id,
-			auth_code_id,
+			grant_id,
 			token_type: TokenType::Access,
 			redirect_uri: None,
+			code_challenge: None,
+			auth_time: auth_code.and_then(|code| code.auth_time),
+			nonce: None,
+			act: None,
+			cnf: None,
+			authorization_details: auth_code.and_then(|code| code.authorization_details.clone()),
+			resource: auth_code.and_then(|code| code.resource.clone()),
+		})
+	}
+
+	/// An access token for the subject of another token, which the client can
+	/// use on the subject's behalf. The actor is whoever the token is being
+	/// delegated to. The new token never outlives the subject token, and keeps
+	/// its binding and restrictions.
+	pub async fn exchanged_token(
+		db: &MySqlPool,
+		subject_token: &Claims,
+		actor: Uuid,
+		client_id: Uuid,
+		audience: &[String],
+		duration: Duration,
+		scopes: &str,
+	) -> Result<Self, RawUnexpected> {
+		let id = new_id(db, db::access_token_exists).await?;
+		let iat = Utc::now();
+		let exp = (iat + duration.min(max_access_token_lifetime())).min(subject_token.exp);
+		let grant_id = subject_token.grant_id;
+
+		db::create_access_token(db, id, grant_id, client_id, exp).await?;
+
+		let self_id = subject_token.iss.clone();
+		let mut aud = vec![self_id.to_string()];
+		if audience.is_empty() {
+			aud.push(client_id.to_string());
+		} else {
+			aud.extend_from_slice(audience);
+		}
+
+		let act = Actor {
+			sub: actor,
+			act: subject_token.act.clone().map(Box::new),
+		};
+
+		Ok(Self {
+			iss: self_id,
+			sub: subject_token.sub,
+			aud: aud.into_boxed_slice(),
+			exp,
+			nbf: None,
+			iat,
+			jti: id,
+			scope: scopes.into(),
+			client_id,
+			grant_id,
+			token_type: TokenType::Access,
+			redirect_uri: None,
+			code_challenge: None,
+			auth_time: subject_token.auth_time,
+			nonce: None,
+			act: Some(act),
+			cnf: subject_token.cnf.clone(),
+			authorization_details: subject_token.authorization_details.clone(),
+			resource: subject_token.resource.clone(),
 		})
 	}
 
@@ -117,13 +298,14 @@ impl Claims {
 		db: &MySqlPool,
 		other_token: &Claims,
 	) -> Result<Self, RawUnexpected> {
-		let one_day = Duration::days(1);
-
 		let id = new_id(db, db::refresh_token_exists).await?;
 		let iat = Utc::now();
-		let exp = other_token.exp + one_day;
+		// the other token might be a refresh token, which shouldn't keep getting
+		// pushed further out every time that it's used
+		let exp =
+			other_token.exp.min(iat + max_access_token_lifetime()) + refresh_token_extension();
 
-		db::create_refresh_token(db, id, other_token.auth_code_id, exp).await?;
+		db::create_refresh_token(db, id, other_token.grant_id, other_token.client_id, exp).await?;
 
 		let mut claims = other_token.clone();
 		claims.exp = exp;
@@ -134,21 +316,25 @@ impl Claims {
 		Ok(claims)
 	}
 
+	/// The scopes can be narrowed down from the ones in the refresh token
 	pub async fn refreshed_access_token(
 		db: &MySqlPool,
 		refresh_token: &Claims,
 		exp_time: Duration,
+		scopes: &str,
 	) -> Result<Self, RawUnexpected> {
 		let id = new_id(db, db::access_token_exists).await?;
 		let iat = Utc::now();
-		let exp = iat + exp_time;
+		let exp = iat + exp_time.min(max_access_token_lifetime());
 
-		db::create_access_token(db, id, refresh_token.auth_code_id, exp).await?;
+		db::create_access_token(db, id, refresh_token.grant_id, refresh_token.client_id, exp)
+			.await?;
 
 		let mut claims = refresh_token.clone();
 		claims.exp = exp;
 		claims.iat = iat;
 		claims.jti = id;
+		claims.scope = scopes.into();
 		claims.token_type = TokenType::Access;
 
 		Ok(claims)
@@ -158,6 +344,34 @@ impl Claims {
 		self.jti
 	}
 
+	pub fn grant_id(&self) -> Uuid {
+		self.grant_id
+	}
+
+	pub fn issuer(&self) -> &Url {
+		&self.iss
+	}
+
+	pub fn audience(&self) -> &[String] {
+		&self.aud
+	}
+
+	pub fn issued_at(&self) -> DateTime<Utc> {
+		self.iat
+	}
+
+	pub fn not_before(&self) -> Option<DateTime<Utc>> {
+		self.nbf
+	}
+
+	pub fn expiration(&self) -> DateTime<Utc> {
+		self.exp
+	}
+
+	pub fn token_type(&self) -> TokenType {
+		self.token_type
+	}
+
 	pub fn subject(&self) -> Uuid {
 		self.sub
 	}
@@ -174,17 +388,60 @@ impl Claims {
 		self.client_id
 	}
 
-	pub fn to_jwt(&self) -> Result<Box<str>, RawUnexpected> {
-		let key = secrets::signing_key()?;
-		let jwt = self.sign_with_key(&key)?.into_boxed_str();
-		Ok(jwt)
+	pub fn code_challenge(&self) -> Option<&CodeChallenge> {
+		self.code_challenge.as_ref()
+	}
+
+	pub fn auth_time(&self) -> Option<DateTime<Utc>> {
+		self.auth_time
+	}
+
+	pub fn nonce(&self) -> Option<&str> {
+		self.nonce.as_deref()
+	}
+
+	pub fn actor(&self) -> Option<&Actor> {
+		self.act.as_ref()
+	}
+
+	pub fn confirmation(&self) -> Option<&Confirmation> {
+		self.cnf.as_ref()
+	}
+
+	pub fn authorization_details(&self) -> Option<&AuthorizationDetails> {
+		self.authorization_details.as_ref()
+	}
+
+	pub fn resource(&self) -> Option<&Url> {
+		self.resource.as_ref()
+	}
+
+	/// Returns `true` if the token can be used at the given audience
+	pub fn is_intended_for(&self, audience: &Url) -> bool {
+		self.aud.contains(&audience.to_string())
+	}
+
+	/// The thumbprint of the key that the token is bound to, if any
+	pub fn jkt(&self) -> Option<&str> {
+		self.cnf.as_ref().and_then(|cnf| cnf.jkt.as_deref())
+	}
+
+	/// The thumbprint of the client certificate that the token is bound to, if
+	/// any
+	pub fn x5t_s256(&self) -> Option<&str> {
+		self.cnf.as_ref().and_then(|cnf| cnf.x5t_s256.as_deref())
+	}
+
+	pub async fn to_jwt(&self, db: &MySqlPool) -> Result<Box<str>, RawUnexpected> {
+		let key = key_set::active_signing_key(db).await?;
+		jws::encode(&jws::Header::jwt(&key), self, &key)
 	}
 }
 
 #[derive(Debug, Error)]
 pub enum VerifyJwtError {
 	#[error("{0}")]
-	ParseJwtError(#[from] jwt::Error),
+	ParseJwtError(#[from] JwsError),
 	#[error("The issuer for this token is incorrect")]
 	IncorrectIssuer,
 	#[error("This bearer token was intended for a different client")]
@@ -199,17 +456,28 @@ pub enum VerifyJwtError {
 	NotYet,
 	#[error("The bearer token has been revoked")]
 	JwtRevoked,
+	#[error("This type of token is not supported by this endpoint")]
+	UnsupportedTokenType,
 }
 
-fn verify_jwt(
+/// The audience isn't checked here, because an access token which is
+/// restricted to a resource server can still be introspected, revoked, or
+/// exchanged at this server
+async fn verify_jwt(
+	db: &MySqlPool,
 	token: &str,
 	self_id: &Url,
 	client_id: Option<Uuid>,
 ) -> Result<Claims, Expect<VerifyJwtError>> {
-	let key = secrets::signing_key()?;
-	let claims: Claims = token
-		.verify_with_key(&key)
-		.map_err(|e| VerifyJwtError::from(e))?;
+	let token = jws::decode(token).map_err(VerifyJwtError::from)?;
+	let Some(kid) = token.header().kid() else {
+		yeet!(VerifyJwtError::from(JwsError::UnknownKey).into())
+	};
+	let Some(key) = key_set::verifying_key(db, kid).await? else {
+		yeet!(VerifyJwtError::from(JwsError::UnknownKey).into())
+	};
+
+	let claims: Claims = token.verify(&key).map_err(VerifyJwtError::from)?;
 
 	if &claims.iss != self_id {
 		yeet!(VerifyJwtError::IncorrectIssuer.into())
@@ -221,10 +489,6 @@ fn verify_jwt(
 		}
 	}
 
-	if !claims.aud.contains(&self_id.to_string()) {
-		yeet!(VerifyJwtError::BadAudience.into())
-	}
-
 	let now = Utc::now();
 
 	if now > claims.exp {
@@ -247,7 +511,11 @@ pub async fn verify_auth_code<'c>(
 	client_id: Uuid,
 	redirect_uri: Url,
 ) -> Result<Claims, Expect<VerifyJwtError>> {
-	let claims = verify_jwt(token, self_id, Some(client_id))?;
+	let claims = verify_jwt(db, token, self_id, Some(client_id)).await?;
+
+	if !claims.is_intended_for(self_id) {
+		yeet!(VerifyJwtError::BadAudience.into())
+	}
 
 	if let Some(claimed_uri) = &claims.redirect_uri {
 		if claimed_uri.clone() != redirect_uri {
@@ -255,22 +523,50 @@ pub async fn verify_auth_code<'c>(
 		}
 	}
 
-	if db::delete_auth_code(db, claims.jti).await? {
-		db::delete_access_tokens_with_auth_code(db, claims.jti).await?;
-		db::revoke_refresh_tokens_with_auth_code(db, claims.jti).await?;
+	if !db::auth_code_exists(db, claims.jti).await? {
+		revoke_reused_auth_code(db, &claims).await?;
 		yeet!(VerifyJwtError::JwtRevoked.into());
 	}
 
 	Ok(claims)
 }
 
-pub async fn verify_access_token<'c>(
-	db: impl Executor<'c, Database = MySql>,
+/// Use up an authorization code from [`verify_auth_code`], so that it can't be
+/// used again. This should only be done once the client has been
+/// authenticated, and every other check on the request has passed.
+pub async fn consume_auth_code(
+	db: &MySqlPool,
+	claims: &Claims,
+) -> Result<(), Expect<VerifyJwtError>> {
+	if db::delete_auth_code(db, claims.jti).await? {
+		revoke_reused_auth_code(db, claims).await?;
+		yeet!(VerifyJwtError::JwtRevoked.into());
+	}
+
+	Ok(())
+}
+
+/// An authorization code which is used twice might have been stolen, so every
+/// token that came from it is revoked
+async fn revoke_reused_auth_code(db: &MySqlPool, claims: &Claims) -> Result<(), RawUnexpected> {
+	db::delete_access_tokens_with_grant(db, claims.grant_id).await?;
+	db::revoke_refresh_tokens_with_grant(
+		db,
+		claims.grant_id,
+		RevokedRefreshTokenReason::ReusedAuthorizationCode,
+	)
+	.await?;
+
+	Ok(())
+}
+
+pub async fn verify_access_token(
+	db: &MySqlPool,
 	token: &str,
 	self_id: &Url,
-	client_id: Uuid,
+	client_id: Option<Uuid>,
 ) -> Result<Claims, Expect<VerifyJwtError>> {
-	let claims = verify_jwt(token, self_id, Some(client_id))?;
+	let claims = verify_jwt(db, token, self_id, client_id).await?;
 
 	if !db::access_token_exists(db, claims.jti).await? {
 		yeet!(VerifyJwtError::JwtRevoked.into())
@@ -279,18 +575,82 @@ pub async fn verify_access_token<'c>(
 	Ok(claims)
 }
 
-pub async fn verify_refresh_token<'c>(
-	db: impl Executor<'c, Database = MySql> + Clone,
+pub async fn verify_refresh_token(
+	db: &MySqlPool,
 	token: &str,
 	self_id: &Url,
 ) -> Result<Claims, Expect<VerifyJwtError>> {
-	let claims = verify_jwt(token, self_id, None)?;
+	let claims = verify_jwt(db, token, self_id, None).await?;
 
-	if db::refresh_token_revoked(db.clone(), claims.jti).await? {
+	if !claims.is_intended_for(self_id) {
+		yeet!(VerifyJwtError::BadAudience.into())
+	}
+
+	if db::refresh_token_revoked(db, claims.jti).await? {
 		yeet!(VerifyJwtError::JwtRevoked.into())
 	}
 
-	db::revoke_refresh_token(db, claims.jti).await?;
+	Ok(claims)
+}
+
+/// Use up a refresh token from [`verify_refresh_token`], so that it can't be
+/// used again. This should only be done once every other check on the request
+/// has passed, so that a bad request can't burn the client's token.
+pub async fn consume_refresh_token(
+	db: &MySqlPool,
+	claims: &Claims,
+) -> Result<(), Expect<VerifyJwtError>> {
+	let reason = RevokedRefreshTokenReason::NewRefreshToken;
+	if !db::consume_refresh_token(db, claims.jti, reason).await? {
+		yeet!(VerifyJwtError::JwtRevoked.into())
+	}
+
+	Ok(())
+}
+
+/// Verify an access token or refresh token. This is meant to be used by token
+/// introspection.
+pub async fn introspect_token(
+	db: &MySqlPool,
+	token: &str,
+	self_id: &Url,
+) -> Result<Claims, Expect<VerifyJwtError>> {
+	let claims = verify_jwt(db, token, self_id, None).await?;
+
+	let revoked = match claims.token_type {
+		TokenType::Access => !db::access_token_exists(db, claims.jti).await?,
+		TokenType::Refresh => db::refresh_token_revoked(db, claims.jti).await?,
+		TokenType::Authorization => yeet!(VerifyJwtError::UnsupportedTokenType.into()),
+	};
+	if revoked {
+		yeet!(VerifyJwtError::JwtRevoked.into())
+	}
 
 	Ok(claims)
 }
+
+/// Revoke an access token or refresh token which was issued to the given
+/// client. Revoking a refresh token also revokes every other token from the
+/// same grant.
+pub async fn revoke_token(
+	db: &MySqlPool,
+	token: &str,
+	self_id: &Url,
+	client_id: Uuid,
+) -> Result<(), Expect<VerifyJwtError>> {
+	let claims = verify_jwt(db, token, self_id, Some(client_id)).await?;
+
+	match claims.token_type {
+		TokenType::Access => {
+			db::delete_access_token(db, claims.jti).await?;
+		}
+		TokenType::Refresh => {
+			let reason = RevokedRefreshTokenReason::Revoked;
+			db::delete_access_tokens_with_grant(db, claims.grant_id).await?;
+			db::revoke_refresh_tokens_with_grant(db, claims.grant_id, reason).await?;
+		}
+		TokenType::Authorization => yeet!(VerifyJwtError::UnsupportedTokenType.into()),
+	}
+
+	Ok(())
+}
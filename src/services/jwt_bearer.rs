@@ -0,0 +1,128 @@
+use std::collections::HashMap;
+
+use chrono::serde::{ts_seconds, ts_seconds_option};
+use chrono::{DateTime, Duration, Utc};
+use exun::{Expect, RawUnexpected};
+use raise::yeet;
+use serde::Deserialize;
+use sqlx::MySqlPool;
+use thiserror::Error;
+use url::Url;
+
+use super::config::TrustedIssuer;
+use super::jws::{self, JwsError};
+use super::keys::{Jwk, JwkSet};
+use super::request_object::Audience;
+use super::{crypto, db};
+
+#[derive(Debug, Clone, Deserialize)]
+struct AssertionClaims {
+	sub: Box<str>,
+	aud: Audience,
+	#[serde(with = "ts_seconds")]
+	exp: DateTime<Utc>,
+	#[serde(default, with = "ts_seconds_option")]
+	nbf: Option<DateTime<Utc>>,
+	jti: Box<str>,
+}
+
+/// The issuer is needed to find out which key set to use, so it's read before
+/// the signature is checked
+#[derive(Debug, Clone, Deserialize)]
+struct AssertionIssuer {
+	iss: Box<str>,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyAssertionGrantError {
+	#[error("{0}")]
+	ParseJwtError(#[from] JwsError),
+	#[error("The assertion was not issued by a trusted issuer")]
+	UntrustedIssuer,
+	#[error("The assertion was intended for a different server")]
+	BadAudience,
+	#[error("The assertion is expired")]
+	ExpiredToken,
+	#[error("The assertion cannot be used yet")]
+	NotYet,
+	#[error("The assertion expires too far in the future")]
+	ExcessiveLifetime,
+	#[error("The assertion has already been used")]
+	ReusedAssertion,
+}
+
+/// The key sets of the trusted issuers, which are read when the server starts
+#[derive(Debug, Clone, Default)]
+pub struct TrustedIssuers {
+	key_sets: HashMap<Box<str>, JwkSet>,
+}
+
+/// Assertions which expire later than this are rejected, so that used
+/// assertions don't need to be remembered for very long
+fn max_assertion_lifetime() -> Duration {
+	Duration::hours(1)
+}
+
+pub fn initialize(trusted_issuers: &[TrustedIssuer]) -> Result<TrustedIssuers, RawUnexpected> {
+	let mut key_sets = HashMap::new();
+	for trusted_issuer in trusted_issuers {
+		let jwks = std::fs::read_to_string(&trusted_issuer.jwks)?;
+		let jwks: JwkSet = serde_json::from_str(&jwks)?;
+		key_sets.insert(trusted_issuer.issuer.clone(), jwks);
+	}
+
+	Ok(TrustedIssuers { key_sets })
+}
+
+/// Verify an assertion which is used as an authorization grant, as described
+/// in RFC 7523. It must be signed by one of the configured trusted issuers.
+/// Returns the subject of the assertion.
+pub async fn verify_assertion_grant(
+	db: &MySqlPool,
+	trusted_issuers: &TrustedIssuers,
+	assertion: &str,
+	self_id: &Url,
+) -> Result<Box<str>, Expect<VerifyAssertionGrantError>> {
+	let token = jws::decode(assertion).map_err(VerifyAssertionGrantError::from)?;
+	let issuer: AssertionIssuer = token
+		.unverified_claims()
+		.map_err(VerifyAssertionGrantError::from)?;
+
+	let Some(jwks) = trusted_issuers.key_sets.get(&issuer.iss) else {
+		yeet!(VerifyAssertionGrantError::UntrustedIssuer.into())
+	};
+	let Some(key) = jwks.get(token.header().kid()).and_then(Jwk::verifying_key) else {
+		yeet!(VerifyAssertionGrantError::from(JwsError::UnknownKey).into())
+	};
+
+	let claims: AssertionClaims = token
+		.verify(&key)
+		.map_err(VerifyAssertionGrantError::from)?;
+
+	// the issuer and the token endpoint both identify this server
+	let token_uri = self_id.join("oauth/token").unwrap();
+	if !claims.aud.contains(self_id.as_str()) && !claims.aud.contains(token_uri.as_str()) {
+		yeet!(VerifyAssertionGrantError::BadAudience.into())
+	}
+
+	let now = Utc::now();
+
+	if now > claims.exp {
+		yeet!(VerifyAssertionGrantError::ExpiredToken.into())
+	}
+
+	if claims.exp > now + max_assertion_lifetime() {
+		yeet!(VerifyAssertionGrantError::ExcessiveLifetime.into())
+	}
+
+	if claims.nbf.is_some_and(|nbf| now < nbf) {
+		yeet!(VerifyAssertionGrantError::NotYet.into())
+	}
+
+	let jti_hash = crypto::token_hash(&format!("{}:{}", issuer.iss, claims.jti));
+	if !db::use_jwt_bearer_assertion(db, &jti_hash, claims.exp).await? {
+		yeet!(VerifyAssertionGrantError::ReusedAssertion.into())
+	}
+
+	Ok(claims.sub)
+}
@@ -0,0 +1,153 @@
+use chrono::{Duration, Utc};
+use exun::RawUnexpected;
+use raise::yeet;
+use sqlx::MySqlPool;
+
+use super::keys::{Algorithm, JwkSet, SigningKey, VerifyingKey};
+use super::{crypto, db, jwt};
+
+/// The lifecycle of a signing key. The next key is published before it's used,
+/// so that anyone caching the key set already knows about it by the time it
+/// becomes active. Retired keys stay published until their tokens expire.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, sqlx::Type)]
+#[sqlx(rename_all = "kebab-case")]
+pub enum KeyState {
+	Next,
+	Active,
+	Retired,
+}
+
+const DEFAULT_ALGORITHM: Algorithm = Algorithm::Es256;
+
+/// How long a retired key can still be used to verify tokens. This needs to be
+/// at least as long as any token lasts.
+fn retired_key_lifetime() -> Duration {
+	jwt::max_token_lifetime()
+}
+
+/// Private keys are stored encrypted
+fn decrypt_key(ciphertext: &[u8]) -> Result<SigningKey, RawUnexpected> {
+	let pem = crypto::decrypt_signing_key(ciphertext)?;
+	Ok(SigningKey::from_pkcs8_pem(&pem)?)
+}
+
+fn encrypt_key(key: &SigningKey) -> Result<Box<[u8]>, RawUnexpected> {
+	crypto::encrypt_signing_key(&key.to_pkcs8_pem()?)
+}
+
+async fn get_key_with_state(
+	db: &MySqlPool,
+	state: KeyState,
+) -> Result<Option<SigningKey>, RawUnexpected> {
+	let Some(key) = db::get_signing_key_with_state(db, state).await? else {
+		return Ok(None);
+	};
+
+	Ok(Some(decrypt_key(&key)?))
+}
+
+/// The key that new tokens should be signed with
+pub async fn active_signing_key(db: &MySqlPool) -> Result<SigningKey, RawUnexpected> {
+	let Some(key) = get_key_with_state(db, KeyState::Active).await? else {
+		yeet!(RawUnexpected::msg("There is no active signing key"))
+	};
+
+	Ok(key)
+}
+
+/// Find a key which can still be used to verify tokens
+pub async fn verifying_key(
+	db: &MySqlPool,
+	kid: &str,
+) -> Result<Option<VerifyingKey>, RawUnexpected> {
+	let Some(key) = db::get_unexpired_signing_key(db, kid).await? else {
+		return Ok(None);
+	};
+
+	Ok(Some(decrypt_key(&key)?.verifying_key()))
+}
+
+/// Every key that tokens might be signed with: the next, active, and
+/// unexpired retired keys
+pub async fn published_keys(db: &MySqlPool) -> Result<JwkSet, RawUnexpected> {
+	let mut keys = Vec::new();
+	for key in db::get_unexpired_signing_keys(db).await? {
+		keys.push(decrypt_key(&key)?.verifying_key().to_jwk());
+	}
+
+	Ok(JwkSet::new(keys))
+}
+
+/// Retire the active key, and replace it with the next key. A new next key is
+/// generated with the given algorithm, or with the active key's algorithm if
+/// none is given. Returns the key ID of the new active key.
+pub async fn roll_keys(
+	db: &MySqlPool,
+	algorithm: Option<Algorithm>,
+) -> Result<Box<str>, RawUnexpected> {
+	let active = get_key_with_state(db, KeyState::Active).await?;
+	let next = get_key_with_state(db, KeyState::Next).await?;
+	let algorithm = algorithm
+		.or(active.as_ref().map(SigningKey::algorithm))
+		.unwrap_or(DEFAULT_ALGORITHM);
+
+	let mut transaction = db.begin().await?;
+
+	if let Some(active) = &active {
+		let expires = Utc::now() + retired_key_lifetime();
+		db::update_signing_key_state(
+			transaction.as_mut(),
+			active.kid(),
+			KeyState::Retired,
+			Some(expires),
+		)
+		.await?;
+	}
+
+	let new_active = match next {
+		Some(next) => {
+			db::update_signing_key_state(transaction.as_mut(), next.kid(), KeyState::Active, None)
+				.await?;
+			next
+		}
+		// this only happens the first time that the keys are rolled
+		None => {
+			let key = SigningKey::generate(algorithm)?;
+			let ciphertext = encrypt_key(&key)?;
+			db::create_signing_key(
+				transaction.as_mut(),
+				key.kid(),
+				&ciphertext,
+				KeyState::Active,
+			)
+			.await?;
+			key
+		}
+	};
+
+	let new_next = SigningKey::generate(algorithm)?;
+	let ciphertext = encrypt_key(&new_next)?;
+	db::create_signing_key(
+		transaction.as_mut(),
+		new_next.kid(),
+		&ciphertext,
+		KeyState::Next,
+	)
+	.await?;
+
+	transaction.commit().await?;
+
+	Ok(new_active.kid().into())
+}
+
+/// Make sure that there's an active key, so that tokens can be signed
+pub async fn initialize(db: &MySqlPool) -> Result<(), RawUnexpected> {
+	if db::get_signing_key_with_state(db, KeyState::Active)
+		.await?
+		.is_none()
+	{
+		roll_keys(db, None).await?;
+	}
+
+	Ok(())
+}
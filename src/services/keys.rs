@@ -0,0 +1,519 @@
+use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use base64::Engine;
+use exun::RawUnexpected;
+use rand::rngs::OsRng;
+use rsa::pkcs8::{DecodePrivateKey, EncodePrivateKey, LineEnding};
+use rsa::signature::{SignatureEncoding, Signer, Verifier};
+use rsa::traits::PublicKeyParts;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::mysql::MySqlTypeInfo;
+use sqlx::MySql;
+use thiserror::Error;
+
+/// The algorithms which can be used to sign a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Algorithm {
+	#[serde(rename = "RS256")]
+	Rs256,
+	#[serde(rename = "ES256")]
+	Es256,
+	#[serde(rename = "EdDSA")]
+	EdDsa,
+}
+
+impl Algorithm {
+	/// Every algorithm that the server can sign tokens with. Which one is used
+	/// depends on the type of the active key.
+	pub const SUPPORTED: &'static [Self] = &[Self::Rs256, Self::Es256, Self::EdDsa];
+}
+
+impl Display for Algorithm {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			Self::Rs256 => f.write_str("RS256"),
+			Self::Es256 => f.write_str("ES256"),
+			Self::EdDsa => f.write_str("EdDSA"),
+		}
+	}
+}
+
+#[derive(Debug, Clone, Error)]
+#[error("Expected one of the following algorithms: RS256, ES256, EdDSA. Found {string}")]
+pub struct ParseAlgorithmError {
+	string: Box<str>,
+}
+
+impl FromStr for Algorithm {
+	type Err = ParseAlgorithmError;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"RS256" => Ok(Self::Rs256),
+			"ES256" => Ok(Self::Es256),
+			"EdDSA" => Ok(Self::EdDsa),
+			_ => Err(ParseAlgorithmError { string: s.into() }),
+		}
+	}
+}
+
+enum PrivateKey {
+	Rsa(Box<rsa::pkcs1v15::SigningKey<Sha256>>),
+	Ecdsa(Box<p256::ecdsa::SigningKey>),
+	EdDsa(Box<ed25519_dalek::SigningKey>),
+}
+
+#[derive(Debug, Clone)]
+enum PublicKey {
+	Rsa(Box<rsa::pkcs1v15::VerifyingKey<Sha256>>),
+	Ecdsa(Box<p256::ecdsa::VerifyingKey>),
+	EdDsa(Box<ed25519_dalek::VerifyingKey>),
+}
+
+/// A private key which is used to sign tokens. Each key is identified by its
+/// JWK thumbprint, which is sent in the `kid` header of every token it signs.
+pub struct SigningKey {
+	kid: Box<str>,
+	key: PrivateKey,
+}
+
+/// A public key, which can be used to verify the signature on a token
+#[derive(Debug, Clone)]
+pub struct VerifyingKey {
+	kid: Box<str>,
+	key: PublicKey,
+}
+
+/// The key type specific parameters of a JSON Web Key
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kty")]
+pub enum JwkParameters {
+	#[serde(rename = "RSA")]
+	Rsa { n: Box<str>, e: Box<str> },
+	#[serde(rename = "EC")]
+	Ec {
+		crv: Box<str>,
+		x: Box<str>,
+		y: Box<str>,
+	},
+	#[serde(rename = "OKP")]
+	Okp { crv: Box<str>, x: Box<str> },
+}
+
+/// A public key, in the format specified by RFC 7517
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Jwk {
+	#[serde(flatten)]
+	parameters: JwkParameters,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	kid: Option<Box<str>>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	alg: Option<Algorithm>,
+	#[serde(rename = "use", skip_serializing_if = "Option::is_none")]
+	key_use: Option<Box<str>>,
+	/// The certificate chain for the key, as standard base64-encoded DER
+	#[serde(skip_serializing_if = "Option::is_none")]
+	x5c: Option<Box<[Box<str>]>>,
+}
+
+/// A set of public keys, which is published so that other parties can verify
+/// the tokens issued by this server
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JwkSet {
+	keys: Box<[Jwk]>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Error)]
+pub enum InvalidKeyError {
+	#[error("The key is not a supported RSA, P-256, or Ed25519 private key")]
+	UnsupportedPrivateKey,
+}
+
+fn base64_encode(bytes: &[u8]) -> Box<str> {
+	base64::engine::general_purpose::URL_SAFE_NO_PAD
+		.encode(bytes)
+		.into_boxed_str()
+}
+
+impl SigningKey {
+	/// Generate a new random key for the given algorithm. RSA keys are 2048 bits.
+	pub fn generate(algorithm: Algorithm) -> Result<Self, RawUnexpected> {
+		let key = match algorithm {
+			Algorithm::Rs256 => {
+				let key = RsaPrivateKey::new(&mut OsRng, 2048)?;
+				PrivateKey::Rsa(Box::new(rsa::pkcs1v15::SigningKey::new(key)))
+			}
+			Algorithm::Es256 => {
+				PrivateKey::Ecdsa(Box::new(p256::ecdsa::SigningKey::random(&mut OsRng)))
+			}
+			Algorithm::EdDsa => {
+				PrivateKey::EdDsa(Box::new(ed25519_dalek::SigningKey::generate(&mut OsRng)))
+			}
+		};
+
+		Ok(Self::with_thumbprint(key))
+	}
+
+	/// Parse a PKCS#8 private key. RSA, P-256, and Ed25519 keys are accepted.
+	pub fn from_pkcs8_pem(pem: &str) -> Result<Self, InvalidKeyError> {
+		let key = if let Ok(key) = RsaPrivateKey::from_pkcs8_pem(pem) {
+			PrivateKey::Rsa(Box::new(rsa::pkcs1v15::SigningKey::new(key)))
+		} else if let Ok(key) = p256::ecdsa::SigningKey::from_pkcs8_pem(pem) {
+			PrivateKey::Ecdsa(Box::new(key))
+		} else if let Ok(key) = ed25519_dalek::SigningKey::from_pkcs8_pem(pem) {
+			PrivateKey::EdDsa(Box::new(key))
+		} else {
+			return Err(InvalidKeyError::UnsupportedPrivateKey);
+		};
+
+		Ok(Self::with_thumbprint(key))
+	}
+
+	fn with_thumbprint(key: PrivateKey) -> Self {
+		let mut this = Self {
+			kid: Box::default(),
+			key,
+		};
+		this.kid = this.verifying_key().to_jwk().thumbprint();
+		this
+	}
+
+	/// Encode the key as a PEM-encoded PKCS#8 document
+	pub fn to_pkcs8_pem(&self) -> Result<String, RawUnexpected> {
+		let pem = match &self.key {
+			PrivateKey::Rsa(key) => key.to_pkcs8_pem(LineEnding::LF)?,
+			PrivateKey::Ecdsa(key) => key.to_pkcs8_pem(LineEnding::LF)?,
+			PrivateKey::EdDsa(key) => key.to_pkcs8_pem(LineEnding::LF)?,
+		};
+
+		Ok(pem.to_string())
+	}
+
+	pub fn kid(&self) -> &str {
+		&self.kid
+	}
+
+	pub fn algorithm(&self) -> Algorithm {
+		match self.key {
+			PrivateKey::Rsa(_) => Algorithm::Rs256,
+			PrivateKey::Ecdsa(_) => Algorithm::Es256,
+			PrivateKey::EdDsa(_) => Algorithm::EdDsa,
+		}
+	}
+
+	pub fn verifying_key(&self) -> VerifyingKey {
+		let key = match &self.key {
+			PrivateKey::Rsa(key) => {
+				let public = RsaPublicKey::from(AsRef::<RsaPrivateKey>::as_ref(key.as_ref()));
+				PublicKey::Rsa(Box::new(rsa::pkcs1v15::VerifyingKey::new(public)))
+			}
+			PrivateKey::Ecdsa(key) => PublicKey::Ecdsa(Box::new(*key.verifying_key())),
+			PrivateKey::EdDsa(key) => PublicKey::EdDsa(Box::new(key.verifying_key())),
+		};
+
+		VerifyingKey {
+			kid: self.kid.clone(),
+			key,
+		}
+	}
+
+	/// Sign the message, using this key's algorithm
+	pub fn sign(&self, message: &[u8]) -> Result<Box<[u8]>, RawUnexpected> {
+		let signature = match &self.key {
+			PrivateKey::Rsa(key) => key.try_sign(message)?.to_bytes(),
+			PrivateKey::Ecdsa(key) => {
+				let signature: p256::ecdsa::Signature = key.try_sign(message)?;
+				signature.to_bytes().to_vec().into_boxed_slice()
+			}
+			PrivateKey::EdDsa(key) => key.try_sign(message)?.to_bytes().into(),
+		};
+
+		Ok(signature)
+	}
+}
+
+impl VerifyingKey {
+	pub fn algorithm(&self) -> Algorithm {
+		match self.key {
+			PublicKey::Rsa(_) => Algorithm::Rs256,
+			PublicKey::Ecdsa(_) => Algorithm::Es256,
+			PublicKey::EdDsa(_) => Algorithm::EdDsa,
+		}
+	}
+
+	/// Returns `true` if the signature was made by this key
+	pub fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+		match &self.key {
+			PublicKey::Rsa(key) => rsa::pkcs1v15::Signature::try_from(signature)
+				.is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+			PublicKey::Ecdsa(key) => p256::ecdsa::Signature::from_slice(signature)
+				.is_ok_and(|signature| key.verify(message, &signature).is_ok()),
+			PublicKey::EdDsa(key) => ed25519_dalek::Signature::from_slice(signature)
+				.is_ok_and(|signature| key.verify_strict(message, &signature).is_ok()),
+		}
+	}
+
+	pub fn to_jwk(&self) -> Jwk {
+		let parameters = match &self.key {
+			PublicKey::Rsa(key) => {
+				let key: &RsaPublicKey = key.as_ref().as_ref();
+				JwkParameters::Rsa {
+					n: base64_encode(&key.n().to_bytes_be()),
+					e: base64_encode(&key.e().to_bytes_be()),
+				}
+			}
+			PublicKey::Ecdsa(key) => {
+				let point = key.to_encoded_point(false);
+				JwkParameters::Ec {
+					crv: "P-256".into(),
+					x: base64_encode(point.x().expect("an uncompressed point")),
+					y: base64_encode(point.y().expect("an uncompressed point")),
+				}
+			}
+			PublicKey::EdDsa(key) => JwkParameters::Okp {
+				crv: "Ed25519".into(),
+				x: base64_encode(key.as_bytes()),
+			},
+		};
+
+		Jwk {
+			parameters,
+			kid: (!self.kid.is_empty()).then(|| self.kid.clone()),
+			alg: Some(self.algorithm()),
+			key_use: Some("sig".into()),
+			x5c: None,
+		}
+	}
+}
+
+impl Jwk {
+	/// The JWK thumbprint, as defined by RFC 7638
+	pub fn thumbprint(&self) -> Box<str> {
+		// the required members, in lexicographic order, with no whitespace
+		let canonical = match &self.parameters {
+			JwkParameters::Rsa { n, e } => format!(r#"{{"e":"{e}","kty":"RSA","n":"{n}"}}"#),
+			JwkParameters::Ec { crv, x, y } => {
+				format!(r#"{{"crv":"{crv}","kty":"EC","x":"{x}","y":"{y}"}}"#)
+			}
+			JwkParameters::Okp { crv, x } => format!(r#"{{"crv":"{crv}","kty":"OKP","x":"{x}"}}"#),
+		};
+
+		base64_encode(&Sha256::digest(canonical.as_bytes()))
+	}
+
+	/// Parse a public key that was registered by a client. Returns `None` if it
+	/// isn't a valid RSA, P-256, or Ed25519 key.
+	pub fn verifying_key(&self) -> Option<VerifyingKey> {
+		let decode = |value: &str| {
+			base64::engine::general_purpose::URL_SAFE_NO_PAD
+				.decode(value)
+				.ok()
+		};
+
+		let key = match &self.parameters {
+			JwkParameters::Rsa { n, e } => {
+				let n = rsa::BigUint::from_bytes_be(&decode(n)?);
+				let e = rsa::BigUint::from_bytes_be(&decode(e)?);
+				let key = RsaPublicKey::new(n, e).ok()?;
+				PublicKey::Rsa(Box::new(rsa::pkcs1v15::VerifyingKey::new(key)))
+			}
+			JwkParameters::Ec { crv, x, y } => {
+				let (x, y) = (decode(x)?, decode(y)?);
+				if &**crv != "P-256" || x.len() != 32 || y.len() != 32 {
+					return None;
+				}
+				let point = p256::EncodedPoint::from_affine_coordinates(
+					p256::FieldBytes::from_slice(&x),
+					p256::FieldBytes::from_slice(&y),
+					false,
+				);
+				let key = p256::ecdsa::VerifyingKey::from_encoded_point(&point).ok()?;
+				PublicKey::Ecdsa(Box::new(key))
+			}
+			JwkParameters::Okp { crv, x } => {
+				let x: [u8; 32] = decode(x)?.try_into().ok()?;
+				if &**crv != "Ed25519" {
+					return None;
+				}
+				let key = ed25519_dalek::VerifyingKey::from_bytes(&x).ok()?;
+				PublicKey::EdDsa(Box::new(key))
+			}
+		};
+
+		Some(VerifyingKey {
+			kid: self.kid.clone().unwrap_or_else(|| self.thumbprint()),
+			key,
+		})
+	}
+}
+
+impl JwkSet {
+	pub fn new(keys: impl IntoIterator<Item = Jwk>) -> Self {
+		Self {
+			keys: keys.into_iter().collect(),
+		}
+	}
+
+	/// Returns `true` if every key in the set can be used to verify signatures
+	pub fn is_valid(&self) -> bool {
+		self.keys.iter().all(|key| key.verifying_key().is_some())
+	}
+
+	/// Returns `true` if any of the keys has a certificate chain
+	pub fn has_certificates(&self) -> bool {
+		self.keys.iter().any(|key| key.x5c.is_some())
+	}
+
+	/// Returns `true` if the DER-encoded certificate is the first certificate
+	/// in the chain of one of the keys
+	pub fn contains_certificate(&self, certificate: &[u8]) -> bool {
+		self.keys.iter().any(|key| {
+			key.x5c
+				.as_ref()
+				.and_then(|x5c| x5c.first())
+				.and_then(|first| {
+					base64::engine::general_purpose::STANDARD
+						.decode(&**first)
+						.ok()
+				})
+				.is_some_and(|first| first == certificate)
+		})
+	}
+
+	/// Find the key with the given key ID. If there's no key ID, then the set
+	/// can only have one key in it.
+	pub fn get(&self, kid: Option<&str>) -> Option<&Jwk> {
+		let Some(kid) = kid else {
+			return match &*self.keys {
+				[key] => Some(key),
+				_ => None,
+			};
+		};
+
+		self.keys
+			.iter()
+			.find(|key| key.kid.as_deref() == Some(kid) || &*key.thumbprint() == kid)
+	}
+}
+
+// key sets registered by clients are stored as JSON
+
+impl sqlx::Type<MySql> for JwkSet {
+	fn type_info() -> MySqlTypeInfo {
+		<str as sqlx::Type<MySql>>::type_info()
+	}
+
+	fn compatible(ty: &MySqlTypeInfo) -> bool {
+		<str as sqlx::Type<MySql>>::compatible(ty)
+	}
+}
+
+impl sqlx::Encode<'_, MySql> for JwkSet {
+	fn encode_by_ref(
+		&self,
+		buf: &mut <MySql as sqlx::database::HasArguments<'_>>::ArgumentBuffer,
+	) -> sqlx::encode::IsNull {
+		let json = serde_json::to_string(self).unwrap();
+		<String as sqlx::Encode<MySql>>::encode_by_ref(&json, buf)
+	}
+}
+
+impl sqlx::Decode<'_, MySql> for JwkSet {
+	fn decode(
+		value: <MySql as sqlx::database::HasValueRef<'_>>::ValueRef,
+	) -> Result<Self, sqlx::error::BoxDynError> {
+		let json = <&str as sqlx::Decode<MySql>>::decode(value)?;
+		Ok(serde_json::from_str(json)?)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the example from RFC 7638, section 3.1
+	const RFC_7638_JWK: &str = r#"{
+		"kty": "RSA",
+		"n": "0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw",
+		"e": "AQAB",
+		"alg": "RS256",
+		"kid": "2011-04-29"
+	}"#;
+	const RFC_7638_THUMBPRINT: &str = "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs";
+
+	#[test]
+	fn thumbprint_matches_rfc_7638() {
+		let jwk: Jwk = serde_json::from_str(RFC_7638_JWK).unwrap();
+		assert_eq!(&*jwk.thumbprint(), RFC_7638_THUMBPRINT);
+	}
+
+	#[test]
+	fn thumbprint_ignores_optional_members() {
+		let jwk: Jwk = serde_json::from_str(RFC_7638_JWK).unwrap();
+		let bare = Jwk {
+			kid: None,
+			alg: None,
+			key_use: None,
+			..jwk.clone()
+		};
+		assert_eq!(jwk.thumbprint(), bare.thumbprint());
+	}
+
+	#[test]
+	fn signing_key_id_is_thumbprint() {
+		for algorithm in [Algorithm::Es256, Algorithm::EdDsa] {
+			let key = SigningKey::generate(algorithm).unwrap();
+			let jwk = key.verifying_key().to_jwk();
+			assert_eq!(key.kid(), &*jwk.thumbprint());
+		}
+	}
+
+	#[test]
+	fn published_key_verifies_signatures() {
+		for algorithm in [Algorithm::Es256, Algorithm::EdDsa] {
+			let key = SigningKey::generate(algorithm).unwrap();
+			let signature = key.sign(b"message").unwrap();
+			let jwk = key.verifying_key().to_jwk();
+			let verifying_key = jwk.verifying_key().unwrap();
+			assert_eq!(verifying_key.algorithm(), algorithm);
+			assert!(verifying_key.verify(b"message", &signature));
+			assert!(!verifying_key.verify(b"other message", &signature));
+		}
+	}
+
+	#[test]
+	fn pkcs8_round_trip_keeps_key_id() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let pem = key.to_pkcs8_pem().unwrap();
+		let parsed = SigningKey::from_pkcs8_pem(&pem).unwrap();
+		assert_eq!(key.kid(), parsed.kid());
+	}
+
+	#[test]
+	fn rejects_unsupported_curve() {
+		let key = SigningKey::generate(Algorithm::Es256).unwrap();
+		let mut jwk = key.verifying_key().to_jwk();
+		if let JwkParameters::Ec { crv, .. } = &mut jwk.parameters {
+			*crv = "P-384".into();
+		}
+		assert!(jwk.verifying_key().is_none());
+	}
+
+	#[test]
+	fn key_set_without_kid_needs_one_key() {
+		let first = SigningKey::generate(Algorithm::EdDsa).unwrap();
+		let second = SigningKey::generate(Algorithm::EdDsa).unwrap();
+		let one = JwkSet::new([first.verifying_key().to_jwk()]);
+		let two = JwkSet::new([
+			first.verifying_key().to_jwk(),
+			second.verifying_key().to_jwk(),
+		]);
+
+		assert!(one.get(None).is_some());
+		assert!(two.get(None).is_none());
+		assert!(two.get(Some(second.kid())).is_some());
+	}
+}
@@ -1,8 +1,24 @@
 pub mod authorization;
+pub mod authorization_details;
+pub mod backchannel_authentication;
+pub mod backchannel_notifier;
 pub mod brute_force_detection;
+pub mod client_assertion;
 pub mod config;
+pub mod consent;
 pub mod crypto;
 pub mod db;
+pub mod device_code;
+pub mod dpop;
 pub mod id;
+pub mod id_token;
+pub mod jarm;
+pub mod jws;
 pub mod jwt;
+pub mod jwt_bearer;
+pub mod key_set;
+pub mod keys;
+pub mod pkce;
+pub mod request_object;
 pub mod secrets;
+pub mod tls;
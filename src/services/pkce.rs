@@ -0,0 +1,134 @@
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The transformation applied to a code verifier to get the code challenge.
+/// If the client doesn't specify a method, it's assumed to be `plain`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum CodeChallengeMethod {
+	#[default]
+	#[serde(rename = "plain")]
+	Plain,
+	S256,
+}
+
+impl CodeChallengeMethod {
+	/// Every code challenge method that the server accepts
+	pub const SUPPORTED: &'static [Self] = &[Self::Plain, Self::S256];
+}
+
+/// A code challenge, which is bound to an authorization code. The client must
+/// provide the verifier that the challenge was derived from in order to
+/// redeem the code.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct CodeChallenge {
+	challenge: Box<str>,
+	method: CodeChallengeMethod,
+}
+
+/// Returns `true` if the given string is between 43 and 128 characters, and
+/// only uses the unreserved characters from RFC 3986. This is the syntax for
+/// both code verifiers and code challenges.
+fn is_valid_syntax(value: &str) -> bool {
+	(43..=128).contains(&value.len())
+		&& value
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+impl CodeChallenge {
+	/// Returns `None` if the challenge isn't syntactically valid
+	pub fn new(challenge: &str, method: CodeChallengeMethod) -> Option<Self> {
+		if !is_valid_syntax(challenge) {
+			return None;
+		}
+
+		Some(Self {
+			challenge: challenge.into(),
+			method,
+		})
+	}
+
+	/// Returns `true` if the challenge was derived from the given verifier
+	pub fn verify(&self, verifier: &str) -> bool {
+		if !is_valid_syntax(verifier) {
+			return false;
+		}
+
+		match self.method {
+			CodeChallengeMethod::Plain => self.challenge.as_ref() == verifier,
+			CodeChallengeMethod::S256 => {
+				let hash = Sha256::digest(verifier.as_bytes());
+				let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash);
+				self.challenge.as_ref() == challenge
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// the example from RFC 7636, appendix B
+	const VERIFIER: &str = "dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk";
+	const CHALLENGE: &str = "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM";
+
+	#[test]
+	fn s256_accepts_matching_verifier() {
+		let challenge = CodeChallenge::new(CHALLENGE, CodeChallengeMethod::S256).unwrap();
+		assert!(challenge.verify(VERIFIER));
+	}
+
+	#[test]
+	fn s256_rejects_other_verifier() {
+		let challenge = CodeChallenge::new(CHALLENGE, CodeChallengeMethod::S256).unwrap();
+		assert!(!challenge.verify(&VERIFIER.replace('d', "e")));
+	}
+
+	#[test]
+	fn s256_rejects_challenge_as_verifier() {
+		let challenge = CodeChallenge::new(CHALLENGE, CodeChallengeMethod::S256).unwrap();
+		assert!(!challenge.verify(CHALLENGE));
+	}
+
+	#[test]
+	fn plain_compares_directly() {
+		let challenge = CodeChallenge::new(VERIFIER, CodeChallengeMethod::Plain).unwrap();
+		assert!(challenge.verify(VERIFIER));
+		assert!(!challenge.verify(CHALLENGE));
+	}
+
+	#[test]
+	fn default_method_is_plain() {
+		assert_eq!(CodeChallengeMethod::default(), CodeChallengeMethod::Plain);
+	}
+
+	#[test]
+	fn rejects_short_challenge() {
+		assert!(CodeChallenge::new(&"a".repeat(42), CodeChallengeMethod::Plain).is_none());
+		assert!(CodeChallenge::new(&"a".repeat(43), CodeChallengeMethod::Plain).is_some());
+	}
+
+	#[test]
+	fn rejects_long_challenge() {
+		assert!(CodeChallenge::new(&"a".repeat(128), CodeChallengeMethod::Plain).is_some());
+		assert!(CodeChallenge::new(&"a".repeat(129), CodeChallengeMethod::Plain).is_none());
+	}
+
+	#[test]
+	fn rejects_reserved_characters() {
+		let challenge = format!("{}+/=", "a".repeat(43));
+		assert!(CodeChallenge::new(&challenge, CodeChallengeMethod::Plain).is_none());
+	}
+
+	#[test]
+	fn rejects_invalid_verifier() {
+		// the challenge matches, but the verifier is too short
+		let verifier = "a".repeat(42);
+		let hash = Sha256::digest(verifier.as_bytes());
+		let challenge = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(hash);
+		let challenge = CodeChallenge::new(&challenge, CodeChallengeMethod::S256).unwrap();
+		assert!(!challenge.verify(&verifier));
+	}
+}
@@ -0,0 +1,103 @@
+use chrono::serde::ts_seconds_option;
+use chrono::{DateTime, Utc};
+use exun::Expect;
+use raise::yeet;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use sqlx::MySqlPool;
+use thiserror::Error;
+use url::Url;
+use uuid::Uuid;
+
+use super::db;
+use super::jws::{self, JwsError};
+use super::keys::Jwk;
+
+/// A request object can be intended for one audience, or for several
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub(crate) enum Audience {
+	One(Box<str>),
+	Many(Box<[Box<str>]>),
+}
+
+impl Audience {
+	pub(crate) fn contains(&self, audience: &str) -> bool {
+		match self {
+			Self::One(aud) => &**aud == audience,
+			Self::Many(auds) => auds.iter().any(|aud| &**aud == audience),
+		}
+	}
+}
+
+/// The claims which describe the request object itself. Every other claim is
+/// an authorization parameter.
+#[derive(Debug, Clone, Deserialize)]
+struct RequestObjectClaims {
+	iss: Box<str>,
+	aud: Audience,
+	#[serde(default, with = "ts_seconds_option")]
+	exp: Option<DateTime<Utc>>,
+	#[serde(default, with = "ts_seconds_option")]
+	nbf: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Error)]
+pub enum VerifyRequestObjectError {
+	#[error("{0}")]
+	ParseJwtError(#[from] JwsError),
+	#[error("The client has not registered any keys")]
+	NoClientKeys,
+	#[error("The request object was not issued by the client")]
+	IncorrectIssuer,
+	#[error("The request object was intended for a different server")]
+	BadAudience,
+	#[error("The request object is expired")]
+	ExpiredToken,
+	#[error("The request object cannot be used yet")]
+	NotYet,
+}
+
+/// Verify a request object, as described in RFC 9101. It must be signed by one
+/// of the keys which the client registered. Returns every claim in the object.
+pub async fn verify_request_object(
+	db: &MySqlPool,
+	token: &str,
+	self_id: &Url,
+	client_id: Uuid,
+	client_alias: &str,
+) -> Result<Map<String, Value>, Expect<VerifyRequestObjectError>> {
+	let token = jws::decode(token).map_err(VerifyRequestObjectError::from)?;
+	let Some(jwks) = db::get_client_jwks(db, client_id).await?.flatten() else {
+		yeet!(VerifyRequestObjectError::NoClientKeys.into())
+	};
+	let Some(key) = jwks.get(token.header().kid()).and_then(Jwk::verifying_key) else {
+		yeet!(VerifyRequestObjectError::from(JwsError::UnknownKey).into())
+	};
+
+	let claims: Map<String, Value> = token.verify(&key).map_err(VerifyRequestObjectError::from)?;
+	let Ok(object) = serde_json::from_value::<RequestObjectClaims>(Value::Object(claims.clone()))
+	else {
+		yeet!(VerifyRequestObjectError::from(JwsError::Malformed).into())
+	};
+
+	if &*object.iss != client_alias {
+		yeet!(VerifyRequestObjectError::IncorrectIssuer.into())
+	}
+
+	if !object.aud.contains(self_id.as_str()) {
+		yeet!(VerifyRequestObjectError::BadAudience.into())
+	}
+
+	let now = Utc::now();
+
+	if object.exp.is_some_and(|exp| now > exp) {
+		yeet!(VerifyRequestObjectError::ExpiredToken.into())
+	}
+
+	if object.nbf.is_some_and(|nbf| now < nbf) {
+		yeet!(VerifyRequestObjectError::NotYet.into())
+	}
+
+	Ok(claims)
+}
@@ -1,8 +1,6 @@
 use std::env;
 
 use exun::*;
-use hmac::{Hmac, Mac};
-use sha2::Sha256;
 
 /// If in debug mode, use hot reloading
 fn reload() -> Result<(), RawUnexpected> {
@@ -27,10 +25,3 @@ pub fn database_url() -> Result<String, RawUnexpected> {
 	reload()?;
 	env::var("DATABASE_URL").unexpect()
 }
-
-pub fn signing_key() -> Result<Hmac<Sha256>, RawUnexpected> {
-	reload()?;
-	let key = env::var("PRIVATE_KEY")?;
-	let key = Hmac::<Sha256>::new_from_slice(key.as_bytes())?;
-	Ok(key)
-}
@@ -0,0 +1,157 @@
+use std::any::Any;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use actix_rt::net::TcpStream;
+use actix_tls::accept::rustls::TlsStream;
+use actix_web::dev::Extensions;
+use base64::Engine;
+use exun::RawUnexpected;
+use rustls::server::{ClientCertVerified, ClientCertVerifier};
+use rustls::{Certificate, DistinguishedNames, PrivateKey, ServerConfig};
+use sha2::{Digest, Sha256};
+
+use super::config::{self, TlsConfig};
+
+/// The signature algorithms which a client certificate chain may use
+static SIGNATURE_ALGORITHMS: &[&webpki::SignatureAlgorithm] = &[
+	&webpki::ECDSA_P256_SHA256,
+	&webpki::ECDSA_P256_SHA384,
+	&webpki::ECDSA_P384_SHA256,
+	&webpki::ECDSA_P384_SHA384,
+	&webpki::ED25519,
+	&webpki::RSA_PKCS1_2048_8192_SHA256,
+	&webpki::RSA_PKCS1_2048_8192_SHA384,
+	&webpki::RSA_PKCS1_2048_8192_SHA512,
+	&webpki::RSA_PSS_2048_8192_SHA256_LEGACY_KEY,
+	&webpki::RSA_PSS_2048_8192_SHA384_LEGACY_KEY,
+	&webpki::RSA_PSS_2048_8192_SHA512_LEGACY_KEY,
+];
+
+/// The certificate chain that a client presented during the TLS handshake.
+/// The first certificate belongs to the client.
+#[derive(Debug, Clone)]
+pub struct ClientCertificate {
+	chain: Box<[Box<[u8]>]>,
+}
+
+/// Clients may authenticate with a self-signed certificate, so any
+/// certificate is accepted during the handshake. The handshake still proves
+/// that the client has the certificate's private key. Whether the certificate
+/// belongs to the client is checked when the client authenticates.
+struct AcceptAnyClientCertificate;
+
+impl ClientCertVerifier for AcceptAnyClientCertificate {
+	fn client_auth_mandatory(&self) -> Option<bool> {
+		Some(false)
+	}
+
+	fn client_auth_root_subjects(&self) -> Option<DistinguishedNames> {
+		Some(Vec::new())
+	}
+
+	fn verify_client_cert(
+		&self,
+		_end_entity: &Certificate,
+		_intermediates: &[Certificate],
+		_now: SystemTime,
+	) -> Result<ClientCertVerified, rustls::Error> {
+		Ok(ClientCertVerified::assertion())
+	}
+}
+
+fn read_certificates(path: &Path) -> Result<Vec<Vec<u8>>, RawUnexpected> {
+	let mut reader = BufReader::new(File::open(path)?);
+	Ok(rustls_pemfile::certs(&mut reader)?)
+}
+
+/// Load the server's certificate and private key. Clients are asked for a
+/// certificate, but don't need to send one.
+pub fn server_config(tls: &TlsConfig) -> Result<ServerConfig, RawUnexpected> {
+	let certificates = read_certificates(&tls.certificate)?
+		.into_iter()
+		.map(Certificate)
+		.collect();
+
+	let mut reader = BufReader::new(File::open(&tls.private_key)?);
+	let Some(private_key) = rustls_pemfile::pkcs8_private_keys(&mut reader)?.pop() else {
+		return Err(RawUnexpected::msg(
+			"The TLS private key file has no PKCS#8 key",
+		));
+	};
+
+	let config = ServerConfig::builder()
+		.with_safe_defaults()
+		.with_client_cert_verifier(Arc::new(AcceptAnyClientCertificate))
+		.with_single_cert(certificates, PrivateKey(private_key))?;
+	Ok(config)
+}
+
+/// Save the client's certificate chain with the connection, so that it can be
+/// found with `HttpRequest::conn_data`
+pub fn on_connect(connection: &dyn Any, data: &mut Extensions) {
+	let Some(stream) = connection.downcast_ref::<TlsStream<TcpStream>>() else {
+		return;
+	};
+	let (_, session) = stream.get_ref();
+	if let Some(certificates) = session.peer_certificates() {
+		data.insert(ClientCertificate {
+			chain: certificates
+				.iter()
+				.map(|certificate| certificate.0.clone().into_boxed_slice())
+				.collect(),
+		});
+	}
+}
+
+impl ClientCertificate {
+	/// The client's own DER-encoded certificate
+	pub fn der(&self) -> &[u8] {
+		&self.chain[0]
+	}
+
+	/// The base64url-encoded SHA-256 hash of the certificate, as used in the
+	/// `x5t#S256` confirmation method from RFC 8705
+	pub fn thumbprint(&self) -> Box<str> {
+		base64::engine::general_purpose::URL_SAFE_NO_PAD
+			.encode(Sha256::digest(self.der()))
+			.into_boxed_str()
+	}
+
+	/// Returns `true` if the certificate chains to one of the configured client
+	/// certificate authorities, and has the given DNS name as a subject
+	/// alternative name
+	pub fn is_issued_for(&self, dns_name: &str) -> Result<bool, RawUnexpected> {
+		let config = config::get_config()?;
+		let Some(client_ca) = config.tls.and_then(|tls| tls.client_ca) else {
+			return Ok(false);
+		};
+		let roots = read_certificates(&client_ca)?;
+		let anchors: Vec<_> = roots
+			.iter()
+			.filter_map(|root| webpki::TrustAnchor::try_from_cert_der(root).ok())
+			.collect();
+
+		let Ok(certificate) = webpki::EndEntityCert::try_from(self.der()) else {
+			return Ok(false);
+		};
+		let Ok(dns_name) = webpki::DnsNameRef::try_from_ascii_str(dns_name) else {
+			return Ok(false);
+		};
+		let intermediates: Vec<&[u8]> = self.chain[1..].iter().map(|der| &**der).collect();
+		let now = webpki::Time::try_from(SystemTime::now())?;
+
+		let chain_valid = certificate
+			.verify_is_valid_tls_client_cert(
+				SIGNATURE_ALGORITHMS,
+				&webpki::TlsClientTrustAnchors(&anchors),
+				&intermediates,
+				now,
+			)
+			.is_ok();
+		Ok(chain_valid && certificate.verify_is_valid_for_dns_name(dns_name).is_ok())
+	}
+}